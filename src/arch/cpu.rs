@@ -0,0 +1,85 @@
+#![allow(dead_code)]
+
+use core::arch::asm;
+
+const EFLAGS_IF: u32 = 1 << 9;
+
+pub fn enable_interrupts() {
+    unsafe { asm!("sti", options(nomem, nostack)) };
+}
+
+pub fn disable_interrupts() {
+    unsafe { asm!("cli", options(nomem, nostack)) };
+}
+
+pub fn interrupts_enabled() -> bool {
+    let eflags: u32;
+    unsafe { asm!("pushfd", "pop {:e}", out(reg) eflags, options(nomem, preserves_flags)) };
+    eflags & EFLAGS_IF != 0
+}
+
+// `sti` takes effect after the next instruction, so no interrupt can slip in
+// between a check done with interrupts disabled and the `hlt`
+pub fn enable_and_halt() {
+    unsafe { asm!("sti", "hlt", options(nomem, nostack)) };
+}
+
+pub fn halt() {
+    unsafe { asm!("hlt", options(nomem, nostack)) };
+}
+
+// runs `f` with interrupts disabled, restoring their previous state after
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    let enabled = interrupts_enabled();
+    disable_interrupts();
+    let ret = f();
+    if enabled {
+        enable_interrupts();
+    }
+    ret
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cpuid {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+}
+
+// ebx is reserved by the compiler, it goes through another register
+pub fn cpuid(leaf: u32) -> Cpuid {
+    let (eax, ebx, ecx, edx): (u32, u32, u32, u32);
+    unsafe {
+        asm!(
+            "mov {tmp:e}, ebx",
+            "cpuid",
+            "xchg {tmp:e}, ebx",
+            tmp = out(reg) ebx,
+            inout("eax") leaf => eax,
+            inout("ecx") 0 => ecx,
+            out("edx") edx,
+            options(nomem, nostack, preserves_flags)
+        )
+    };
+    Cpuid { eax, ebx, ecx, edx }
+}
+
+pub fn read_msr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    unsafe {
+        asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack))
+    };
+    (high as u64) << 32 | low as u64
+}
+
+pub fn write_msr(msr: u32, value: u64) {
+    let (low, high) = (value as u32, (value >> 32) as u32);
+    unsafe { asm!("wrmsr", in("ecx") msr, in("eax") low, in("edx") high, options(nomem, nostack)) };
+}
+
+pub fn rdtsc() -> u64 {
+    let (low, high): (u32, u32);
+    unsafe { asm!("rdtsc", out("eax") low, out("edx") high, options(nomem, nostack)) };
+    (high as u64) << 32 | low as u64
+}
@@ -0,0 +1,159 @@
+#![allow(dead_code)]
+
+use core::arch::asm;
+
+use crate::arch::cpu;
+use crate::mem::paging::{USER_END, USER_START};
+use crate::mem::space::Backing;
+use crate::syscall::Registers;
+use crate::task::{self, signal, NoPreempt};
+
+pub const COUNT: usize = 32;
+
+pub fn name(vector: u8) -> &'static str {
+    match vector {
+        0 => "divide error",
+        1 => "debug",
+        2 => "non-maskable interrupt",
+        3 => "breakpoint",
+        4 => "overflow",
+        5 => "bound range exceeded",
+        6 => "invalid opcode",
+        7 => "device not available",
+        8 => "double fault",
+        10 => "invalid TSS",
+        11 => "segment not present",
+        12 => "stack-segment fault",
+        13 => "general protection fault",
+        14 => "page fault",
+        16 => "x87 floating-point exception",
+        17 => "alignment check",
+        18 => "machine check",
+        19 => "SIMD floating-point exception",
+        20 => "virtualization exception",
+        21 => "control protection exception",
+        _ => "reserved exception",
+    }
+}
+
+fn cr2() -> u32 {
+    let cr2: u32;
+    unsafe { asm!("mov {:e}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags)) };
+    cr2
+}
+
+// the signal a fault from user code stands for
+fn signal(vector: u8) -> u8 {
+    match vector {
+        0 | 16 | 19 => signal::SIGFPE,
+        6 => signal::SIGILL,
+        _ => signal::SIGSEGV,
+    }
+}
+
+// the CPU pushes an error code for these only
+fn has_code(vector: u8) -> bool {
+    matches!(vector, 8 | 10..=14 | 17 | 21 | 29 | 30)
+}
+
+// error code of a page fault: set on writes, clear on reads
+const PAGE_FAULT_WRITE: u32 = 1 << 1;
+
+// a user page not backed yet or shared copy-on-write gets its frame and the
+// access is retried. The kernel prepares user buffers before using them, so
+// its own faults are never resolved. This is the work syscall::prepare does,
+// file pages are read with interrupts on as system calls run
+fn resolve_page_fault(regs: &Registers, code: u32) -> bool {
+    let addr = cr2() as usize;
+    if !regs.is_user() || !(USER_START..USER_END).contains(&addr) {
+        return false;
+    }
+    let Some(space) = task::space() else {
+        return false;
+    };
+    let _guard = NoPreempt::new();
+    let file = space
+        .regions()
+        .any(|region| region.contains(addr, 1) && matches!(region.backing, Backing::File { .. }));
+    if file {
+        cpu::enable_interrupts();
+    }
+    let resolved = space.fault(addr, code & PAGE_FAULT_WRITE != 0).is_ok();
+    cpu::disable_interrupts();
+    resolved
+}
+
+// a fault in user code raises a signal in the task, in the kernel it is fatal
+fn fault(vector: u8, regs: &mut Registers, code: Option<u32>) {
+    if let (14, Some(code)) = (vector, code) {
+        if resolve_page_fault(regs, code) {
+            return;
+        }
+    }
+    let name = name(vector);
+    let eip = regs.eip;
+    if regs.is_user() && vector != 8 && vector != 18 {
+        signal::force(signal(vector));
+        signal::deliver(regs);
+        return;
+    }
+    match (vector, code) {
+        (14, Some(code)) => panic!(
+            "Kernel {name} at {eip:#010x} accessing {:#010x} (code {code:#x})",
+            cr2()
+        ),
+        (_, Some(code)) => panic!("Kernel {name} at {eip:#010x} (code {code:#x})"),
+        (_, None) => panic!("Kernel {name} at {eip:#010x}"),
+    }
+}
+
+#[no_mangle]
+extern "C" fn exception_dispatch(regs: &mut Registers, vector: u32, code: u32) {
+    let vector = vector as u8;
+    fault(vector, regs, has_code(vector).then_some(code));
+}
+
+// the entry points are in exceptions.s
+macro_rules! exception_handlers {
+    ($($name:ident,)*) => {
+        extern "C" {
+            $(fn $name();)*
+        }
+        pub const HANDLERS: [unsafe extern "C" fn(); COUNT] = [$($name),*];
+    };
+}
+
+exception_handlers! {
+    divide_error,
+    debug,
+    nmi,
+    breakpoint,
+    overflow,
+    bound_range,
+    invalid_opcode,
+    device_not_available,
+    double_fault,
+    coprocessor_overrun,
+    invalid_tss,
+    segment_not_present,
+    stack_segment,
+    general_protection,
+    page_fault,
+    reserved15,
+    x87_floating_point,
+    alignment_check,
+    machine_check,
+    simd_floating_point,
+    virtualization,
+    control_protection,
+    reserved22,
+    reserved23,
+    reserved24,
+    reserved25,
+    reserved26,
+    reserved27,
+    reserved28,
+    reserved29,
+    reserved30,
+    reserved31,
+}
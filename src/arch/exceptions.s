@@ -0,0 +1,70 @@
+# every exception saves the registers the way syscall_entry does, so a fault
+# in user code can resume in a signal handler and come back with sigreturn.
+# The error code some of them push is moved out of the frame first, the
+# others get 0, then exception_dispatch(Registers *, vector, code) is called
+.macro exception name, vector, code
+.global \name
+.type \name, @function
+\name:
+.if \code
+    pop dword ptr [exception_code]
+.else
+    mov dword ptr [exception_code], 0
+.endif
+    push ds
+    push es
+    push fs
+    push gs
+    pushad
+    mov ax, 0x10
+    mov ds, ax
+    mov es, ax
+    mov fs, ax
+    mov gs, ax
+    push dword ptr [exception_code]
+    push \vector
+    lea eax, [esp + 8]
+    push eax
+    call exception_dispatch
+    add esp, 12
+    jmp syscall_exit
+.endm
+
+.section .text
+exception divide_error, 0, 0
+exception debug, 1, 0
+exception nmi, 2, 0
+exception breakpoint, 3, 0
+exception overflow, 4, 0
+exception bound_range, 5, 0
+exception invalid_opcode, 6, 0
+exception device_not_available, 7, 0
+exception double_fault, 8, 1
+exception coprocessor_overrun, 9, 0
+exception invalid_tss, 10, 1
+exception segment_not_present, 11, 1
+exception stack_segment, 12, 1
+exception general_protection, 13, 1
+exception page_fault, 14, 1
+exception reserved15, 15, 0
+exception x87_floating_point, 16, 0
+exception alignment_check, 17, 1
+exception machine_check, 18, 0
+exception simd_floating_point, 19, 0
+exception virtualization, 20, 0
+exception control_protection, 21, 1
+exception reserved22, 22, 0
+exception reserved23, 23, 0
+exception reserved24, 24, 0
+exception reserved25, 25, 0
+exception reserved26, 26, 0
+exception reserved27, 27, 0
+exception reserved28, 28, 0
+exception reserved29, 29, 1
+exception reserved30, 30, 1
+exception reserved31, 31, 0
+
+# only read before anything can fault again, interrupts are disabled
+.section .data
+exception_code:
+    .long 0
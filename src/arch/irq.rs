@@ -0,0 +1,139 @@
+#![allow(dead_code)]
+
+use crate::arch::tables::idt;
+use crate::lazy::LazyMut;
+use crate::syscall::Registers;
+use crate::task::signal;
+
+pub const PIC1_OFFSET: u8 = 0x20;
+pub const PIC2_OFFSET: u8 = 0x28;
+pub const COUNT: usize = 16;
+// handlers per line, PCI functions share INTx lines
+pub const MAX_SHARED: usize = 4;
+
+pub type Handler = fn(irq: u8);
+
+struct Irqs {
+    handlers: [[Option<Handler>; MAX_SHARED]; COUNT],
+    counts: [u64; COUNT],
+    spurious: u64,
+}
+
+static IRQS: LazyMut<Irqs> = LazyMut::new();
+
+pub fn init() {
+    unsafe {
+        IRQS.init(Irqs {
+            handlers: [[None; MAX_SHARED]; COUNT],
+            counts: [0; COUNT],
+            spurious: 0,
+        })
+    };
+    idt::init(PIC1_OFFSET, PIC2_OFFSET);
+    for irq in 0..COUNT as u8 {
+        idt::set_mask(irq, true);
+    }
+    // the slave PIC is cascaded on IRQ 2
+    idt::set_mask(2, false);
+}
+
+pub const fn vector(irq: u8) -> u8 {
+    if irq < 8 {
+        PIC1_OFFSET + irq
+    } else {
+        PIC2_OFFSET + irq - 8
+    }
+}
+
+fn same(a: Handler, b: Handler) -> bool {
+    core::ptr::fn_addr_eq(a, b)
+}
+
+// adds `handler` to those the line runs, each of them checks whether its
+// device raised it. Fails when the line has MAX_SHARED handlers already
+pub fn register(irq: u8, handler: Handler) -> Result<(), ()> {
+    let handlers = &mut IRQS.get_mut().handlers[irq as usize];
+    if !handlers.iter().flatten().any(|other| same(*other, handler)) {
+        let slot = handlers.iter_mut().find(|slot| slot.is_none()).ok_or(())?;
+        *slot = Some(handler);
+    }
+    idt::set_mask(irq, false);
+    Ok(())
+}
+
+// the line is masked once its last handler is gone
+pub fn unregister(irq: u8, handler: Handler) {
+    let handlers = &mut IRQS.get_mut().handlers[irq as usize];
+    for slot in handlers
+        .iter_mut()
+        .filter(|slot| slot.is_some_and(|other| same(other, handler)))
+    {
+        *slot = None;
+    }
+    if handlers.iter().all(|slot| slot.is_none()) {
+        idt::set_mask(irq, true);
+    }
+}
+
+pub fn count(irq: u8) -> u64 {
+    IRQS.get().counts[irq as usize]
+}
+
+pub fn spurious() -> u64 {
+    IRQS.get().spurious
+}
+
+fn dispatch(irq: u8) {
+    let irqs = IRQS.get_mut();
+    // IRQ 7 and 15 fire spuriously when the line drops before acknowledgement
+    if (irq == 7 || irq == 15) && !idt::in_service(irq) {
+        irqs.spurious += 1;
+        if irq == 15 {
+            idt::eoi(0);
+        }
+        return;
+    }
+    irqs.counts[irq as usize] += 1;
+    // acknowledge first, a handler may switch to another flow of execution
+    idt::eoi(irq);
+    for handler in irqs.handlers[irq as usize].into_iter().flatten() {
+        handler(irq);
+    }
+}
+
+#[no_mangle]
+extern "C" fn irq_dispatch(regs: &mut Registers, irq: u32) {
+    dispatch(irq as u8);
+    if regs.is_user() {
+        signal::deliver(regs);
+    }
+}
+
+// the entry points are in irq.s
+macro_rules! irq_handlers {
+    ($($name:ident,)*) => {
+        extern "C" {
+            $(fn $name();)*
+        }
+        pub const HANDLERS: [unsafe extern "C" fn(); COUNT] = [$($name),*];
+    };
+}
+
+irq_handlers! {
+    irq0,
+    irq1,
+    irq2,
+    irq3,
+    irq4,
+    irq5,
+    irq6,
+    irq7,
+    irq8,
+    irq9,
+    irq10,
+    irq11,
+    irq12,
+    irq13,
+    irq14,
+    irq15,
+}
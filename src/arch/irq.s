@@ -0,0 +1,42 @@
+# every IRQ saves the registers the way syscall_entry does, so a signal sent
+# to a program that never makes a system call can still run its handler on
+# the way back. irq_dispatch(Registers *, irq) is called with the line
+.macro irq name, line
+.global \name
+.type \name, @function
+\name:
+    push ds
+    push es
+    push fs
+    push gs
+    pushad
+    mov ax, 0x10
+    mov ds, ax
+    mov es, ax
+    mov fs, ax
+    mov gs, ax
+    push \line
+    lea eax, [esp + 4]
+    push eax
+    call irq_dispatch
+    add esp, 8
+    jmp syscall_exit
+.endm
+
+.section .text
+irq irq0, 0
+irq irq1, 1
+irq irq2, 2
+irq irq3, 3
+irq irq4, 4
+irq irq5, 5
+irq irq6, 6
+irq irq7, 7
+irq irq8, 8
+irq irq9, 9
+irq irq10, 10
+irq irq11, 11
+irq irq12, 12
+irq irq13, 13
+irq irq14, 14
+irq irq15, 15
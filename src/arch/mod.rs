@@ -1,4 +1,15 @@
+pub mod cpu;
+pub mod exceptions;
+pub mod irq;
 pub mod ports;
+pub mod sysenter;
 pub mod tables;
+pub mod timer;
+pub mod usermode;
 
 core::arch::global_asm!(include_str!("boot.s"));
+core::arch::global_asm!(include_str!("exceptions.s"));
+core::arch::global_asm!(include_str!("irq.s"));
+core::arch::global_asm!(include_str!("switch.s"));
+core::arch::global_asm!(include_str!("syscall.s"));
+core::arch::global_asm!(include_str!("vdso.s"));
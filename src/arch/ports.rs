@@ -1,34 +1,168 @@
+#![allow(dead_code)]
+
 use core::arch::asm;
+use core::fmt;
+use core::marker::PhantomData;
 
-#[derive(Debug)]
-pub struct Port(u16);
+pub trait PortValue: Copy {
+    unsafe fn read_port(port: u16) -> Self;
+    unsafe fn write_port(port: u16, val: Self);
+    unsafe fn read_string(port: u16, dst: *mut Self, count: usize);
+    unsafe fn write_string(port: u16, src: *const Self, count: usize);
+}
 
-impl Port {
-    pub const fn new(port: u16) -> Self {
-        Self(port)
+impl PortValue for u8 {
+    unsafe fn read_port(port: u16) -> Self {
+        let ret;
+        asm!("in al, dx", in("dx") port, out("al") ret, options(nomem, nostack, preserves_flags));
+        ret
     }
-
-    pub const fn add(&self, offset: u16) -> Self {
-        Self(self.0 + offset)
+    unsafe fn write_port(port: u16, val: Self) {
+        asm!("out dx, al", in("dx") port, in("al") val, options(nomem, nostack, preserves_flags));
     }
-
-    pub fn out_u8(&self, val: u8) {
-        unsafe { asm!("out dx, al", in("dx") self.0, in("al") val) };
+    unsafe fn read_string(port: u16, dst: *mut Self, count: usize) {
+        asm!("rep insb", in("dx") port, inout("edi") dst => _, inout("ecx") count => _, options(nostack, preserves_flags));
+    }
+    unsafe fn write_string(port: u16, src: *const Self, count: usize) {
+        asm!("rep outsb", in("dx") port, inout("esi") src => _, inout("ecx") count => _, options(nostack, preserves_flags));
     }
+}
 
-    pub fn slow_out_u8(&self, val: u8) {
-        self.out_u8(val);
-        wait();
+impl PortValue for u16 {
+    unsafe fn read_port(port: u16) -> Self {
+        let ret;
+        asm!("in ax, dx", in("dx") port, out("ax") ret, options(nomem, nostack, preserves_flags));
+        ret
     }
+    unsafe fn write_port(port: u16, val: Self) {
+        asm!("out dx, ax", in("dx") port, in("ax") val, options(nomem, nostack, preserves_flags));
+    }
+    unsafe fn read_string(port: u16, dst: *mut Self, count: usize) {
+        asm!("rep insw", in("dx") port, inout("edi") dst => _, inout("ecx") count => _, options(nostack, preserves_flags));
+    }
+    unsafe fn write_string(port: u16, src: *const Self, count: usize) {
+        asm!("rep outsw", in("dx") port, inout("esi") src => _, inout("ecx") count => _, options(nostack, preserves_flags));
+    }
+}
 
-    pub fn in_u8(&self) -> u8 {
+impl PortValue for u32 {
+    unsafe fn read_port(port: u16) -> Self {
         let ret;
-        unsafe { asm!("in al, dx", in("dx") self.0, out("al") ret) };
+        asm!("in eax, dx", in("dx") port, out("eax") ret, options(nomem, nostack, preserves_flags));
         ret
     }
+    unsafe fn write_port(port: u16, val: Self) {
+        asm!("out dx, eax", in("dx") port, in("eax") val, options(nomem, nostack, preserves_flags));
+    }
+    unsafe fn read_string(port: u16, dst: *mut Self, count: usize) {
+        asm!("rep insd", in("dx") port, inout("edi") dst => _, inout("ecx") count => _, options(nostack, preserves_flags));
+    }
+    unsafe fn write_string(port: u16, src: *const Self, count: usize) {
+        asm!("rep outsd", in("dx") port, inout("esi") src => _, inout("ecx") count => _, options(nostack, preserves_flags));
+    }
+}
+
+macro_rules! port_type {
+    ($name:ident) => {
+        pub struct $name<T: PortValue = u8> {
+            port: u16,
+            _value: PhantomData<T>,
+        }
+
+        impl<T: PortValue> $name<T> {
+            pub const fn new(port: u16) -> Self {
+                Self {
+                    port,
+                    _value: PhantomData,
+                }
+            }
+
+            pub const fn add(&self, offset: u16) -> Self {
+                Self::new(self.port + offset)
+            }
+
+            pub const fn number(&self) -> u16 {
+                self.port
+            }
+        }
+
+        impl<T: PortValue> Clone for $name<T> {
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+        impl<T: PortValue> Copy for $name<T> {}
+
+        impl<T: PortValue> fmt::Debug for $name<T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}({:#x})", stringify!($name), self.port)
+            }
+        }
+    };
+}
+
+port_type!(Port);
+port_type!(ReadOnly);
+port_type!(WriteOnly);
+
+macro_rules! port_read {
+    ($name:ident) => {
+        impl<T: PortValue> $name<T> {
+            pub fn read(&self) -> T {
+                unsafe { T::read_port(self.port) }
+            }
+
+            pub fn read_slice(&self, buf: &mut [T]) {
+                unsafe { T::read_string(self.port, buf.as_mut_ptr(), buf.len()) };
+            }
+
+            // the destination may be unaligned, `rep ins` doesn't care
+            pub unsafe fn read_raw(&self, dst: *mut T, count: usize) {
+                T::read_string(self.port, dst, count);
+            }
+        }
+    };
+}
+
+macro_rules! port_write {
+    ($name:ident) => {
+        impl<T: PortValue> $name<T> {
+            pub fn write(&self, val: T) {
+                unsafe { T::write_port(self.port, val) };
+            }
+
+            pub fn slow_write(&self, val: T) {
+                self.write(val);
+                wait();
+            }
+
+            pub fn write_slice(&self, buf: &[T]) {
+                unsafe { T::write_string(self.port, buf.as_ptr(), buf.len()) };
+            }
+
+            pub unsafe fn write_raw(&self, src: *const T, count: usize) {
+                T::write_string(self.port, src, count);
+            }
+        }
+    };
+}
+
+port_read!(Port);
+port_read!(ReadOnly);
+port_write!(Port);
+port_write!(WriteOnly);
+
+impl<T: PortValue> Port<T> {
+    pub const fn read_only(&self) -> ReadOnly<T> {
+        ReadOnly::new(self.port)
+    }
+
+    pub const fn write_only(&self) -> WriteOnly<T> {
+        WriteOnly::new(self.port)
+    }
 }
 
 pub fn wait() {
     // port 0x80 is always unused after boot
-    Port::new(0x80).out_u8(0);
+    WriteOnly::<u8>::new(0x80).write(0);
 }
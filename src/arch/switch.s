@@ -0,0 +1,27 @@
+# void switch_context(Context *old, const Context *new)
+# saves the callee-saved registers and the stack pointer in `old` and resumes
+# the task described by `new` where it last called switch_context
+.section .text
+.global switch_context
+.type switch_context, @function
+switch_context:
+    mov eax, [esp + 4]
+    mov edx, [esp + 8]
+    mov [eax + 0], ebx
+    mov [eax + 4], esi
+    mov [eax + 8], edi
+    mov [eax + 12], ebp
+    mov [eax + 16], esp
+    mov ebx, [edx + 0]
+    mov esi, [edx + 4]
+    mov edi, [edx + 8]
+    mov ebp, [edx + 12]
+    mov esp, [edx + 16]
+    ret
+
+# first return address of a new task, its stack is empty and aligned
+.global task_start
+.type task_start, @function
+task_start:
+    call task_entry
+    ud2
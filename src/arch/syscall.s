@@ -0,0 +1,84 @@
+# entered through the DPL 3 trap gate at 0x80, saves the registers of the
+# caller and hands them to syscall_dispatch(Registers *), the result is
+# whatever it left in eax
+.section .text
+.global syscall_entry
+.type syscall_entry, @function
+syscall_entry:
+    push ds
+    push es
+    push fs
+    push gs
+    pushad
+    # kernel data segment, see main.rs
+    mov ax, 0x10
+    mov ds, ax
+    mov es, ax
+    mov fs, ax
+    mov gs, ax
+    push esp
+    call syscall_dispatch
+    add esp, 4
+# also the way back from exceptions, see exceptions.s
+.global syscall_exit
+syscall_exit:
+    popad
+    pop gs
+    pop fs
+    pop es
+    pop ds
+    iretd
+
+# returns to ring 3 with the Registers at [esp + 4] as if a system call had
+# just been made, for tasks that never went through syscall_entry
+.global syscall_resume
+.type syscall_resume, @function
+syscall_resume:
+    cli
+    mov esp, [esp + 4]
+    jmp syscall_exit
+
+# entered through sysenter from vdso_sysenter with interrupts disabled and
+# the user stack in ebp, builds the same frame as an int 0x80 would
+.global sysenter_entry
+.type sysenter_entry, @function
+sysenter_entry:
+    push 0x23
+    push ebp
+    pushfd
+    or dword ptr [esp], 0x200
+    push 0x1B
+    push dword ptr [sysexit_return]
+    push ds
+    push es
+    push fs
+    push gs
+    pushad
+    mov ax, 0x10
+    mov ds, ax
+    mov es, ax
+    mov fs, ax
+    mov gs, ax
+    sti
+    push esp
+    call syscall_dispatch
+    add esp, 4
+    cli
+    popad
+    pop gs
+    pop fs
+    pop es
+    pop ds
+    # eip and esp from the frame, the dispatcher may have changed them
+    mov edx, [esp]
+    mov ecx, [esp + 12]
+    add esp, 20
+    # interrupts are only taken after sysexit
+    sti
+    sysexit
+
+# user address of vdso_sysexit, set by sysenter.rs
+.section .data
+.global sysexit_return
+sysexit_return:
+    .long 0
@@ -0,0 +1,90 @@
+#![allow(dead_code)]
+
+use crate::arch::cpu;
+use crate::lazy::LazyMut;
+use crate::mem::paging::PAGE_SIZE;
+use crate::mem::space::VDSO_ADDR;
+
+const MSR_SYSENTER_CS: u32 = 0x174;
+const MSR_SYSENTER_ESP: u32 = 0x175;
+const MSR_SYSENTER_EIP: u32 = 0x176;
+
+const CPUID_SEP: u32 = 1 << 11;
+
+extern "C" {
+    fn sysenter_entry();
+    static mut vdso_syscall: [u8; 2];
+    static vdso_sysenter: u8;
+    static vdso_sysexit: u8;
+    static vdso_sigreturn: u8;
+    static mut sysexit_return: u32;
+    static vdso_start: u8;
+    static vdso_end: u8;
+}
+
+static ENABLED: LazyMut<bool> = LazyMut::new();
+
+// early Pentium Pros report SEP without implementing it
+fn supported() -> bool {
+    let info = cpu::cpuid(1);
+    let family = (info.eax >> 8) & 0xF;
+    let model = (info.eax >> 4) & 0xF;
+    let stepping = info.eax & 0xF;
+    info.edx & CPUID_SEP != 0 && !(family == 6 && model < 3 && stepping < 3)
+}
+
+// SYSENTER_CS is the kernel code selector, the CPU derives the others from
+// it, which the GDT layout follows: kernel data, user code then user data
+pub fn init(code_selector: u16) {
+    let (start, end) = page();
+    if end - start != PAGE_SIZE {
+        panic!("The vDSO doesn't fit in a page");
+    }
+    let enabled = supported();
+    unsafe { ENABLED.init(enabled) };
+    if !enabled {
+        return;
+    }
+    cpu::write_msr(MSR_SYSENTER_CS, code_selector as u64);
+    cpu::write_msr(MSR_SYSENTER_ESP, 0);
+    cpu::write_msr(MSR_SYSENTER_EIP, sysenter_entry as *const () as u64);
+    // the kernel mappings are writable, the vDSO is patched in place before
+    // any program maps it
+    let jump = core::ptr::addr_of_mut!(vdso_syscall) as usize;
+    let target = core::ptr::addr_of!(vdso_sysenter) as usize;
+    let exit = core::ptr::addr_of!(vdso_sysexit) as usize;
+    unsafe {
+        core::ptr::addr_of_mut!(sysexit_return).write_volatile((VDSO_ADDR + exit - start) as u32);
+        ((jump + 1) as *mut u8).write_volatile((target - jump - 2) as u8);
+    }
+}
+
+pub fn enabled() -> bool {
+    ENABLED.try_get().is_some_and(|enabled| *enabled)
+}
+
+// sysenter doesn't look at the TSS, its stack follows the current task
+pub fn set_kernel_stack(esp: u32) {
+    if enabled() {
+        cpu::write_msr(MSR_SYSENTER_ESP, esp as u64);
+    }
+}
+
+// user address of the entry point programs call instead of `int 0x80`
+pub fn entry() -> u32 {
+    let (start, _) = page();
+    (VDSO_ADDR + core::ptr::addr_of!(vdso_syscall) as usize - start) as u32
+}
+
+// user address signal handlers return to
+pub fn sigreturn() -> u32 {
+    let (start, _) = page();
+    (VDSO_ADDR + core::ptr::addr_of!(vdso_sigreturn) as usize - start) as u32
+}
+
+pub fn page() -> (usize, usize) {
+    (
+        core::ptr::addr_of!(vdso_start) as usize,
+        core::ptr::addr_of!(vdso_end) as usize,
+    )
+}
@@ -1,7 +1,7 @@
 #![allow(dead_code)]
 
-use core::fmt;
 use core::arch::asm;
+use core::fmt;
 
 use crate::arch::tables::Descriptor;
 use crate::utils::bits::u2;
@@ -49,7 +49,11 @@ pub fn reload_gs(selector: u16) {
     unsafe { asm!("mov gs, ax", in("ax") selector) };
 }
 
-pub fn selector(index: u16, ti: bool, dpl: u2) -> u16 {
+pub fn load_tr(selector: u16) {
+    unsafe { asm!("ltr ax", in("ax") selector, options(nostack, preserves_flags)) };
+}
+
+pub const fn selector(index: u16, ti: bool, dpl: u2) -> u16 {
     (dpl as u16) | ((ti as u16) << 2) | ((index & 0x1FFF) << 3)
 }
 
@@ -127,7 +131,7 @@ impl Entry {
         self.set_bit(40, bit)
     }
     pub fn set_type(self, typ: SystemSegmentType) -> Self {
-        self.set_bits(43, typ as u64, 4)
+        self.set_bits(40, typ as u64, 4)
     }
 
     pub fn set_g(self, bit: bool) -> Self {
@@ -137,7 +141,7 @@ impl Entry {
         self.set_bit(54, bit)
     }
     pub fn set_l(self, bit: bool) -> Self {
-        self.set_bit(43, bit)
+        self.set_bit(53, bit)
     }
 
     pub fn get_flags(&self) -> u8 {
@@ -148,11 +152,25 @@ impl Entry {
     }
 }
 
+// system segment pointing at the task state segment
+pub fn tss_segment(base: u32, limit: u32) -> Entry {
+    Entry::new()
+        .set_base(base)
+        .set_limit(limit)
+        .set_p(true)
+        .set_dpl(u2::V00)
+        .set_s(false)
+        .set_type(SystemSegmentType::TssAvailable)
+        .set_g(false)
+        .set_db(false)
+}
+
 pub fn default_segments() -> [Entry; 5] {
     let null = Entry::new();
+    // the kernel segments are flat to reach memory mapped devices near 4 GiB
     let kernel_code = Entry::new()
         .set_base(0)
-        .set_limit(0x80000 - 1)
+        .set_limit(0xFFFFF)
         .set_p(true)
         .set_dpl(u2::V00)
         .set_s(true)
@@ -164,7 +182,7 @@ pub fn default_segments() -> [Entry; 5] {
         .set_db(true);
     let kernel_data = Entry::new()
         .set_base(0)
-        .set_limit(0x80000 - 1)
+        .set_limit(0xFFFFF)
         .set_p(true)
         .set_dpl(u2::V00)
         .set_s(true)
@@ -174,21 +192,22 @@ pub fn default_segments() -> [Entry; 5] {
         .set_a(true)
         .set_g(true)
         .set_db(true);
+    // flat as well, nothing but paging can isolate user code from the kernel
     let user_code = Entry::new()
-        .set_base(0x80000 - 1)
-        .set_limit(0x80000)
+        .set_base(0)
+        .set_limit(0xFFFFF)
         .set_p(true)
         .set_dpl(u2::V11)
         .set_s(true)
-        .set_e(false)
+        .set_e(true)
         .set_dc(false)
         .set_rw(true)
         .set_a(true)
         .set_g(true)
         .set_db(true);
     let user_data = Entry::new()
-        .set_base(0x80000 - 1)
-        .set_limit(0x80000)
+        .set_base(0)
+        .set_limit(0xFFFFF)
         .set_p(true)
         .set_dpl(u2::V11)
         .set_s(true)
@@ -3,10 +3,16 @@
 use core::arch::asm;
 use core::fmt;
 
-use crate::arch::tables::Descriptor;
 use crate::arch::ports::Port;
+use crate::arch::tables::Descriptor;
+use crate::arch::{exceptions, irq};
+use crate::syscall;
 use crate::utils::bits::u2;
 
+extern "C" {
+    fn syscall_entry();
+}
+
 const PIC1_CMD: Port = Port::new(0x20);
 const PIC1_DATA: Port = Port::new(0x21);
 const PIC2_CMD: Port = Port::new(0xA0);
@@ -18,24 +24,55 @@ pub fn init(offset1: u8, offset2: u8) {
     const PIC2_IRQ: u8 = 0x2;
     const MODE_8086: u8 = 0x1;
 
-    let mask1 = PIC1_DATA.in_u8();
-    let mask2 = PIC2_DATA.in_u8();
+    let mask1 = PIC1_DATA.read();
+    let mask2 = PIC2_DATA.read();
 
     // ICW1
-    PIC1_CMD.slow_out_u8(INIT | ICW4_NEEDED);
-    PIC2_CMD.slow_out_u8(INIT | ICW4_NEEDED);
+    PIC1_CMD.slow_write(INIT | ICW4_NEEDED);
+    PIC2_CMD.slow_write(INIT | ICW4_NEEDED);
     // ICW2
-    PIC1_DATA.slow_out_u8(offset1);
-    PIC2_DATA.slow_out_u8(offset2);
+    PIC1_DATA.slow_write(offset1);
+    PIC2_DATA.slow_write(offset2);
     // ICW3
-    PIC1_DATA.slow_out_u8(PIC2_IRQ << 1);
-    PIC2_DATA.slow_out_u8(PIC2_IRQ);
+    PIC1_DATA.slow_write(PIC2_IRQ << 1);
+    PIC2_DATA.slow_write(PIC2_IRQ);
     // ICW4
-    PIC1_DATA.slow_out_u8(MODE_8086);
-    PIC2_DATA.slow_out_u8(MODE_8086);
+    PIC1_DATA.slow_write(MODE_8086);
+    PIC2_DATA.slow_write(MODE_8086);
 
-    PIC1_DATA.out_u8(mask1);
-    PIC2_DATA.out_u8(mask2);
+    PIC1_DATA.write(mask1);
+    PIC2_DATA.write(mask2);
+}
+
+pub fn eoi(irq: u8) {
+    const EOI: u8 = 0x20;
+    if irq >= 8 {
+        PIC2_CMD.write(EOI);
+    }
+    PIC1_CMD.write(EOI);
+}
+
+pub fn set_mask(irq: u8, masked: bool) {
+    let (port, line) = if irq < 8 {
+        (PIC1_DATA, irq)
+    } else {
+        (PIC2_DATA, irq - 8)
+    };
+    let mask = port.read();
+    if masked {
+        port.write(mask | 1 << line);
+    } else {
+        port.write(mask & !(1 << line));
+    }
+}
+
+// in service register, tells real IRQs from spurious ones
+pub fn in_service(irq: u8) -> bool {
+    const READ_ISR: u8 = 0x0B;
+    PIC1_CMD.write(READ_ISR);
+    PIC2_CMD.write(READ_ISR);
+    let isr = PIC1_CMD.read() as u16 | (PIC2_CMD.read() as u16) << 8;
+    isr & (1 << irq) != 0
 }
 
 pub fn load(entries: &[Entry]) {
@@ -47,7 +84,6 @@ pub fn load(entries: &[Entry]) {
         asm!(
             "lidt [{}]",
             "sti",
-            "int 13",
             in(reg) &descriptor,
             options(nostack, preserves_flags)
         )
@@ -57,6 +93,21 @@ pub fn load(entries: &[Entry]) {
 #[derive(Clone, Copy)]
 pub struct Entry(u64);
 
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct InterruptFrame {
+    pub eip: u32,
+    pub cs: u32,
+    pub eflags: u32,
+}
+
+impl InterruptFrame {
+    // interrupted code ran in ring 3, the CPU also pushed its esp and ss
+    pub fn is_user(&self) -> bool {
+        self.cs & 3 == 3
+    }
+}
+
 #[derive(Clone, Copy)]
 #[repr(u8)]
 pub enum GateType {
@@ -113,17 +164,35 @@ impl Entry {
     }
 }
 
-extern "x86-interrupt" fn general_fault_handler() {
-    crate::println!("GENERAL_FAULT_TRIGGERED");
+fn interrupt_gate(handler: u32, code_selector: u16) -> Entry {
+    Entry::new()
+        .set_offset(handler)
+        .set_selector(code_selector)
+        .set_p(true)
+        .set_dpl(u2::V00)
+        .set_type(GateType::Interrupt)
 }
 
-pub fn default_gates(code_selector: u16) -> [Entry; 256] {
-    let mut gates = [Entry::new(); 256];
-    gates[13] = Entry::new()
-        .set_offset(general_fault_handler as u32)
+// interrupts stay enabled, and the DPL lets ring 3 raise it with `int`
+fn trap_gate(handler: u32, code_selector: u16, dpl: u2) -> Entry {
+    Entry::new()
+        .set_offset(handler)
         .set_selector(code_selector)
         .set_p(true)
-        .set_dpl(u2::V00)
-        .set_type(GateType::Interrupt);
+        .set_dpl(dpl)
+        .set_type(GateType::Trap)
+}
+
+pub fn default_gates(code_selector: u16) -> [Entry; 256] {
+    let mut gates = [Entry::new(); 256];
+    for (vector, handler) in exceptions::HANDLERS.iter().enumerate() {
+        gates[vector] = interrupt_gate(*handler as *const () as u32, code_selector);
+    }
+    for (irq, handler) in irq::HANDLERS.iter().enumerate() {
+        let vector = irq::vector(irq as u8) as usize;
+        gates[vector] = interrupt_gate(*handler as *const () as u32, code_selector);
+    }
+    gates[syscall::VECTOR as usize] =
+        trap_gate(syscall_entry as *const () as u32, code_selector, u2::V11);
     gates
 }
@@ -1,5 +1,6 @@
 pub mod gdt;
 pub mod idt;
+pub mod tss;
 
 #[derive(Debug)]
 #[repr(C, packed(2))]
@@ -0,0 +1,69 @@
+#![allow(dead_code)]
+
+use crate::arch::tables::gdt;
+use crate::lazy::LazyMut;
+
+static TSS: LazyMut<Tss> = LazyMut::new();
+
+// only esp0/ss0 are used: the stack the CPU switches to when an interrupt
+// or an exception comes from ring 3, tasks are switched in software
+#[derive(Debug, Default)]
+#[repr(C)]
+pub struct Tss {
+    link: u16,
+    _reserved0: u16,
+    esp0: u32,
+    ss0: u16,
+    _reserved1: u16,
+    esp1: u32,
+    ss1: u16,
+    _reserved2: u16,
+    esp2: u32,
+    ss2: u16,
+    _reserved3: u16,
+    cr3: u32,
+    eip: u32,
+    eflags: u32,
+    eax: u32,
+    ecx: u32,
+    edx: u32,
+    ebx: u32,
+    esp: u32,
+    ebp: u32,
+    esi: u32,
+    edi: u32,
+    es: u32,
+    cs: u32,
+    ss: u32,
+    ds: u32,
+    fs: u32,
+    gs: u32,
+    ldtr: u32,
+    trap: u16,
+    // past the limit, so no I/O port is allowed from ring 3
+    iomap_base: u16,
+}
+const_assert!(@size Tss == 104);
+
+pub fn init(ss0: u16) -> gdt::Entry {
+    let size = core::mem::size_of::<Tss>();
+    unsafe {
+        TSS.init(Tss {
+            ss0,
+            iomap_base: size as u16,
+            ..Tss::default()
+        })
+    };
+    gdt::tss_segment(TSS.get() as *const Tss as u32, size as u32 - 1)
+}
+
+// called on every task switch, each task gets its own kernel stack
+pub fn set_kernel_stack(esp0: u32) {
+    if let Some(tss) = TSS.try_get_mut() {
+        tss.esp0 = esp0;
+    }
+}
+
+pub fn kernel_stack() -> u32 {
+    TSS.get().esp0
+}
@@ -0,0 +1,42 @@
+#![allow(dead_code)]
+
+use crate::arch::irq;
+use crate::arch::ports::{Port, WriteOnly};
+use crate::lazy::LazyMut;
+use crate::task;
+
+const PIT_CHANNEL0: Port = Port::new(0x40);
+const PIT_CMD: WriteOnly = WriteOnly::new(0x43);
+const PIT_FREQUENCY: u32 = 1193182;
+
+pub const HZ: u32 = 100;
+
+static TICKS: LazyMut<u64> = LazyMut::new();
+
+pub fn init() {
+    const CHANNEL0: u8 = 0b00 << 6;
+    const LOHI: u8 = 0b11 << 4;
+    const RATE_GENERATOR: u8 = 0b010 << 1;
+
+    unsafe { TICKS.init(0) };
+    let divisor = PIT_FREQUENCY / HZ;
+    PIT_CMD.write(CHANNEL0 | LOHI | RATE_GENERATOR);
+    PIT_CHANNEL0.write((divisor & 0xFF) as u8);
+    PIT_CHANNEL0.write((divisor >> 8) as u8);
+    if irq::register(0, tick).is_err() {
+        panic!("Could not register the timer interrupt");
+    }
+}
+
+fn tick(_irq: u8) {
+    *TICKS.get_mut() += 1;
+    task::tick();
+}
+
+pub fn ticks() -> u64 {
+    *TICKS.get()
+}
+
+pub fn uptime_ms() -> u64 {
+    ticks() * 1000 / HZ as u64
+}
@@ -0,0 +1,38 @@
+#![allow(dead_code)]
+
+use core::arch::asm;
+
+use crate::arch::tables::gdt;
+use crate::utils::bits::u2;
+
+pub const CODE_SELECTOR: u16 = gdt::selector(3, false, u2::V11);
+pub const DATA_SELECTOR: u16 = gdt::selector(4, false, u2::V11);
+
+const EFLAGS_RESERVED: u32 = 1 << 1;
+const EFLAGS_IF: u32 = 1 << 9;
+
+// drops to ring 3 at `entry` with `stack`, the way back into the kernel is an
+// interrupt or an exception landing on the kernel stack set in the TSS
+pub fn enter(entry: u32, stack: u32) -> ! {
+    unsafe {
+        asm!(
+            "cli",
+            "mov ds, {data:x}",
+            "mov es, {data:x}",
+            "mov fs, {data:x}",
+            "mov gs, {data:x}",
+            "push {data:e}",
+            "push {stack:e}",
+            "push {eflags:e}",
+            "push {code:e}",
+            "push {entry:e}",
+            "iretd",
+            data = in(reg) DATA_SELECTOR as u32,
+            code = in(reg) CODE_SELECTOR as u32,
+            eflags = in(reg) EFLAGS_RESERVED | EFLAGS_IF,
+            stack = in(reg) stack,
+            entry = in(reg) entry,
+            options(noreturn)
+        )
+    }
+}
@@ -0,0 +1,37 @@
+# page mapped read-only at the top of every user space, see mem/space.rs, so
+# everything in it is position independent. vdso_syscall takes the int 0x80
+# register convention and goes through the fastest path the CPU has: its
+# short jump is retargeted at vdso_sysenter by sysenter.rs when available
+.section .vdso, "ax"
+.global vdso_syscall
+.type vdso_syscall, @function
+vdso_syscall:
+    .byte 0xEB, vdso_int80 - vdso_syscall - 2
+
+vdso_int80:
+    int 0x80
+    ret
+
+# sysexit comes back with eip in edx and esp in ecx, the kernel finds the
+# user stack in ebp
+.global vdso_sysenter
+vdso_sysenter:
+    push ecx
+    push edx
+    push ebp
+    mov ebp, esp
+    sysenter
+.global vdso_sysexit
+vdso_sysexit:
+    pop ebp
+    pop edx
+    pop ecx
+    ret
+
+# signal handlers return here, see task/signal.rs. 17 is syscall::SIGRETURN,
+# made with int 0x80 since sysexit would clobber the ecx and edx the
+# interrupted code gets back
+.global vdso_sigreturn
+vdso_sigreturn:
+    mov eax, 17
+    int 0x80
@@ -0,0 +1,395 @@
+#![allow(dead_code)]
+
+use core::fmt::{self, Write};
+
+use crate::arch::timer;
+use crate::dev::ata::{self, Identity};
+use crate::dev::block::{self, BlockDevice};
+use crate::dev::pci::{self, Function, Match};
+use crate::fs;
+use crate::lazy::LazyMut;
+use crate::utils::fixed::FixedStr;
+use crate::FRAMES;
+
+pub const SECTOR_SIZE: usize = 512;
+pub const MAX_DISKS: usize = 8;
+
+pub const IDS: &[Match] = &[Match {
+    prog_if: Some(0x01),
+    ..Match::class(0x01, 0x06)
+}];
+
+const CMD_READ_DMA_EXT: u8 = 0x25;
+const CMD_WRITE_DMA_EXT: u8 = 0x35;
+
+// generic host control registers
+const HBA_CAP: usize = 0x00;
+const HBA_GHC: usize = 0x04;
+const HBA_IS: usize = 0x08;
+const HBA_PI: usize = 0x0C;
+const HBA_VS: usize = 0x10;
+const HBA_CAP2: usize = 0x24;
+const HBA_BOHC: usize = 0x28;
+
+const GHC_AE: u32 = 1 << 31;
+const CAP2_BOH: u32 = 1 << 0;
+const BOHC_BOS: u32 = 1 << 0;
+const BOHC_OOS: u32 = 1 << 1;
+
+// port registers, relative to the port base
+const PORTS: usize = 0x100;
+const PORT_SIZE: usize = 0x80;
+const PX_CLB: usize = 0x00;
+const PX_CLBU: usize = 0x04;
+const PX_FB: usize = 0x08;
+const PX_FBU: usize = 0x0C;
+const PX_IS: usize = 0x10;
+const PX_IE: usize = 0x14;
+const PX_CMD: usize = 0x18;
+const PX_TFD: usize = 0x20;
+const PX_SIG: usize = 0x24;
+const PX_SSTS: usize = 0x28;
+const PX_SERR: usize = 0x30;
+const PX_CI: usize = 0x38;
+
+const CMD_ST: u32 = 1 << 0;
+const CMD_FRE: u32 = 1 << 4;
+const CMD_FR: u32 = 1 << 14;
+const CMD_CR: u32 = 1 << 15;
+
+const IS_TFES: u32 = 1 << 30;
+const TFD_ERR: u32 = 1 << 0;
+const TFD_DRQ: u32 = 1 << 3;
+const TFD_BSY: u32 = 1 << 7;
+
+const SSTS_DET_PRESENT: u32 = 3;
+const SSTS_IPM_ACTIVE: u32 = 1;
+const SIG_ATA: u32 = 0x0000_0101;
+
+const FIS_REG_H2D: u8 = 0x27;
+const FIS_COMMAND: u8 = 1 << 7;
+
+// one frame per port: command list, received FIS area, then one command table
+const CLB_OFFSET: usize = 0;
+const FB_OFFSET: usize = 0x400;
+const CTBA_OFFSET: usize = 0x500;
+const PRDT_OFFSET: usize = 0x80;
+const PRDT_ENTRIES: usize = (crate::mem::frames::FRAME_SIZE - CTBA_OFFSET - PRDT_OFFSET) / 16;
+const PRD_MAX: usize = 4 << 20;
+
+const TIMEOUT_MS: u64 = 5000;
+
+static DISKS: LazyMut<[Option<Disk>; MAX_DISKS]> = LazyMut::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    NoDevice,
+    NotAta,
+    Timeout,
+    // content of the task file: error register and status
+    TaskFile(u8, u8),
+    OutOfRange,
+    NoMemory,
+}
+
+// memory mapped registers, the ABAR is identity mapped
+#[derive(Debug, Clone, Copy)]
+struct Regs(usize);
+
+pub struct Disk {
+    port: Regs,
+    index: usize,
+    memory: usize,
+    lba48: bool,
+    sectors: u64,
+    model: FixedStr<40>,
+}
+
+impl Regs {
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { ((self.0 + offset) as *const u32).read_volatile() }
+    }
+
+    fn write(&self, offset: usize, val: u32) {
+        unsafe { ((self.0 + offset) as *mut u32).write_volatile(val) }
+    }
+
+    fn port(&self, index: usize) -> Regs {
+        Regs(self.0 + PORTS + index * PORT_SIZE)
+    }
+
+    // waits for the `mask` bits of a register to read as `val`
+    fn wait(&self, offset: usize, mask: u32, val: u32) -> Result<(), Error> {
+        let start = timer::ticks();
+        let timeout = TIMEOUT_MS * timer::HZ as u64 / 1000;
+        while self.read(offset) & mask != val {
+            if timer::ticks() - start > timeout {
+                return Err(Error::Timeout);
+            }
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+}
+
+impl Disk {
+    fn probe(port: Regs, index: usize) -> Result<Self, Error> {
+        let ssts = port.read(PX_SSTS);
+        if ssts & 0xF != SSTS_DET_PRESENT || (ssts >> 8) & 0xF != SSTS_IPM_ACTIVE {
+            return Err(Error::NoDevice);
+        }
+        // ATAPI, port multipliers and enclosures have other signatures
+        if port.read(PX_SIG) != SIG_ATA {
+            return Err(Error::NotAta);
+        }
+
+        Self::stop(port)?;
+        let memory = FRAMES.get_mut().alloc().ok_or(Error::NoMemory)?;
+        unsafe { core::ptr::write_bytes(memory as *mut u8, 0, crate::mem::frames::FRAME_SIZE) };
+        port.write(PX_CLB, (memory + CLB_OFFSET) as u32);
+        port.write(PX_CLBU, 0);
+        port.write(PX_FB, (memory + FB_OFFSET) as u32);
+        port.write(PX_FBU, 0);
+        port.write(PX_SERR, u32::MAX);
+        port.write(PX_IS, u32::MAX);
+        // completion is polled
+        port.write(PX_IE, 0);
+        port.write(PX_CMD, port.read(PX_CMD) | CMD_FRE);
+        port.wait(PX_TFD, TFD_BSY | TFD_DRQ, 0)?;
+        port.write(PX_CMD, port.read(PX_CMD) | CMD_ST);
+
+        let mut disk = Self {
+            port,
+            index,
+            memory,
+            lba48: false,
+            sectors: 0,
+            model: FixedStr::new(),
+        };
+        let mut words = [0u16; 256];
+        disk.command(
+            ata::CMD_IDENTIFY,
+            0,
+            0,
+            words.as_mut_ptr() as usize,
+            SECTOR_SIZE,
+            false,
+        )?;
+        let identity = Identity::parse(&words);
+        disk.lba48 = identity.lba48;
+        disk.sectors = identity.sectors;
+        disk.model = identity.model;
+        Ok(disk)
+    }
+
+    fn stop(port: Regs) -> Result<(), Error> {
+        port.write(PX_CMD, port.read(PX_CMD) & !(CMD_ST | CMD_FRE));
+        port.wait(PX_CMD, CMD_CR | CMD_FR, 0)
+    }
+
+    pub fn sectors(&self) -> u64 {
+        self.sectors
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    // issues a command in slot 0 and polls for its completion, the data buffer
+    // is physically contiguous since memory is identity mapped
+    fn command(
+        &mut self,
+        cmd: u8,
+        lba: u64,
+        count: u16,
+        addr: usize,
+        len: usize,
+        write: bool,
+    ) -> Result<(), Error> {
+        let prds = len.div_ceil(PRD_MAX);
+        if prds > PRDT_ENTRIES || addr & 1 != 0 || len & 1 != 0 {
+            return Err(Error::OutOfRange);
+        }
+        let table = self.memory + CTBA_OFFSET;
+        unsafe {
+            let fis = table as *mut u8;
+            core::ptr::write_bytes(fis, 0, PRDT_OFFSET);
+            let bytes = [
+                FIS_REG_H2D,
+                FIS_COMMAND,
+                cmd,
+                0,
+                lba as u8,
+                (lba >> 8) as u8,
+                (lba >> 16) as u8,
+                // LBA mode
+                1 << 6,
+                (lba >> 24) as u8,
+                (lba >> 32) as u8,
+                (lba >> 40) as u8,
+                0,
+                count as u8,
+                (count >> 8) as u8,
+            ];
+            core::ptr::copy_nonoverlapping(bytes.as_ptr(), fis, bytes.len());
+
+            let prdt = (table + PRDT_OFFSET) as *mut u32;
+            for i in 0..prds {
+                let offset = i * PRD_MAX;
+                let size = (len - offset).min(PRD_MAX);
+                let prd = prdt.add(i * 4);
+                prd.write_volatile((addr + offset) as u32);
+                prd.add(1).write_volatile(0);
+                prd.add(2).write_volatile(0);
+                // byte count minus one
+                prd.add(3).write_volatile(size as u32 - 1);
+            }
+
+            // command FIS length in dwords, write direction, PRDT length
+            let header = (self.memory + CLB_OFFSET) as *mut u32;
+            header.write_volatile(5 | (write as u32) << 6 | (prds as u32) << 16);
+            header.add(1).write_volatile(0);
+            header.add(2).write_volatile(table as u32);
+            header.add(3).write_volatile(0);
+        }
+
+        let port = self.port;
+        port.wait(PX_TFD, TFD_BSY | TFD_DRQ, 0)?;
+        port.write(PX_IS, u32::MAX);
+        port.write(PX_CI, 1);
+        let start = timer::ticks();
+        let timeout = TIMEOUT_MS * timer::HZ as u64 / 1000;
+        while port.read(PX_CI) & 1 != 0 {
+            if port.read(PX_IS) & IS_TFES != 0 {
+                break;
+            }
+            if timer::ticks() - start > timeout {
+                return Err(Error::Timeout);
+            }
+            core::hint::spin_loop();
+        }
+        let tfd = port.read(PX_TFD);
+        if port.read(PX_IS) & IS_TFES != 0 || tfd & TFD_ERR != 0 {
+            // the port stops on errors, restart it for the next command
+            port.write(PX_IS, u32::MAX);
+            Self::stop(port)?;
+            port.write(PX_SERR, u32::MAX);
+            port.write(PX_CMD, port.read(PX_CMD) | CMD_FRE | CMD_ST);
+            return Err(Error::TaskFile((tfd >> 8) as u8, tfd as u8));
+        }
+        Ok(())
+    }
+
+    fn transfer(&mut self, lba: u64, addr: usize, len: usize, write: bool) -> Result<(), Error> {
+        if !len.is_multiple_of(SECTOR_SIZE) {
+            return Err(Error::OutOfRange);
+        }
+        let count = len / SECTOR_SIZE;
+        if count == 0
+            || count > u16::MAX as usize
+            || lba
+                .checked_add(count as u64)
+                .is_none_or(|end| end > self.sectors)
+            || !self.lba48
+        {
+            return Err(Error::OutOfRange);
+        }
+        let cmd = if write {
+            CMD_WRITE_DMA_EXT
+        } else {
+            CMD_READ_DMA_EXT
+        };
+        self.command(cmd, lba, count as u16, addr, len, write)
+    }
+
+    fn io_error(&self, err: Error) -> fs::Error {
+        crate::eprintln!("AHCI error on {self:?}: {err:?}");
+        fs::Error::Io
+    }
+}
+
+impl fmt::Debug for Disk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Disk")
+            .field("port", &self.index)
+            .field("lba48", &self.lba48)
+            .field("sectors", &self.sectors)
+            .field("model", &self.model)
+            .finish()
+    }
+}
+
+impl BlockDevice for Disk {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> fs::Result<()> {
+        self.transfer(lba, buf.as_mut_ptr() as usize, buf.len(), false)
+            .map_err(|err| self.io_error(err))
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> fs::Result<()> {
+        self.transfer(lba, buf.as_ptr() as usize, buf.len(), true)
+            .map_err(|err| self.io_error(err))
+    }
+
+    fn flush(&mut self) -> fs::Result<()> {
+        self.command(ata::CMD_CACHE_FLUSH_EXT, 0, 0, 0, 0, false)
+            .map_err(|err| self.io_error(err))
+    }
+}
+
+// takes the HBA from the firmware and puts it in AHCI mode, without a full
+// reset so the links brought up by the firmware stay up
+fn enable(hba: Regs) -> Result<(), Error> {
+    if hba.read(HBA_CAP2) & CAP2_BOH != 0 {
+        hba.write(HBA_BOHC, hba.read(HBA_BOHC) | BOHC_OOS);
+        hba.wait(HBA_BOHC, BOHC_BOS, 0)?;
+    }
+    hba.write(HBA_GHC, hba.read(HBA_GHC) | GHC_AE);
+    Ok(())
+}
+
+// registers every SATA disk of the HBA as `sd<letter>`
+pub fn probe(function: &Function) -> fs::Result<()> {
+    let (abar, _) = function.bars[5].memory().ok_or(fs::Error::Unsupported)?;
+    if abar > u32::MAX as u64 {
+        return Err(fs::Error::Unsupported);
+    }
+    function.set_command(pci::COMMAND_MEMORY | pci::COMMAND_BUS_MASTER, true);
+    let hba = Regs(abar as usize);
+    if let Err(err) = enable(hba) {
+        crate::eprintln!("AHCI {}: {err:?}", function.addr);
+        return Err(fs::Error::Io);
+    }
+
+    if !DISKS.is_init() {
+        unsafe { DISKS.init([const { None }; MAX_DISKS]) };
+    }
+    let implemented = hba.read(HBA_PI);
+    for index in (0..32).filter(|i| implemented & (1 << i) != 0) {
+        let disks = DISKS.get_mut();
+        let Some(slot) = disks.iter().position(|disk| disk.is_none()) else {
+            break;
+        };
+        let disk = match Disk::probe(hba.port(index), index) {
+            Ok(disk) => disk,
+            Err(Error::NoDevice) => continue,
+            Err(err) => {
+                crate::eprintln!("AHCI {} port {index}: {err:?}", function.addr);
+                continue;
+            }
+        };
+        crate::eprintln!("AHCI {} port {index}: {disk:?}", function.addr);
+        let disk = disks[slot].insert(disk);
+        let mut name = crate::dev::Name::from("sd");
+        let _ = name.write_char((b'a' + slot as u8) as char);
+        block::register(&name, disk)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,409 @@
+#![allow(dead_code)]
+
+use core::fmt::{self, Write};
+
+use crate::arch::ports::{Port, ReadOnly, WriteOnly};
+use crate::dev::block::{self, BlockDevice};
+use crate::fs;
+use crate::lazy::LazyMut;
+use crate::utils::fixed::FixedStr;
+
+pub const SECTOR_SIZE: usize = 512;
+
+const PRIMARY_IO: u16 = 0x1F0;
+const PRIMARY_CTRL: u16 = 0x3F6;
+const SECONDARY_IO: u16 = 0x170;
+const SECONDARY_CTRL: u16 = 0x376;
+
+const STATUS_ERR: u8 = 1 << 0;
+const STATUS_DRQ: u8 = 1 << 3;
+const STATUS_DF: u8 = 1 << 5;
+const STATUS_BSY: u8 = 1 << 7;
+
+const CTRL_NIEN: u8 = 1 << 1;
+const CTRL_SRST: u8 = 1 << 2;
+
+const CMD_READ_SECTORS: u8 = 0x20;
+pub const CMD_READ_SECTORS_EXT: u8 = 0x24;
+const CMD_WRITE_SECTORS: u8 = 0x30;
+pub const CMD_WRITE_SECTORS_EXT: u8 = 0x34;
+const CMD_CACHE_FLUSH: u8 = 0xE7;
+pub const CMD_CACHE_FLUSH_EXT: u8 = 0xEA;
+pub const CMD_IDENTIFY: u8 = 0xEC;
+
+const LBA28_MAX: u64 = 1 << 28;
+const TIMEOUT: usize = 1_000_000;
+
+static DRIVES: LazyMut<[Option<Drive>; 4]> = LazyMut::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    NoDevice,
+    NotAta,
+    Timeout,
+    DeviceFault,
+    // content of the error register
+    Command(u8),
+    OutOfRange,
+}
+
+#[derive(Debug)]
+struct Channel {
+    data: Port<u16>,
+    error: ReadOnly,
+    features: WriteOnly,
+    sector_count: Port,
+    lba_lo: Port,
+    lba_mid: Port,
+    lba_hi: Port,
+    drive: Port,
+    status: ReadOnly,
+    command: WriteOnly,
+    alt_status: ReadOnly,
+    control: WriteOnly,
+}
+
+pub struct Drive {
+    channel: Channel,
+    slave: bool,
+    lba48: bool,
+    sectors: u64,
+    model: FixedStr<40>,
+}
+
+// the parts of the IDENTIFY DEVICE data we use, shared with AHCI
+#[derive(Debug, Clone, Copy)]
+pub struct Identity {
+    pub lba48: bool,
+    pub sectors: u64,
+    pub model: FixedStr<40>,
+}
+
+impl Identity {
+    pub fn parse(words: &[u16; 256]) -> Self {
+        let lba48 = words[83] & (1 << 10) != 0;
+        let sectors = if lba48 {
+            words[100] as u64
+                | (words[101] as u64) << 16
+                | (words[102] as u64) << 32
+                | (words[103] as u64) << 48
+        } else {
+            words[60] as u64 | (words[61] as u64) << 16
+        };
+        // the model string is stored as big endian words padded with spaces
+        let mut model = [0; 40];
+        for (i, word) in words[27..47].iter().enumerate() {
+            model[i * 2..i * 2 + 2].copy_from_slice(&word.to_be_bytes());
+        }
+        let model = core::str::from_utf8(&model).unwrap_or("").trim_end();
+        Self {
+            lba48,
+            sectors,
+            model: FixedStr::from(model),
+        }
+    }
+}
+
+impl Channel {
+    // error/features and status/command share the same io ports
+    const fn new(io: u16, ctrl: u16) -> Self {
+        Self {
+            data: Port::new(io),
+            error: ReadOnly::new(io + 1),
+            features: WriteOnly::new(io + 1),
+            sector_count: Port::new(io + 2),
+            lba_lo: Port::new(io + 3),
+            lba_mid: Port::new(io + 4),
+            lba_hi: Port::new(io + 5),
+            drive: Port::new(io + 6),
+            status: ReadOnly::new(io + 7),
+            command: WriteOnly::new(io + 7),
+            alt_status: ReadOnly::new(ctrl),
+            control: WriteOnly::new(ctrl),
+        }
+    }
+
+    // reading the alternate status 4 times gives the drive the 400ns it needs
+    fn delay(&self) {
+        for _ in 0..4 {
+            self.alt_status.read();
+        }
+    }
+
+    fn reset(&self) {
+        self.control.write(CTRL_SRST | CTRL_NIEN);
+        self.delay();
+        self.control.write(CTRL_NIEN);
+        self.delay();
+    }
+
+    fn select(&self, slave: bool, bits: u8) {
+        self.drive.write(0xA0 | bits | (slave as u8) << 4);
+        self.delay();
+    }
+
+    fn wait_not_busy(&self) -> Result<u8, Error> {
+        for _ in 0..TIMEOUT {
+            let status = self.status.read();
+            if status & STATUS_BSY == 0 {
+                return Ok(status);
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    // a single TIMEOUT bounds the whole wait, busy or not
+    fn wait_data(&self) -> Result<(), Error> {
+        for _ in 0..TIMEOUT {
+            let status = self.status.read();
+            if status & STATUS_BSY != 0 {
+                continue;
+            }
+            if status & STATUS_ERR != 0 {
+                return Err(Error::Command(self.error.read()));
+            }
+            if status & STATUS_DF != 0 {
+                return Err(Error::DeviceFault);
+            }
+            if status & STATUS_DRQ != 0 {
+                return Ok(());
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        let status = self.wait_not_busy()?;
+        if status & STATUS_ERR != 0 {
+            Err(Error::Command(self.error.read()))
+        } else if status & STATUS_DF != 0 {
+            Err(Error::DeviceFault)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn read_sector(&self, buf: &mut [u8]) {
+        let buf = &mut buf[..SECTOR_SIZE];
+        unsafe {
+            self.data
+                .read_raw(buf.as_mut_ptr() as *mut u16, SECTOR_SIZE / 2)
+        };
+    }
+
+    fn write_sector(&self, buf: &[u8]) {
+        let buf = &buf[..SECTOR_SIZE];
+        unsafe {
+            self.data
+                .write_raw(buf.as_ptr() as *const u16, SECTOR_SIZE / 2)
+        };
+    }
+
+    fn identify(&self, slave: bool) -> Result<[u16; 256], Error> {
+        self.select(slave, 0);
+        self.sector_count.write(0);
+        self.lba_lo.write(0);
+        self.lba_mid.write(0);
+        self.lba_hi.write(0);
+        self.command.write(CMD_IDENTIFY);
+        self.delay();
+        if self.status.read() == 0 {
+            return Err(Error::NoDevice);
+        }
+        self.wait_not_busy()?;
+        // ATAPI and SATA devices report their signature here and abort
+        if self.lba_mid.read() != 0 || self.lba_hi.read() != 0 {
+            return Err(Error::NotAta);
+        }
+        self.wait_data()?;
+        let mut words = [0; 256];
+        self.data.read_slice(&mut words);
+        Ok(words)
+    }
+}
+
+impl Drive {
+    fn probe(io: u16, ctrl: u16, slave: bool) -> Result<Self, Error> {
+        let channel = Channel::new(io, ctrl);
+        // a floating bus reads as 0xFF
+        if channel.status.read() == 0xFF {
+            return Err(Error::NoDevice);
+        }
+        let identity = Identity::parse(&channel.identify(slave)?);
+        Ok(Self {
+            channel,
+            slave,
+            lba48: identity.lba48,
+            sectors: identity.sectors,
+            model: identity.model,
+        })
+    }
+
+    pub fn sectors(&self) -> u64 {
+        self.sectors
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn setup(&self, lba: u64, count: u16) -> Result<bool, Error> {
+        let count = count as u64;
+        if count == 0 || lba + count > self.sectors {
+            return Err(Error::OutOfRange);
+        }
+        let channel = &self.channel;
+        channel.wait_not_busy()?;
+        let ext = lba + count > LBA28_MAX || count > 256;
+        if ext {
+            if !self.lba48 {
+                return Err(Error::OutOfRange);
+            }
+            channel.select(self.slave, 0x40);
+            // high bytes go first, the registers are two bytes deep
+            channel.sector_count.write((count >> 8) as u8);
+            channel.lba_lo.write((lba >> 24) as u8);
+            channel.lba_mid.write((lba >> 32) as u8);
+            channel.lba_hi.write((lba >> 40) as u8);
+        } else {
+            channel.select(self.slave, 0x40 | ((lba >> 24) & 0x0F) as u8);
+        }
+        // 256 sectors is encoded as 0
+        channel.sector_count.write(count as u8);
+        channel.lba_lo.write(lba as u8);
+        channel.lba_mid.write((lba >> 8) as u8);
+        channel.lba_hi.write((lba >> 16) as u8);
+        Ok(ext)
+    }
+
+    pub fn read_sectors(&self, lba: u64, buf: &mut [u8]) -> Result<(), Error> {
+        if !buf.len().is_multiple_of(SECTOR_SIZE) {
+            return Err(Error::OutOfRange);
+        }
+        let count = buf.len() / SECTOR_SIZE;
+        if count > u16::MAX as usize {
+            return Err(Error::OutOfRange);
+        }
+        let ext = self.setup(lba, count as u16)?;
+        let cmd = if ext {
+            CMD_READ_SECTORS_EXT
+        } else {
+            CMD_READ_SECTORS
+        };
+        self.channel.command.write(cmd);
+        for sector in buf.chunks_exact_mut(SECTOR_SIZE) {
+            self.channel.delay();
+            self.channel.wait_data()?;
+            self.channel.read_sector(sector);
+        }
+        self.channel.check()
+    }
+
+    pub fn write_sectors(&self, lba: u64, buf: &[u8]) -> Result<(), Error> {
+        if !buf.len().is_multiple_of(SECTOR_SIZE) {
+            return Err(Error::OutOfRange);
+        }
+        let count = buf.len() / SECTOR_SIZE;
+        if count > u16::MAX as usize {
+            return Err(Error::OutOfRange);
+        }
+        let ext = self.setup(lba, count as u16)?;
+        let cmd = if ext {
+            CMD_WRITE_SECTORS_EXT
+        } else {
+            CMD_WRITE_SECTORS
+        };
+        self.channel.command.write(cmd);
+        for sector in buf.chunks_exact(SECTOR_SIZE) {
+            self.channel.delay();
+            self.channel.wait_data()?;
+            self.channel.write_sector(sector);
+        }
+        self.channel.check()
+    }
+
+    pub fn flush(&self) -> Result<(), Error> {
+        self.channel.select(self.slave, 0x40);
+        let cmd = if self.lba48 {
+            CMD_CACHE_FLUSH_EXT
+        } else {
+            CMD_CACHE_FLUSH
+        };
+        self.channel.command.write(cmd);
+        self.channel.delay();
+        self.channel.check()
+    }
+
+    fn io_error(&self, err: Error) -> fs::Error {
+        crate::eprintln!("ATA error on {self:?}: {err:?}");
+        fs::Error::Io
+    }
+}
+
+impl fmt::Debug for Drive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Drive")
+            .field("io", &self.channel.data)
+            .field("slave", &self.slave)
+            .field("lba48", &self.lba48)
+            .field("sectors", &self.sectors)
+            .field("model", &self.model)
+            .finish()
+    }
+}
+
+impl BlockDevice for Drive {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> fs::Result<()> {
+        self.read_sectors(lba, buf)
+            .map_err(|err| self.io_error(err))
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> fs::Result<()> {
+        self.write_sectors(lba, buf)
+            .map_err(|err| self.io_error(err))
+    }
+
+    fn flush(&mut self) -> fs::Result<()> {
+        Drive::flush(self).map_err(|err| self.io_error(err))
+    }
+}
+
+pub fn init() -> fs::Result<()> {
+    const NAMES: [&str; 4] = ["hda", "hdb", "hdc", "hdd"];
+    const LOCATIONS: [(u16, u16, bool); 4] = [
+        (PRIMARY_IO, PRIMARY_CTRL, false),
+        (PRIMARY_IO, PRIMARY_CTRL, true),
+        (SECONDARY_IO, SECONDARY_CTRL, false),
+        (SECONDARY_IO, SECONDARY_CTRL, true),
+    ];
+
+    unsafe { DRIVES.init([const { None }; 4]) };
+    Channel::new(PRIMARY_IO, PRIMARY_CTRL).reset();
+    Channel::new(SECONDARY_IO, SECONDARY_CTRL).reset();
+    for (i, (io, ctrl, slave)) in LOCATIONS.into_iter().enumerate() {
+        match Drive::probe(io, ctrl, slave) {
+            Ok(drive) => {
+                crate::eprintln!("ATA {}: {drive:?}", NAMES[i]);
+                DRIVES.get_mut()[i] = Some(drive);
+            }
+            Err(Error::NoDevice) => {}
+            Err(err) => {
+                crate::eprintln!("ATA {}: {err:?}", NAMES[i]);
+            }
+        }
+    }
+    for (i, drive) in DRIVES.get_mut().iter_mut().enumerate() {
+        if let Some(drive) = drive {
+            block::register(NAMES[i], drive)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use crate::dev::block;
+use crate::fs::{Error, Result};
+use crate::lazy::LazyMut;
+use crate::mem::frames::FRAME_SIZE;
+use crate::FRAMES;
+
+// each buffer holds one block in its own frame
+pub const BUFFERS: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+    pub writebacks: u64,
+    pub dirty: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Buffer {
+    disk: usize,
+    lba: u64,
+    data: *mut u8,
+    valid: bool,
+    dirty: bool,
+    // last use, the smallest one is evicted first
+    stamp: u64,
+}
+
+struct Cache {
+    buffers: [Buffer; BUFFERS],
+    clock: u64,
+    hits: u64,
+    misses: u64,
+    writebacks: u64,
+}
+
+static CACHE: LazyMut<Cache> = LazyMut::new();
+
+pub fn init() -> Result<()> {
+    let mut buffers = [Buffer {
+        disk: 0,
+        lba: 0,
+        data: core::ptr::null_mut(),
+        valid: false,
+        dirty: false,
+        stamp: 0,
+    }; BUFFERS];
+    for buffer in buffers.iter_mut() {
+        buffer.data = FRAMES.get_mut().alloc().ok_or(Error::NoSpace)? as *mut u8;
+    }
+    unsafe {
+        CACHE.init(Cache {
+            buffers,
+            clock: 0,
+            hits: 0,
+            misses: 0,
+            writebacks: 0,
+        })
+    };
+    Ok(())
+}
+
+impl Buffer {
+    fn data(&self, len: usize) -> &'static mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.data, len) }
+    }
+
+    fn write_back(&mut self) -> Result<()> {
+        if self.valid && self.dirty {
+            let disk = block::disk(self.disk);
+            disk.write_blocks(self.lba, self.data(disk.block_size()))?;
+            self.dirty = false;
+            CACHE.get_mut().writebacks += 1;
+        }
+        Ok(())
+    }
+}
+
+// returns the buffer holding the block, reading it from the disk if `fill` is set
+fn get(disk: usize, lba: u64, fill: bool) -> Result<&'static mut Buffer> {
+    let cache = CACHE.get_mut();
+    cache.clock += 1;
+    let stamp = cache.clock;
+    if let Some(i) = cache
+        .buffers
+        .iter()
+        .position(|b| b.valid && b.disk == disk && b.lba == lba)
+    {
+        cache.hits += 1;
+        cache.buffers[i].stamp = stamp;
+        return Ok(&mut cache.buffers[i]);
+    }
+    cache.misses += 1;
+
+    let block_size = block::disk(disk).block_size();
+    if block_size > FRAME_SIZE {
+        return Err(Error::Unsupported);
+    }
+    let buffer = cache
+        .buffers
+        .iter_mut()
+        .min_by_key(|b| if b.valid { b.stamp } else { 0 })
+        .ok_or(Error::NoSpace)?;
+    buffer.write_back()?;
+    buffer.valid = false;
+    if fill {
+        block::disk(disk).read_blocks(lba, buffer.data(block_size))?;
+    }
+    buffer.disk = disk;
+    buffer.lba = lba;
+    buffer.valid = true;
+    buffer.dirty = false;
+    buffer.stamp = stamp;
+    Ok(buffer)
+}
+
+pub fn read(disk: usize, lba: u64, offset: usize, buf: &mut [u8]) -> Result<()> {
+    let block_size = block::disk(disk).block_size();
+    if offset + buf.len() > block_size {
+        return Err(Error::Invalid);
+    }
+    let buffer = get(disk, lba, true)?;
+    buf.copy_from_slice(&buffer.data(block_size)[offset..offset + buf.len()]);
+    Ok(())
+}
+
+pub fn write(disk: usize, lba: u64, offset: usize, buf: &[u8]) -> Result<()> {
+    let block_size = block::disk(disk).block_size();
+    if offset + buf.len() > block_size {
+        return Err(Error::Invalid);
+    }
+    // no need to read a block that is entirely overwritten
+    let buffer = get(disk, lba, buf.len() != block_size)?;
+    buffer.data(block_size)[offset..offset + buf.len()].copy_from_slice(buf);
+    buffer.dirty = true;
+    Ok(())
+}
+
+pub fn sync(disk: usize) -> Result<()> {
+    for buffer in CACHE.get_mut().buffers.iter_mut() {
+        if buffer.disk == disk {
+            buffer.write_back()?;
+        }
+    }
+    Ok(())
+}
+
+pub fn sync_all() -> Result<()> {
+    for buffer in CACHE.get_mut().buffers.iter_mut() {
+        buffer.write_back()?;
+    }
+    Ok(())
+}
+
+pub fn stats() -> Stats {
+    let cache = CACHE.get();
+    Stats {
+        hits: cache.hits,
+        misses: cache.misses,
+        writebacks: cache.writebacks,
+        dirty: cache.buffers.iter().filter(|b| b.valid && b.dirty).count(),
+    }
+}
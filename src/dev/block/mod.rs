@@ -0,0 +1,226 @@
+#![allow(dead_code)]
+
+pub mod cache;
+pub mod partition;
+
+use core::fmt::Write;
+
+use crate::dev::{self, Device};
+use crate::fs::{Error, Result};
+use crate::lazy::LazyMut;
+use crate::DEVICES;
+
+pub const MAX_BLOCKS: usize = 32;
+
+pub trait BlockDevice {
+    fn block_size(&self) -> usize;
+    fn block_count(&self) -> u64;
+    // `buf` holds a whole number of blocks
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<()>;
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<()>;
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+enum Backing {
+    Disk(&'static mut dyn BlockDevice),
+    Partition { disk: usize, start: u64, count: u64 },
+}
+
+struct Block {
+    name: dev::Name,
+    backing: Backing,
+}
+
+static BLOCKS: LazyMut<[Option<Block>; MAX_BLOCKS]> = LazyMut::new();
+static NODES: LazyMut<[Node; MAX_BLOCKS]> = LazyMut::new();
+
+// a registered disk or partition, all accesses go through the buffer cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Handle(usize);
+
+// the /dev entry of a block device
+struct Node(Handle);
+
+pub fn init() -> Result<()> {
+    unsafe {
+        BLOCKS.init([const { None }; MAX_BLOCKS]);
+        NODES.init(core::array::from_fn(|i| Node(Handle(i))));
+    }
+    cache::init()
+}
+
+fn add(name: &str, backing: Backing) -> Result<Handle> {
+    let blocks = BLOCKS.get_mut();
+    let id = blocks
+        .iter()
+        .position(|block| block.is_none())
+        .ok_or(Error::NoSpace)?;
+    blocks[id] = Some(Block {
+        name: dev::Name::from(name),
+        backing,
+    });
+    let node = &mut NODES.get_mut()[id];
+    if let Err(err) = DEVICES.get_mut().register(name, dev::Kind::Block, node) {
+        blocks[id] = None;
+        return Err(err);
+    }
+    Ok(Handle(id))
+}
+
+// registers a whole disk and the partitions found on it
+pub fn register(name: &str, dev: &'static mut dyn BlockDevice) -> Result<Handle> {
+    let disk = add(name, Backing::Disk(dev))?;
+    if let Err(err) = partition::scan(disk) {
+        crate::eprintln!("Could not read partitions of {name}: {err:?}");
+    }
+    Ok(disk)
+}
+
+pub fn register_partition(disk: Handle, index: usize, start: u64, count: u64) -> Result<Handle> {
+    let mut name = dev::Name::from(disk.name());
+    let _ = write!(name, "{index}");
+    if start
+        .checked_add(count)
+        .is_none_or(|end| end > disk.block_count())
+    {
+        return Err(Error::Invalid);
+    }
+    add(
+        &name,
+        Backing::Partition {
+            disk: disk.0,
+            start,
+            count,
+        },
+    )
+}
+
+pub fn find(name: &str) -> Option<Handle> {
+    BLOCKS
+        .get()
+        .iter()
+        .position(|block| block.as_ref().is_some_and(|block| *block.name == *name))
+        .map(Handle)
+}
+
+fn block(id: usize) -> &'static mut Block {
+    match BLOCKS.get_mut()[id].as_mut() {
+        Some(block) => block,
+        None => panic!("Use of unregistered block device {id}"),
+    }
+}
+
+fn disk(id: usize) -> &'static mut dyn BlockDevice {
+    match &mut block(id).backing {
+        Backing::Disk(dev) => *dev,
+        Backing::Partition { disk, .. } => self::disk(*disk),
+    }
+}
+
+impl Handle {
+    pub fn name(&self) -> &'static str {
+        &block(self.0).name
+    }
+
+    pub fn block_size(&self) -> usize {
+        disk(self.0).block_size()
+    }
+
+    pub fn block_count(&self) -> u64 {
+        match block(self.0).backing {
+            Backing::Disk(ref dev) => dev.block_count(),
+            Backing::Partition { count, .. } => count,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.block_count() * self.block_size() as u64
+    }
+
+    // partitions are cached as blocks of the disk they live on
+    fn locate(&self, lba: u64) -> Result<(usize, u64)> {
+        if lba >= self.block_count() {
+            return Err(Error::Invalid);
+        }
+        match block(self.0).backing {
+            Backing::Disk(_) => Ok((self.0, lba)),
+            Backing::Partition { disk, start, .. } => Ok((disk, start + lba)),
+        }
+    }
+
+    pub fn read_block(&self, lba: u64, buf: &mut [u8]) -> Result<()> {
+        let (disk, lba) = self.locate(lba)?;
+        cache::read(disk, lba, 0, buf)
+    }
+
+    pub fn write_block(&self, lba: u64, buf: &[u8]) -> Result<()> {
+        let (disk, lba) = self.locate(lba)?;
+        cache::write(disk, lba, 0, buf)
+    }
+
+    // byte granular access spanning as many blocks as needed
+    pub fn read_bytes(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let size = self.size();
+        if offset >= size {
+            return Ok(0);
+        }
+        let block_size = self.block_size() as u64;
+        let len = buf.len().min((size - offset) as usize);
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let skip = (pos % block_size) as usize;
+            let chunk = (block_size as usize - skip).min(len - done);
+            let (disk, lba) = self.locate(pos / block_size)?;
+            cache::read(disk, lba, skip, &mut buf[done..done + chunk])?;
+            done += chunk;
+        }
+        Ok(len)
+    }
+
+    pub fn write_bytes(&self, offset: u64, buf: &[u8]) -> Result<usize> {
+        let size = self.size();
+        if offset >= size {
+            return Err(Error::NoSpace);
+        }
+        let block_size = self.block_size() as u64;
+        let len = buf.len().min((size - offset) as usize);
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let skip = (pos % block_size) as usize;
+            let chunk = (block_size as usize - skip).min(len - done);
+            let (disk, lba) = self.locate(pos / block_size)?;
+            cache::write(disk, lba, skip, &buf[done..done + chunk])?;
+            done += chunk;
+        }
+        Ok(len)
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        let (disk, _) = self.locate(0)?;
+        cache::sync(disk)?;
+        self::disk(disk).flush()
+    }
+}
+
+impl Device for Node {
+    fn read(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.0.read_bytes(offset, buf)
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) -> Result<usize> {
+        self.0.write_bytes(offset, buf)
+    }
+
+    fn size(&self) -> u64 {
+        self.0.size()
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.0.sync()
+    }
+}
@@ -0,0 +1,102 @@
+use core::fmt::Write;
+
+use crate::dev::block::{self, Handle};
+use crate::fs::{Error, Result};
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const MBR_ENTRIES: usize = 446;
+const MBR_EMPTY: u8 = 0x00;
+const MBR_EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+const MBR_PROTECTIVE: u8 = 0xEE;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_MAX_ENTRIES: u32 = 128;
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+// registers the partitions of `disk` as `<disk name><index>`, starting at 1
+pub fn scan(disk: Handle) -> Result<usize> {
+    let block_size = disk.block_size();
+    if block_size < 512 {
+        return Err(Error::Unsupported);
+    }
+    let mut mbr = [0; 512];
+    disk.read_bytes(0, &mut mbr)?;
+    if mbr[510..512] != MBR_SIGNATURE {
+        return Ok(0);
+    }
+
+    let mut found = 0;
+    for i in 0..4 {
+        let entry = &mbr[MBR_ENTRIES + i * 16..MBR_ENTRIES + (i + 1) * 16];
+        let typ = entry[4];
+        let start = u32_at(entry, 8) as u64;
+        let count = u32_at(entry, 12) as u64;
+        if typ == MBR_PROTECTIVE {
+            return scan_gpt(disk);
+        }
+        // logical partitions inside extended ones are not supported
+        if typ == MBR_EMPTY || MBR_EXTENDED.contains(&typ) || count == 0 {
+            continue;
+        }
+        match block::register_partition(disk, i + 1, start, count) {
+            Ok(_) => found += 1,
+            Err(err) => {
+                crate::eprintln!("Partition {} of {}: {err:?}", i + 1, disk.name());
+            }
+        }
+    }
+    Ok(found)
+}
+
+fn scan_gpt(disk: Handle) -> Result<usize> {
+    let block_size = disk.block_size() as u64;
+    let mut header = [0; 92];
+    disk.read_bytes(block_size, &mut header)?;
+    if &header[0..8] != GPT_SIGNATURE {
+        return Err(Error::Invalid);
+    }
+    let entries_lba = u64_at(&header, 72);
+    let entries = u32_at(&header, 80).min(GPT_MAX_ENTRIES);
+    let entry_size = u32_at(&header, 84) as u64;
+    if entry_size < 128 {
+        return Err(Error::Invalid);
+    }
+
+    let mut found = 0;
+    for i in 0..entries as u64 {
+        // the header is corrupt when the table doesn't fit in 64 bits
+        let offset = entries_lba
+            .checked_mul(block_size)
+            .and_then(|base| base.checked_add(i.checked_mul(entry_size)?))
+            .ok_or(Error::Invalid)?;
+        let mut entry = [0; 128];
+        disk.read_bytes(offset, &mut entry)?;
+        // an all zero type GUID marks an unused entry
+        if entry[0..16].iter().all(|&byte| byte == 0) {
+            continue;
+        }
+        let first = u64_at(&entry, 32);
+        let last = u64_at(&entry, 40);
+        let Some(count) = last
+            .checked_sub(first)
+            .and_then(|count| count.checked_add(1))
+        else {
+            continue;
+        };
+        let index = i as usize + 1;
+        match block::register_partition(disk, index, first, count) {
+            Ok(_) => found += 1,
+            Err(err) => {
+                crate::eprintln!("Partition {index} of {}: {err:?}", disk.name());
+            }
+        }
+    }
+    Ok(found)
+}
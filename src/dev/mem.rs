@@ -0,0 +1,86 @@
+use core::arch::asm;
+
+use crate::dev::{Device, Kind, Registry};
+use crate::fs::Result;
+use crate::lazy::LazyMut;
+
+static NULL: LazyMut<Null> = LazyMut::new();
+static ZERO: LazyMut<Zero> = LazyMut::new();
+static RANDOM: LazyMut<Random> = LazyMut::new();
+
+pub struct Null;
+pub struct Zero;
+
+// xorshift64*, good enough to scramble bytes but NOT cryptographically secure
+pub struct Random {
+    state: u64,
+}
+
+fn rdtsc() -> u64 {
+    let lo: u32;
+    let hi: u32;
+    unsafe { asm!("rdtsc", out("eax") lo, out("edx") hi, options(nomem, nostack)) };
+    (hi as u64) << 32 | lo as u64
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed | 1 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+}
+
+impl Device for Null {
+    fn read(&mut self, _offset: u64, _buf: &mut [u8]) -> Result<usize> {
+        Ok(0)
+    }
+    fn write(&mut self, _offset: u64, buf: &[u8]) -> Result<usize> {
+        Ok(buf.len())
+    }
+}
+
+impl Device for Zero {
+    fn read(&mut self, _offset: u64, buf: &mut [u8]) -> Result<usize> {
+        buf.fill(0);
+        Ok(buf.len())
+    }
+    fn write(&mut self, _offset: u64, buf: &[u8]) -> Result<usize> {
+        Ok(buf.len())
+    }
+}
+
+impl Device for Random {
+    fn read(&mut self, _offset: u64, buf: &mut [u8]) -> Result<usize> {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+        Ok(buf.len())
+    }
+    // writing mixes the bytes into the state like Linux does
+    fn write(&mut self, _offset: u64, buf: &[u8]) -> Result<usize> {
+        for byte in buf {
+            self.state = (self.state ^ *byte as u64).rotate_left(8);
+        }
+        self.state |= 1;
+        Ok(buf.len())
+    }
+}
+
+pub fn init(registry: &mut Registry) -> Result<()> {
+    unsafe {
+        NULL.init(Null);
+        ZERO.init(Zero);
+        RANDOM.init(Random::new(rdtsc()));
+    }
+    registry.register("null", Kind::Char, NULL.get_mut())?;
+    registry.register("zero", Kind::Char, ZERO.get_mut())?;
+    registry.register("random", Kind::Char, RANDOM.get_mut())?;
+    Ok(())
+}
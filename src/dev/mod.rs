@@ -0,0 +1,106 @@
+#![allow(dead_code)]
+
+pub mod ahci;
+pub mod ata;
+pub mod block;
+pub mod mem;
+pub mod pci;
+pub mod virtio;
+
+use crate::fs::{Error, Result};
+use crate::utils::fixed::FixedStr;
+
+pub const MAX_DEVICES: usize = 32;
+
+// ioctl commands shared by the terminal devices
+pub const IOCTL_TTY_SIZE: u32 = 0x5413;
+pub const IOCTL_TTY_CLEAR: u32 = 0x5401;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Char,
+    Block,
+}
+
+pub trait Device {
+    fn read(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize>;
+    fn write(&mut self, offset: u64, buf: &[u8]) -> Result<usize>;
+
+    fn ioctl(&mut self, _cmd: u32, _arg: usize) -> Result<usize> {
+        Err(Error::Unsupported)
+    }
+    fn size(&self) -> u64 {
+        0
+    }
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub type Name = FixedStr<16>;
+
+pub struct Node {
+    pub name: Name,
+    pub kind: Kind,
+    pub dev: &'static mut dyn Device,
+}
+
+pub struct Registry {
+    nodes: [Option<Node>; MAX_DEVICES],
+}
+
+impl Registry {
+    pub const fn new() -> Self {
+        Self {
+            nodes: [const { None }; MAX_DEVICES],
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        name: &str,
+        kind: Kind,
+        dev: &'static mut dyn Device,
+    ) -> Result<usize> {
+        if self.find(name).is_some() {
+            return Err(Error::Exists);
+        }
+        let (id, slot) = self
+            .nodes
+            .iter_mut()
+            .enumerate()
+            .find(|(_, node)| node.is_none())
+            .ok_or(Error::NoSpace)?;
+        *slot = Some(Node {
+            name: Name::from(name),
+            kind,
+            dev,
+        });
+        Ok(id)
+    }
+
+    pub fn unregister(&mut self, id: usize) -> Option<Node> {
+        self.nodes.get_mut(id)?.take()
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|node| node.as_ref().is_some_and(|node| *node.name == *name))
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Node> {
+        self.nodes.get(id)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Node> {
+        self.nodes.get_mut(id)?.as_mut()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Node)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(id, node)| Some((id, node.as_ref()?)))
+    }
+}
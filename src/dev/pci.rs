@@ -0,0 +1,490 @@
+#![allow(dead_code)]
+
+use core::fmt::{self, Write};
+
+use crate::arch::ports::Port;
+use crate::dev::{ahci, virtio};
+use crate::fs::{Error, Result};
+use crate::lazy::LazyMut;
+
+pub const MAX_FUNCTIONS: usize = 64;
+
+const CONFIG_ADDRESS: Port<u32> = Port::new(0xCF8);
+const CONFIG_DATA: Port<u32> = Port::new(0xCFC);
+
+const VENDOR_NONE: u16 = 0xFFFF;
+
+const REG_VENDOR: u8 = 0x00;
+const REG_COMMAND: u8 = 0x04;
+const REG_REVISION: u8 = 0x08;
+const REG_HEADER_TYPE: u8 = 0x0E;
+const REG_BAR0: u8 = 0x10;
+const REG_SECONDARY_BUS: u8 = 0x19;
+const REG_CAPABILITIES: u8 = 0x34;
+const REG_INTERRUPT_LINE: u8 = 0x3C;
+const REG_INTERRUPT_PIN: u8 = 0x3D;
+
+pub const COMMAND_IO: u16 = 1 << 0;
+pub const COMMAND_MEMORY: u16 = 1 << 1;
+pub const COMMAND_BUS_MASTER: u16 = 1 << 2;
+pub const COMMAND_INTX_DISABLE: u16 = 1 << 10;
+
+const HEADER_MULTIFUNCTION: u8 = 0x80;
+const HEADER_GENERAL: u8 = 0x00;
+const HEADER_BRIDGE: u8 = 0x01;
+
+const CLASS_BRIDGE: u8 = 0x06;
+const SUBCLASS_PCI_BRIDGE: u8 = 0x04;
+
+// interrupt line value for devices not routed to the PIC
+const NO_IRQ: u8 = 0xFF;
+
+static FUNCTIONS: LazyMut<Functions> = LazyMut::new();
+
+// drivers are tried in order, the first one matching a function binds to it
+const DRIVERS: &[Driver] = &[
+    Driver {
+        name: "virtio-blk",
+        ids: virtio::blk::IDS,
+        probe: virtio::blk::probe,
+    },
+    Driver {
+        name: "ahci",
+        ids: ahci::IDS,
+        probe: ahci::probe,
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bar {
+    None,
+    Io {
+        port: u16,
+        size: u32,
+    },
+    Memory {
+        addr: u64,
+        size: u64,
+        prefetchable: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Function {
+    pub addr: Address,
+    pub vendor: u16,
+    pub device: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    pub header_type: u8,
+    pub bars: [Bar; 6],
+    pub irq_line: Option<u8>,
+    pub irq_pin: u8,
+    pub driver: Option<&'static str>,
+}
+
+// every field left to `None` matches anything
+#[derive(Debug, Clone, Copy)]
+pub struct Match {
+    pub vendor: Option<u16>,
+    pub device: Option<u16>,
+    pub class: Option<u8>,
+    pub subclass: Option<u8>,
+    pub prog_if: Option<u8>,
+}
+
+pub struct Driver {
+    pub name: &'static str,
+    pub ids: &'static [Match],
+    pub probe: fn(&Function) -> Result<()>,
+}
+
+struct Functions {
+    list: [Option<Function>; MAX_FUNCTIONS],
+    len: usize,
+}
+
+impl Address {
+    pub const fn new(bus: u8, device: u8, function: u8) -> Self {
+        Self {
+            bus,
+            device,
+            function,
+        }
+    }
+
+    // configuration mechanism #1, only aligned dwords can be accessed
+    fn select(&self, offset: u8) {
+        let address = 1 << 31
+            | (self.bus as u32) << 16
+            | (self.device as u32) << 11
+            | (self.function as u32) << 8
+            | (offset & 0xFC) as u32;
+        CONFIG_ADDRESS.write(address);
+    }
+
+    pub fn read_u32(&self, offset: u8) -> u32 {
+        self.select(offset);
+        CONFIG_DATA.read()
+    }
+
+    pub fn read_u16(&self, offset: u8) -> u16 {
+        (self.read_u32(offset) >> ((offset & 2) * 8)) as u16
+    }
+
+    pub fn read_u8(&self, offset: u8) -> u8 {
+        (self.read_u32(offset) >> ((offset & 3) * 8)) as u8
+    }
+
+    pub fn write_u32(&self, offset: u8, val: u32) {
+        self.select(offset);
+        CONFIG_DATA.write(val);
+    }
+
+    pub fn write_u16(&self, offset: u8, val: u16) {
+        let shift = (offset & 2) * 8;
+        let old = self.read_u32(offset) & !(0xFFFF << shift);
+        self.write_u32(offset, old | (val as u32) << shift);
+    }
+
+    pub fn write_u8(&self, offset: u8, val: u8) {
+        let shift = (offset & 3) * 8;
+        let old = self.read_u32(offset) & !(0xFF << shift);
+        self.write_u32(offset, old | (val as u32) << shift);
+    }
+
+    fn vendor(&self) -> u16 {
+        self.read_u16(REG_VENDOR)
+    }
+
+    fn header_type(&self) -> u8 {
+        self.read_u8(REG_HEADER_TYPE)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02x}:{:02x}.{}", self.bus, self.device, self.function)
+    }
+}
+
+impl Bar {
+    pub fn io_port(&self) -> Option<u16> {
+        match *self {
+            Bar::Io { port, .. } => Some(port),
+            _ => None,
+        }
+    }
+
+    pub fn memory(&self) -> Option<(u64, u64)> {
+        match *self {
+            Bar::Memory { addr, size, .. } => Some((addr, size)),
+            _ => None,
+        }
+    }
+}
+
+// sizes are probed by writing all ones and reading back the writable bits,
+// decoding is turned off meanwhile so the device doesn't claim bogus ranges
+fn read_bars(addr: Address, count: usize) -> [Bar; 6] {
+    let mut bars = [Bar::None; 6];
+    let command = addr.read_u16(REG_COMMAND);
+    addr.write_u16(REG_COMMAND, command & !(COMMAND_IO | COMMAND_MEMORY));
+
+    let mut i = 0;
+    while i < count {
+        let offset = REG_BAR0 + i as u8 * 4;
+        let low = addr.read_u32(offset);
+        addr.write_u32(offset, u32::MAX);
+        let low_mask = addr.read_u32(offset);
+        addr.write_u32(offset, low);
+
+        if low & 1 != 0 {
+            let mask = low_mask & 0xFFFC;
+            if mask != 0 {
+                bars[i] = Bar::Io {
+                    port: (low & 0xFFFC) as u16,
+                    size: (!mask & 0xFFFF) + 1,
+                };
+            }
+            i += 1;
+            continue;
+        }
+
+        // 64 bit bars take the next slot for their upper half
+        let wide = (low >> 1) & 3 == 2 && i + 1 < count;
+        let (high, high_mask) = if wide {
+            let high = addr.read_u32(offset + 4);
+            addr.write_u32(offset + 4, u32::MAX);
+            let high_mask = addr.read_u32(offset + 4);
+            addr.write_u32(offset + 4, high);
+            (high, high_mask)
+        } else {
+            (0, u32::MAX)
+        };
+        let mask = (high_mask as u64) << 32 | (low_mask & !0xF) as u64;
+        if low_mask & !0xF != 0 {
+            bars[i] = Bar::Memory {
+                addr: (high as u64) << 32 | (low & !0xF) as u64,
+                size: (!mask).wrapping_add(1),
+                prefetchable: low & 0x8 != 0,
+            };
+        }
+        i += if wide { 2 } else { 1 };
+    }
+
+    addr.write_u16(REG_COMMAND, command);
+    bars
+}
+
+impl Function {
+    fn read(addr: Address) -> Self {
+        let class = addr.read_u32(REG_REVISION);
+        let header_type = addr.header_type() & !HEADER_MULTIFUNCTION;
+        let bars = match header_type {
+            HEADER_GENERAL => 6,
+            HEADER_BRIDGE => 2,
+            _ => 0,
+        };
+        let irq_line = addr.read_u8(REG_INTERRUPT_LINE);
+        let irq_pin = addr.read_u8(REG_INTERRUPT_PIN);
+        Self {
+            addr,
+            vendor: addr.vendor(),
+            device: addr.read_u16(REG_VENDOR + 2),
+            class: (class >> 24) as u8,
+            subclass: (class >> 16) as u8,
+            prog_if: (class >> 8) as u8,
+            revision: class as u8,
+            header_type,
+            bars: read_bars(addr, bars),
+            irq_line: (irq_pin != 0 && irq_line < 16 && irq_line != NO_IRQ).then_some(irq_line),
+            irq_pin,
+            driver: None,
+        }
+    }
+
+    pub fn command(&self) -> u16 {
+        self.addr.read_u16(REG_COMMAND)
+    }
+
+    pub fn set_command(&self, bits: u16, enabled: bool) {
+        let command = self.command();
+        let command = if enabled {
+            command | bits
+        } else {
+            command & !bits
+        };
+        self.addr.write_u16(REG_COMMAND, command);
+    }
+
+    // offset of the first capability with the given id
+    pub fn capability(&self, id: u8) -> Option<u8> {
+        let status = self.addr.read_u16(REG_COMMAND + 2);
+        if status & (1 << 4) == 0 {
+            return None;
+        }
+        let mut offset = self.addr.read_u8(REG_CAPABILITIES) & 0xFC;
+        // bounded in case of a malformed list
+        for _ in 0..48 {
+            if offset == 0 {
+                return None;
+            }
+            if self.addr.read_u8(offset) == id {
+                return Some(offset);
+            }
+            offset = self.addr.read_u8(offset + 1) & 0xFC;
+        }
+        None
+    }
+
+    pub fn is_bridge(&self) -> bool {
+        self.class == CLASS_BRIDGE && self.subclass == SUBCLASS_PCI_BRIDGE
+    }
+
+    pub fn class_name(&self) -> &'static str {
+        match (self.class, self.subclass) {
+            (0x01, 0x01) => "IDE controller",
+            (0x01, 0x06) => "SATA controller",
+            (0x01, 0x08) => "NVMe controller",
+            (0x01, _) => "storage controller",
+            (0x02, _) => "network controller",
+            (0x03, _) => "display controller",
+            (0x04, _) => "multimedia controller",
+            (0x06, 0x00) => "host bridge",
+            (0x06, 0x01) => "ISA bridge",
+            (0x06, 0x04) => "PCI bridge",
+            (0x06, _) => "bridge",
+            (0x0C, 0x03) => "USB controller",
+            (0x0C, 0x05) => "SMBus controller",
+            _ => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {:04x}:{:04x} [{:02x}{:02x}{:02x}] {}",
+            self.addr,
+            self.vendor,
+            self.device,
+            self.class,
+            self.subclass,
+            self.prog_if,
+            self.class_name()
+        )?;
+        if let Some(irq) = self.irq_line {
+            write!(f, " irq {irq}")?;
+        }
+        if let Some(driver) = self.driver {
+            write!(f, " ({driver})")?;
+        }
+        Ok(())
+    }
+}
+
+impl Match {
+    pub const ANY: Self = Self {
+        vendor: None,
+        device: None,
+        class: None,
+        subclass: None,
+        prog_if: None,
+    };
+
+    pub const fn id(vendor: u16, device: u16) -> Self {
+        Self {
+            vendor: Some(vendor),
+            device: Some(device),
+            ..Self::ANY
+        }
+    }
+
+    pub const fn class(class: u8, subclass: u8) -> Self {
+        Self {
+            class: Some(class),
+            subclass: Some(subclass),
+            ..Self::ANY
+        }
+    }
+
+    pub fn matches(&self, function: &Function) -> bool {
+        self.vendor.is_none_or(|vendor| vendor == function.vendor)
+            && self.device.is_none_or(|device| device == function.device)
+            && self.class.is_none_or(|class| class == function.class)
+            && self
+                .subclass
+                .is_none_or(|subclass| subclass == function.subclass)
+            && self
+                .prog_if
+                .is_none_or(|prog_if| prog_if == function.prog_if)
+    }
+}
+
+impl Functions {
+    fn add(&mut self, function: Function) {
+        if self.len == MAX_FUNCTIONS {
+            crate::eprintln!("PCI: too many functions, {} ignored", function.addr);
+            return;
+        }
+        self.list[self.len] = Some(function);
+        self.len += 1;
+    }
+
+    fn scan_bus(&mut self, bus: u8) {
+        for device in 0..32 {
+            self.scan_device(bus, device);
+        }
+    }
+
+    fn scan_device(&mut self, bus: u8, device: u8) {
+        let addr = Address::new(bus, device, 0);
+        if addr.vendor() == VENDOR_NONE {
+            return;
+        }
+        let functions = if addr.header_type() & HEADER_MULTIFUNCTION != 0 {
+            8
+        } else {
+            1
+        };
+        for function in 0..functions {
+            let addr = Address::new(bus, device, function);
+            if addr.vendor() == VENDOR_NONE {
+                continue;
+            }
+            let function = Function::read(addr);
+            self.add(function);
+            // buses behind a bridge are numbered after it, which bounds the recursion
+            if function.is_bridge() {
+                let secondary = addr.read_u8(REG_SECONDARY_BUS);
+                if secondary > bus {
+                    self.scan_bus(secondary);
+                }
+            }
+        }
+    }
+}
+
+// each function of a multifunction host bridge is the controller of one bus
+pub fn init() {
+    unsafe {
+        FUNCTIONS.init(Functions {
+            list: [None; MAX_FUNCTIONS],
+            len: 0,
+        })
+    };
+    let functions = FUNCTIONS.get_mut();
+    let host = Address::new(0, 0, 0);
+    if host.header_type() & HEADER_MULTIFUNCTION == 0 {
+        functions.scan_bus(0);
+        return;
+    }
+    for function in 0..8 {
+        if Address::new(0, 0, function).vendor() != VENDOR_NONE {
+            functions.scan_bus(function);
+        }
+    }
+}
+
+// binds every function to the first driver matching it
+pub fn probe() {
+    for function in FUNCTIONS.get_mut().list.iter_mut().flatten() {
+        let Some(driver) = DRIVERS
+            .iter()
+            .find(|driver| driver.ids.iter().any(|id| id.matches(function)))
+        else {
+            continue;
+        };
+        match (driver.probe)(function) {
+            Ok(()) => function.driver = Some(driver.name),
+            Err(err) => {
+                crate::eprintln!("PCI {}: {} failed: {err:?}", function.addr, driver.name);
+            }
+        }
+    }
+}
+
+pub fn iter() -> impl Iterator<Item = &'static Function> {
+    FUNCTIONS.get().list.iter().flatten()
+}
+
+pub fn find(id: Match) -> Option<&'static Function> {
+    iter().find(|function| id.matches(function))
+}
+
+pub fn get(addr: Address) -> Result<&'static Function> {
+    iter()
+        .find(|function| function.addr == addr)
+        .ok_or(Error::NotFound)
+}
@@ -0,0 +1,288 @@
+use core::fmt::Write;
+
+use crate::arch::{cpu, irq, timer};
+use crate::dev::block::{self, BlockDevice};
+use crate::dev::pci::{Function, Match};
+use crate::dev::virtio::{self, Buffer, Transport, Virtqueue};
+use crate::fs::{Error, Result};
+use crate::lazy::LazyMut;
+use crate::FRAMES;
+
+pub const SECTOR_SIZE: usize = 512;
+pub const MAX_DISKS: usize = 4;
+
+// transitional device id, modern only devices have no legacy interface
+pub const IDS: &[Match] = &[Match::id(virtio::VENDOR, 0x1001)];
+
+const FEATURE_RO: u32 = 1 << 5;
+const FEATURE_FLUSH: u32 = 1 << 9;
+
+const CONFIG_CAPACITY: u16 = 0x00;
+
+const T_IN: u32 = 0;
+const T_OUT: u32 = 1;
+const T_FLUSH: u32 = 4;
+
+const S_OK: u8 = 0;
+const S_IOERR: u8 = 1;
+const S_UNSUPP: u8 = 2;
+// written by us, overwritten by the device on completion
+const S_PENDING: u8 = 0xFF;
+
+const TIMEOUT_MS: u64 = 5000;
+const POLLS: usize = 10_000_000;
+
+static DISKS: LazyMut<[Option<Disk>; MAX_DISKS]> = LazyMut::new();
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct Header {
+    typ: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+// header and status live in a frame of their own so the device can reach them
+#[derive(Debug)]
+#[repr(C)]
+struct Request {
+    header: Header,
+    status: u8,
+}
+
+#[derive(Debug)]
+pub struct Disk {
+    transport: Transport,
+    queue: Virtqueue,
+    irq: Option<u8>,
+    sectors: u64,
+    features: u32,
+    request: *mut Request,
+    done: bool,
+}
+
+impl Disk {
+    fn new(function: &Function) -> Result<Self> {
+        let transport = Transport::new(function)?;
+        transport.reset();
+        transport.add_status(virtio::STATUS_ACKNOWLEDGE);
+        transport.add_status(virtio::STATUS_DRIVER);
+        let features = transport.negotiate(FEATURE_RO | FEATURE_FLUSH);
+        let queue = match transport.setup_queue(0) {
+            Ok(queue) => queue,
+            Err(err) => {
+                transport.add_status(virtio::STATUS_FAILED);
+                return Err(err);
+            }
+        };
+        let request = FRAMES.get_mut().alloc().ok_or(Error::NoSpace)? as *mut Request;
+        Ok(Self {
+            transport,
+            queue,
+            irq: function.irq_line,
+            sectors: transport.config_u64(CONFIG_CAPACITY),
+            features,
+            request,
+            done: false,
+        })
+    }
+
+    fn is_done(&self) -> bool {
+        unsafe { core::ptr::addr_of!(self.done).read_volatile() }
+    }
+
+    // called from the interrupt handler, or polled without an IRQ line
+    fn complete(&mut self) {
+        while self.queue.pop_used().is_some() {
+            unsafe { core::ptr::addr_of_mut!(self.done).write_volatile(true) };
+        }
+    }
+
+    // leaves interrupts as it found them. Without them, as in a page fault,
+    // the queue is polled and ticks don't advance, so POLLS bounds the wait
+    fn wait(&mut self) -> Result<()> {
+        let enabled = cpu::interrupts_enabled();
+        let start = timer::ticks();
+        let timeout = TIMEOUT_MS * timer::HZ as u64 / 1000;
+        let mut polls = 0;
+        let result = loop {
+            cpu::disable_interrupts();
+            if self.irq.is_none() || !enabled {
+                self.complete();
+            }
+            if self.is_done() {
+                break Ok(());
+            }
+            polls += 1;
+            if timer::ticks() - start > timeout || (!enabled && polls > POLLS) {
+                break Err(Error::Io);
+            }
+            // woken up by our interrupt or at worst by the next timer tick
+            if !enabled {
+                core::hint::spin_loop();
+            } else if self.irq.is_some() {
+                cpu::enable_and_halt();
+            } else {
+                cpu::enable_interrupts();
+                core::hint::spin_loop();
+            }
+        };
+        if enabled {
+            cpu::enable_interrupts();
+        }
+        result
+    }
+
+    // a request the device never completed keeps its descriptors and may
+    // still be written to, so the device is reset and starts over with an
+    // empty queue
+    fn restart(&mut self) {
+        cpu::without_interrupts(|| {
+            self.transport.reset();
+            self.transport.add_status(virtio::STATUS_ACKNOWLEDGE);
+            self.transport.add_status(virtio::STATUS_DRIVER);
+            self.transport.negotiate(self.features);
+            self.queue.clear();
+            self.transport.attach_queue(&self.queue);
+            self.transport.add_status(virtio::STATUS_DRIVER_OK);
+        })
+    }
+
+    // one request at a time, the block layer is synchronous
+    fn transfer(&mut self, typ: u32, sector: u64, data: Option<Buffer>) -> Result<()> {
+        let request = unsafe { &mut *self.request };
+        request.header = Header {
+            typ,
+            reserved: 0,
+            sector,
+        };
+        request.status = S_PENDING;
+        let header = Buffer {
+            addr: self.request as usize,
+            len: core::mem::size_of::<Header>(),
+            writable: false,
+        };
+        let status = Buffer {
+            addr: core::ptr::addr_of!(request.status) as usize,
+            len: 1,
+            writable: true,
+        };
+        let mut buffers = [header, status, status];
+        let buffers = match data {
+            Some(data) => {
+                buffers[1] = data;
+                &buffers[..]
+            }
+            None => &buffers[..2],
+        };
+
+        self.done = false;
+        self.queue.submit(buffers)?;
+        self.transport.notify(&self.queue);
+        if let Err(err) = self.wait() {
+            self.restart();
+            return Err(err);
+        }
+        match unsafe { core::ptr::addr_of!(request.status).read_volatile() } {
+            S_OK => Ok(()),
+            S_UNSUPP => Err(Error::Unsupported),
+            // S_IOERR, or S_PENDING if the device didn't touch it
+            _ => Err(Error::Io),
+        }
+    }
+
+    fn check(&self, lba: u64, len: usize) -> Result<()> {
+        if !len.is_multiple_of(SECTOR_SIZE) || len > u32::MAX as usize {
+            return Err(Error::Invalid);
+        }
+        let count = (len / SECTOR_SIZE) as u64;
+        if lba.checked_add(count).is_none_or(|end| end > self.sectors) {
+            return Err(Error::Invalid);
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for Disk {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.sectors
+    }
+
+    // buffers are handed as is to the device, memory is identity mapped
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<()> {
+        self.check(lba, buf.len())?;
+        let data = Buffer {
+            addr: buf.as_mut_ptr() as usize,
+            len: buf.len(),
+            writable: true,
+        };
+        self.transfer(T_IN, lba, Some(data))
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<()> {
+        if self.features & FEATURE_RO != 0 {
+            return Err(Error::ReadOnly);
+        }
+        self.check(lba, buf.len())?;
+        let data = Buffer {
+            addr: buf.as_ptr() as usize,
+            len: buf.len(),
+            writable: false,
+        };
+        self.transfer(T_OUT, lba, Some(data))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.features & FEATURE_FLUSH == 0 {
+            return Ok(());
+        }
+        self.transfer(T_FLUSH, 0, None)
+    }
+}
+
+fn interrupt(irq: u8) {
+    for disk in DISKS.get_mut().iter_mut().flatten() {
+        if disk.irq == Some(irq) && disk.transport.isr() & virtio::ISR_QUEUE != 0 {
+            disk.complete();
+        }
+    }
+}
+
+// registers the disk as `vd<letter>` in probing order
+pub fn probe(function: &Function) -> Result<()> {
+    if !DISKS.is_init() {
+        unsafe { DISKS.init([const { None }; MAX_DISKS]) };
+    }
+    let disks = DISKS.get_mut();
+    let i = disks
+        .iter()
+        .position(|disk| disk.is_none())
+        .ok_or(Error::NoSpace)?;
+    let disk = disks[i].insert(Disk::new(function)?);
+    // polled when the line has too many devices on it already
+    if let Some(irq) = disk.irq {
+        if irq::register(irq, interrupt).is_err() {
+            disk.irq = None;
+        }
+    }
+    disk.transport.add_status(virtio::STATUS_DRIVER_OK);
+    let mode = if disk.features & FEATURE_RO != 0 {
+        "ro"
+    } else {
+        "rw"
+    };
+    crate::eprintln!(
+        "virtio-blk {}: {} sectors {mode}",
+        function.addr,
+        disk.sectors
+    );
+
+    let mut name = crate::dev::Name::from("vd");
+    let _ = name.write_char((b'a' + i as u8) as char);
+    block::register(&name, disk)?;
+    Ok(())
+}
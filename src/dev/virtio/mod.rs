@@ -0,0 +1,268 @@
+#![allow(dead_code)]
+
+pub mod blk;
+
+use core::sync::atomic::{fence, Ordering};
+
+use crate::arch::ports::{Port, ReadOnly, WriteOnly};
+use crate::dev::pci::{self, Function};
+use crate::fs::{Error, Result};
+use crate::mem::frames::FRAME_SIZE;
+use crate::FRAMES;
+
+pub const VENDOR: u16 = 0x1AF4;
+
+pub const STATUS_ACKNOWLEDGE: u8 = 1;
+pub const STATUS_DRIVER: u8 = 2;
+pub const STATUS_DRIVER_OK: u8 = 4;
+pub const STATUS_FAILED: u8 = 128;
+
+pub const ISR_QUEUE: u8 = 1 << 0;
+pub const ISR_CONFIG: u8 = 1 << 1;
+
+const DESC_NEXT: u16 = 1;
+const DESC_WRITE: u16 = 2;
+
+// the legacy interface needs the used ring on its own page
+const QUEUE_ALIGN: usize = FRAME_SIZE;
+
+// registers of the legacy interface, in the first I/O BAR
+#[derive(Debug, Clone, Copy)]
+pub struct Transport {
+    device_features: ReadOnly<u32>,
+    guest_features: Port<u32>,
+    queue_address: Port<u32>,
+    queue_size: ReadOnly<u16>,
+    queue_select: Port<u16>,
+    queue_notify: WriteOnly<u16>,
+    status: Port,
+    isr: ReadOnly,
+    // device specific configuration, MSI-X is never enabled so it starts at 0x14
+    config: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct Desc {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+// a buffer handed to the device, `writable` if the device fills it
+#[derive(Debug, Clone, Copy)]
+pub struct Buffer {
+    pub addr: usize,
+    pub len: usize,
+    pub writable: bool,
+}
+
+// split virtqueue in physically contiguous frames, the memory is identity
+// mapped so the addresses given to the device are the ones we access
+#[derive(Debug)]
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    base: usize,
+    frames: usize,
+    desc: *mut Desc,
+    avail: *mut u16,
+    used: *mut u16,
+    free_head: u16,
+    free: u16,
+    last_used: u16,
+}
+
+impl Transport {
+    pub fn new(function: &Function) -> Result<Self> {
+        let io = function.bars[0].io_port().ok_or(Error::Unsupported)?;
+        function.set_command(pci::COMMAND_IO | pci::COMMAND_BUS_MASTER, true);
+        Ok(Self {
+            device_features: ReadOnly::new(io),
+            guest_features: Port::new(io + 0x04),
+            queue_address: Port::new(io + 0x08),
+            queue_size: ReadOnly::new(io + 0x0C),
+            queue_select: Port::new(io + 0x0E),
+            queue_notify: WriteOnly::new(io + 0x10),
+            status: Port::new(io + 0x12),
+            isr: ReadOnly::new(io + 0x13),
+            config: io + 0x14,
+        })
+    }
+
+    pub fn reset(&self) {
+        self.status.write(0);
+    }
+
+    pub fn add_status(&self, status: u8) {
+        self.status.write(self.status.read() | status);
+    }
+
+    // the device features we also support, acknowledged to the device
+    pub fn negotiate(&self, supported: u32) -> u32 {
+        let features = self.device_features.read() & supported;
+        self.guest_features.write(features);
+        features
+    }
+
+    pub fn setup_queue(&self, index: u16) -> Result<Virtqueue> {
+        self.queue_select.write(index);
+        if self.queue_address.read() != 0 {
+            return Err(Error::Busy);
+        }
+        let size = self.queue_size.read();
+        if size == 0 {
+            return Err(Error::NotFound);
+        }
+        let queue = Virtqueue::new(index, size)?;
+        self.attach_queue(&queue);
+        Ok(queue)
+    }
+
+    // hands the queue memory to the device, again after a reset
+    pub fn attach_queue(&self, queue: &Virtqueue) {
+        self.queue_select.write(queue.index);
+        self.queue_address.write((queue.base / QUEUE_ALIGN) as u32);
+    }
+
+    pub fn notify(&self, queue: &Virtqueue) {
+        // the descriptors must be visible before the device looks at them
+        fence(Ordering::SeqCst);
+        self.queue_notify.write(queue.index);
+    }
+
+    // reading the ISR also acknowledges the interrupt
+    pub fn isr(&self) -> u8 {
+        self.isr.read()
+    }
+
+    pub fn config_u32(&self, offset: u16) -> u32 {
+        Port::<u32>::new(self.config + offset).read()
+    }
+
+    pub fn config_u64(&self, offset: u16) -> u64 {
+        self.config_u32(offset) as u64 | (self.config_u32(offset + 4) as u64) << 32
+    }
+}
+
+impl Virtqueue {
+    fn layout(size: usize) -> (usize, usize) {
+        let desc = size * core::mem::size_of::<Desc>();
+        let avail = 2 * (3 + size);
+        let used = 2 * 3 + size * core::mem::size_of::<UsedElem>();
+        let used_offset = (desc + avail).next_multiple_of(QUEUE_ALIGN);
+        (used_offset, used_offset + used)
+    }
+
+    fn new(index: u16, size: u16) -> Result<Self> {
+        let (used_offset, total) = Self::layout(size as usize);
+        let frames = total.div_ceil(FRAME_SIZE);
+        let base = FRAMES
+            .get_mut()
+            .alloc_contiguous(frames)
+            .ok_or(Error::NoSpace)?;
+        let mut queue = Self {
+            index,
+            size,
+            base,
+            frames,
+            desc: base as *mut Desc,
+            avail: (base + size as usize * core::mem::size_of::<Desc>()) as *mut u16,
+            used: (base + used_offset) as *mut u16,
+            free_head: 0,
+            free: size,
+            last_used: 0,
+        };
+        queue.clear();
+        Ok(queue)
+    }
+
+    // empty rings with every descriptor in the free list, only while the
+    // device doesn't use the queue
+    pub fn clear(&mut self) {
+        unsafe { core::ptr::write_bytes(self.base as *mut u8, 0, self.frames * FRAME_SIZE) };
+        for i in 0..self.size {
+            unsafe { (*self.desc.add(i as usize)).next = i + 1 };
+        }
+        self.free_head = 0;
+        self.free = self.size;
+        self.last_used = 0;
+    }
+
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    // chains the buffers and makes them available, returns the head descriptor
+    pub fn submit(&mut self, buffers: &[Buffer]) -> Result<u16> {
+        if buffers.is_empty() || buffers.len() > self.free as usize {
+            return Err(Error::Busy);
+        }
+        let head = self.free_head;
+        for (i, buffer) in buffers.iter().enumerate() {
+            let id = self.free_head;
+            let desc = unsafe { &mut *self.desc.add(id as usize) };
+            self.free_head = desc.next;
+            let mut flags = if buffer.writable { DESC_WRITE } else { 0 };
+            if i + 1 < buffers.len() {
+                flags |= DESC_NEXT;
+            }
+            desc.addr = buffer.addr as u64;
+            desc.len = buffer.len as u32;
+            desc.flags = flags;
+            if flags & DESC_NEXT != 0 {
+                desc.next = self.free_head;
+            }
+        }
+        self.free -= buffers.len() as u16;
+
+        unsafe {
+            let idx = self.avail.add(1).read_volatile();
+            self.avail
+                .add(2 + (idx % self.size) as usize)
+                .write_volatile(head);
+            fence(Ordering::SeqCst);
+            self.avail.add(1).write_volatile(idx.wrapping_add(1));
+        }
+        Ok(head)
+    }
+
+    // takes the next buffer chain returned by the device with the length written
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let idx = unsafe { self.used.add(1).read_volatile() };
+        if idx == self.last_used {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let elems = unsafe { self.used.add(2) as *const UsedElem };
+        let elem = unsafe {
+            elems
+                .add((self.last_used % self.size) as usize)
+                .read_volatile()
+        };
+        self.last_used = self.last_used.wrapping_add(1);
+
+        // give the chain back to the free list
+        let head = elem.id as u16;
+        let mut id = head;
+        loop {
+            let desc = unsafe { &mut *self.desc.add(id as usize) };
+            self.free += 1;
+            if desc.flags & DESC_NEXT == 0 {
+                desc.next = self.free_head;
+                break;
+            }
+            id = desc.next;
+        }
+        self.free_head = head;
+        Some((head, elem.len))
+    }
+}
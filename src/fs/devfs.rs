@@ -0,0 +1,93 @@
+use crate::dev;
+use crate::fs::{DirEntry, Error, FileSystem, Kind, Name, Result, Stat, Vfs};
+use crate::lazy::LazyMut;
+use crate::DEVICES;
+
+const ROOT: u64 = 0;
+
+static DEVFS: LazyMut<DevFs> = LazyMut::new();
+
+// inode 0 is the directory, inode `id + 1` is the device `id` of the registry
+pub struct DevFs;
+
+fn node(ino: u64) -> Result<&'static mut dev::Node> {
+    let id = ino.checked_sub(1).ok_or(Error::IsDir)?;
+    DEVICES
+        .get_mut()
+        .get_mut(id as usize)
+        .ok_or(Error::NotFound)
+}
+
+fn kind(kind: dev::Kind) -> Kind {
+    match kind {
+        dev::Kind::Char => Kind::CharDevice,
+        dev::Kind::Block => Kind::BlockDevice,
+    }
+}
+
+impl FileSystem for DevFs {
+    fn root(&self) -> u64 {
+        ROOT
+    }
+
+    fn lookup(&mut self, dir: u64, name: &str) -> Result<u64> {
+        if dir != ROOT {
+            return Err(Error::NotDir);
+        }
+        let id = DEVICES.get().find(name).ok_or(Error::NotFound)?;
+        Ok(id as u64 + 1)
+    }
+
+    fn stat(&mut self, ino: u64) -> Result<Stat> {
+        if ino == ROOT {
+            return Ok(Stat {
+                ino,
+                kind: Kind::Dir,
+                size: 0,
+            });
+        }
+        let node = node(ino)?;
+        Ok(Stat {
+            ino,
+            kind: kind(node.kind),
+            size: node.dev.size(),
+        })
+    }
+
+    fn read(&mut self, ino: u64, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        node(ino)?.dev.read(offset, buf)
+    }
+
+    fn write(&mut self, ino: u64, offset: u64, buf: &[u8]) -> Result<usize> {
+        node(ino)?.dev.write(offset, buf)
+    }
+
+    fn ioctl(&mut self, ino: u64, cmd: u32, arg: usize) -> Result<usize> {
+        node(ino)?.dev.ioctl(cmd, arg)
+    }
+
+    fn readdir(&mut self, dir: u64, index: usize) -> Result<Option<DirEntry>> {
+        if dir != ROOT {
+            return Err(Error::NotDir);
+        }
+        Ok(DEVICES.get().iter().nth(index).map(|(id, node)| DirEntry {
+            ino: id as u64 + 1,
+            kind: kind(node.kind),
+            name: Name::from(node.name.as_str()),
+        }))
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        for id in 0..dev::MAX_DEVICES {
+            if let Some(node) = DEVICES.get_mut().get_mut(id) {
+                node.dev.sync()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn mount(vfs: &mut Vfs, path: &str) -> Result<()> {
+    unsafe { DEVFS.init(DevFs) };
+    vfs.mount(path, DEVFS.get_mut())
+}
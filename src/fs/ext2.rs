@@ -0,0 +1,371 @@
+#![allow(dead_code)]
+
+// read-only for now: everything is read straight from the disk through the
+// block cache, so write support only has to add the bitmaps and the inode and
+// block allocation on top of the same accessors
+
+use crate::dev::block::Handle;
+use crate::fs::{DirEntry, Error, FileSystem, Kind, Name, Result, Stat, Vfs};
+use crate::lazy::LazyMut;
+
+pub const MAX_VOLUMES: usize = 4;
+
+const SUPERBLOCK: u64 = 1024;
+const MAGIC: u16 = 0xEF53;
+const ROOT: u64 = 2;
+
+const GOOD_OLD_REV: u32 = 0;
+const GOOD_OLD_INODE_SIZE: u16 = 128;
+
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+const INCOMPAT_FLEX_BG: u32 = 0x0200;
+// other incompatible features change the on disk layout
+const INCOMPAT_SUPPORTED: u32 = INCOMPAT_FILETYPE | INCOMPAT_FLEX_BG;
+const RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+
+const DIRECT_BLOCKS: u64 = 12;
+const INDIRECT: usize = 12;
+const DOUBLE_INDIRECT: usize = 13;
+const TRIPLE_INDIRECT: usize = 14;
+// symlinks shorter than this are stored in place of the block pointers
+const FAST_SYMLINK: u64 = 60;
+
+const MODE_TYPE: u16 = 0xF000;
+const MODE_FIFO: u16 = 0x1000;
+const MODE_CHR: u16 = 0x2000;
+const MODE_DIR: u16 = 0x4000;
+const MODE_BLK: u16 = 0x6000;
+const MODE_REG: u16 = 0x8000;
+const MODE_LNK: u16 = 0xA000;
+const MODE_SOCK: u16 = 0xC000;
+
+static VOLUMES: LazyMut<[Option<Ext2Fs>; MAX_VOLUMES]> = LazyMut::new();
+
+#[derive(Debug)]
+pub struct Ext2Fs {
+    disk: Handle,
+    block_size: u64,
+    inodes: u32,
+    inodes_per_group: u32,
+    inode_size: u64,
+    first_data_block: u64,
+    groups: u32,
+    incompat: u32,
+    ro_compat: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Inode {
+    mode: u16,
+    size: u64,
+    // in 512 bytes units
+    sectors: u32,
+    file_acl: u32,
+    block: [u32; 15],
+}
+
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+impl Inode {
+    fn kind(&self) -> Kind {
+        match self.mode & MODE_TYPE {
+            MODE_DIR => Kind::Dir,
+            MODE_LNK => Kind::Symlink,
+            MODE_CHR => Kind::CharDevice,
+            MODE_BLK => Kind::BlockDevice,
+            // fifos and sockets have no VFS kind and no content on disk
+            _ => Kind::File,
+        }
+    }
+
+    fn is_fast_symlink(&self, block_size: u64) -> bool {
+        let acl = if self.file_acl != 0 {
+            block_size / 512
+        } else {
+            0
+        };
+        self.mode & MODE_TYPE == MODE_LNK && self.size < FAST_SYMLINK && self.sectors as u64 == acl
+    }
+}
+
+impl Ext2Fs {
+    pub fn new(disk: Handle) -> Result<Self> {
+        let mut sb = [0; 1024];
+        disk.read_bytes(SUPERBLOCK, &mut sb)?;
+        if u16_at(&sb, 56) != MAGIC {
+            return Err(Error::Invalid);
+        }
+        let blocks = u32_at(&sb, 4);
+        let first_data_block = u32_at(&sb, 20);
+        let log_block_size = u32_at(&sb, 24);
+        let blocks_per_group = u32_at(&sb, 32);
+        let inodes_per_group = u32_at(&sb, 40);
+        let rev = u32_at(&sb, 76);
+        let (inode_size, incompat, ro_compat) = if rev == GOOD_OLD_REV {
+            (GOOD_OLD_INODE_SIZE, 0, 0)
+        } else {
+            (u16_at(&sb, 88), u32_at(&sb, 96), u32_at(&sb, 100))
+        };
+        if log_block_size > 6
+            || first_data_block >= blocks
+            || blocks_per_group == 0
+            || inodes_per_group == 0
+            || inode_size < GOOD_OLD_INODE_SIZE
+            || !inode_size.is_power_of_two()
+        {
+            return Err(Error::Invalid);
+        }
+        if incompat & !INCOMPAT_SUPPORTED != 0 {
+            return Err(Error::Unsupported);
+        }
+        Ok(Self {
+            disk,
+            block_size: 1024 << log_block_size,
+            inodes: u32_at(&sb, 0),
+            inodes_per_group,
+            inode_size: inode_size as u64,
+            first_data_block: first_data_block as u64,
+            groups: (blocks - first_data_block).div_ceil(blocks_per_group),
+            incompat,
+            ro_compat,
+        })
+    }
+
+    fn read_block(&self, block: u32, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let at = block as u64 * self.block_size + offset;
+        if self.disk.read_bytes(at, buf)? != buf.len() {
+            return Err(Error::Io);
+        }
+        Ok(())
+    }
+
+    // location of the inode in its group inode table
+    fn inode_offset(&self, ino: u64) -> Result<u64> {
+        if ino == 0 || ino > self.inodes as u64 {
+            return Err(Error::NotFound);
+        }
+        let group = (ino - 1) / self.inodes_per_group as u64;
+        let index = (ino - 1) % self.inodes_per_group as u64;
+        if group >= self.groups as u64 {
+            return Err(Error::Invalid);
+        }
+        // the descriptor table starts on the block following the superblock
+        let mut desc = [0; 32];
+        let table = (self.first_data_block + 1) * self.block_size;
+        self.disk.read_bytes(table + group * 32, &mut desc)?;
+        let inode_table = u32_at(&desc, 8) as u64;
+        Ok(inode_table * self.block_size + index * self.inode_size)
+    }
+
+    fn inode(&self, ino: u64) -> Result<Inode> {
+        let mut buf = [0; GOOD_OLD_INODE_SIZE as usize];
+        self.disk.read_bytes(self.inode_offset(ino)?, &mut buf)?;
+        let mode = u16_at(&buf, 0);
+        let mut size = u32_at(&buf, 4) as u64;
+        // the directory ACL field holds the high size bits of large files
+        if mode & MODE_TYPE == MODE_REG && self.ro_compat & RO_COMPAT_LARGE_FILE != 0 {
+            size |= (u32_at(&buf, 108) as u64) << 32;
+        }
+        let mut block = [0; 15];
+        for (i, block) in block.iter_mut().enumerate() {
+            *block = u32_at(&buf, 40 + i * 4);
+        }
+        Ok(Inode {
+            mode,
+            size,
+            sectors: u32_at(&buf, 28),
+            file_acl: u32_at(&buf, 104),
+            block,
+        })
+    }
+
+    fn indirect(&self, block: u32, index: u64) -> Result<u32> {
+        if block == 0 {
+            return Ok(0);
+        }
+        let mut buf = [0; 4];
+        self.read_block(block, index * 4, &mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    // disk block holding the block `n` of the inode, 0 for a hole
+    fn bmap(&self, inode: &Inode, n: u64) -> Result<u32> {
+        let per_block = self.block_size / 4;
+        if n < DIRECT_BLOCKS {
+            return Ok(inode.block[n as usize]);
+        }
+        let n = n - DIRECT_BLOCKS;
+        if n < per_block {
+            return self.indirect(inode.block[INDIRECT], n);
+        }
+        let n = n - per_block;
+        if n < per_block * per_block {
+            let block = self.indirect(inode.block[DOUBLE_INDIRECT], n / per_block)?;
+            return self.indirect(block, n % per_block);
+        }
+        let n = n - per_block * per_block;
+        if n < per_block * per_block * per_block {
+            let block = self.indirect(inode.block[TRIPLE_INDIRECT], n / (per_block * per_block))?;
+            let block = self.indirect(block, n / per_block % per_block)?;
+            return self.indirect(block, n % per_block);
+        }
+        Err(Error::Invalid)
+    }
+
+    fn read_inode(&self, inode: &Inode, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        if offset >= inode.size {
+            return Ok(0);
+        }
+        let len = buf.len().min((inode.size - offset) as usize);
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let skip = pos % self.block_size;
+            let chunk = ((self.block_size - skip) as usize).min(len - done);
+            match self.bmap(inode, pos / self.block_size)? {
+                0 => buf[done..done + chunk].fill(0),
+                block => self.read_block(block, skip, &mut buf[done..done + chunk])?,
+            }
+            done += chunk;
+        }
+        Ok(len)
+    }
+
+    // walks the entries of a directory until `f` accepts one
+    fn scan(
+        &self,
+        dir: u64,
+        mut f: impl FnMut(u64, Option<Kind>, &str) -> bool,
+    ) -> Result<Option<(u64, Option<Kind>, Name)>> {
+        let inode = self.inode(dir)?;
+        if inode.kind() != Kind::Dir {
+            return Err(Error::NotDir);
+        }
+        let mut offset = 0;
+        while offset < inode.size {
+            let mut header = [0; 8];
+            if self.read_inode(&inode, offset, &mut header)? < header.len() {
+                return Err(Error::Io);
+            }
+            let ino = u32_at(&header, 0) as u64;
+            let rec_len = u16_at(&header, 4) as u64;
+            if rec_len < 8 {
+                return Err(Error::Io);
+            }
+            // without the filetype feature the name length is 16 bits wide
+            let (name_len, kind) = if self.incompat & INCOMPAT_FILETYPE != 0 {
+                let kind = match header[7] {
+                    1 => Some(Kind::File),
+                    2 => Some(Kind::Dir),
+                    3 => Some(Kind::CharDevice),
+                    4 => Some(Kind::BlockDevice),
+                    7 => Some(Kind::Symlink),
+                    _ => None,
+                };
+                (header[6] as usize, kind)
+            } else {
+                (u16_at(&header, 6) as usize, None)
+            };
+
+            let mut buf = [0; 255];
+            let name_len = name_len.min(buf.len());
+            self.read_inode(&inode, offset + 8, &mut buf[..name_len])?;
+            offset += rec_len;
+            let Ok(name) = core::str::from_utf8(&buf[..name_len]) else {
+                continue;
+            };
+            if ino == 0 || name == "." || name == ".." {
+                continue;
+            }
+            if f(ino, kind, name) {
+                return Ok(Some((ino, kind, Name::from(name))));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl FileSystem for Ext2Fs {
+    fn root(&self) -> u64 {
+        ROOT
+    }
+
+    fn lookup(&mut self, dir: u64, name: &str) -> Result<u64> {
+        match self.scan(dir, |_, _, entry| entry == name)? {
+            Some((ino, _, _)) => Ok(ino),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    fn stat(&mut self, ino: u64) -> Result<Stat> {
+        let inode = self.inode(ino)?;
+        Ok(Stat {
+            ino,
+            kind: inode.kind(),
+            size: inode.size,
+        })
+    }
+
+    fn read(&mut self, ino: u64, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let inode = self.inode(ino)?;
+        match inode.kind() {
+            Kind::File => self.read_inode(&inode, offset, buf),
+            Kind::Dir => Err(Error::IsDir),
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    fn readdir(&mut self, dir: u64, index: usize) -> Result<Option<DirEntry>> {
+        let mut i = 0;
+        let Some((ino, kind, name)) = self.scan(dir, |_, _, _| {
+            i += 1;
+            i > index
+        })?
+        else {
+            return Ok(None);
+        };
+        let kind = match kind {
+            Some(kind) => kind,
+            None => self.inode(ino)?.kind(),
+        };
+        Ok(Some(DirEntry { ino, kind, name }))
+    }
+
+    fn readlink(&mut self, ino: u64, buf: &mut [u8]) -> Result<usize> {
+        let inode = self.inode(ino)?;
+        if inode.kind() != Kind::Symlink {
+            return Err(Error::Invalid);
+        }
+        if !inode.is_fast_symlink(self.block_size) {
+            return self.read_inode(&inode, 0, buf);
+        }
+        let len = buf.len().min(inode.size as usize);
+        for (i, byte) in buf[..len].iter_mut().enumerate() {
+            *byte = inode.block[i / 4].to_le_bytes()[i % 4];
+        }
+        Ok(len)
+    }
+}
+
+pub fn mount(vfs: &mut Vfs, path: &str, disk: Handle) -> Result<()> {
+    if !VOLUMES.is_init() {
+        unsafe { VOLUMES.init([const { None }; MAX_VOLUMES]) };
+    }
+    let fs = Ext2Fs::new(disk)?;
+    let volumes = VOLUMES.get_mut();
+    let i = volumes
+        .iter()
+        .position(|volume| volume.is_none())
+        .ok_or(Error::NoSpace)?;
+    if let Err(err) = vfs.mount(path, volumes[i].insert(fs)) {
+        VOLUMES.get_mut()[i] = None;
+        return Err(err);
+    }
+    Ok(())
+}
@@ -0,0 +1,916 @@
+#![allow(dead_code)]
+
+use crate::dev::block::Handle;
+use crate::fs::{DirEntry, Error, FileSystem, Kind, Name, Result, Stat, Vfs};
+use crate::lazy::LazyMut;
+
+pub const MAX_VOLUMES: usize = 4;
+
+// the boot sector can't hold a directory entry, offset 0 is free for the root
+const ROOT: u64 = 0;
+const ENTRY_SIZE: u64 = 32;
+
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_HIDDEN: u8 = 0x02;
+const ATTR_SYSTEM: u8 = 0x04;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LFN: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+const END: u8 = 0x00;
+const DELETED: u8 = 0xE5;
+// a short name really starting with 0xE5 is stored with 0x05
+const KANJI_E5: u8 = 0x05;
+
+const NTRES_LOWER_BASE: u8 = 0x08;
+const NTRES_LOWER_EXT: u8 = 0x10;
+
+const LFN_LAST: u8 = 0x40;
+const LFN_CHARS: usize = 13;
+const LFN_MAX_ENTRIES: usize = 20;
+// offsets of the 13 UTF-16 characters inside a long name entry
+const LFN_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+const INVALID_CHARS: &[char] = &['"', '*', '/', ':', '<', '>', '?', '\\', '|'];
+
+static VOLUMES: LazyMut<[Option<FatFs>; MAX_VOLUMES]> = LazyMut::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+#[derive(Debug)]
+pub struct FatFs {
+    disk: Handle,
+    typ: FatType,
+    cluster_size: u64,
+    fat_start: u64,
+    fat_size: u64,
+    fats: u64,
+    root_start: u64,
+    root_entries: u64,
+    data_start: u64,
+    // valid cluster numbers are 2..clusters + 2
+    clusters: u32,
+    root_cluster: u32,
+    next_free: u32,
+}
+
+// the FAT12/16 root directory is a fixed region before the data clusters
+#[derive(Debug, Clone, Copy)]
+enum Dir {
+    Root,
+    Cluster(u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Raw {
+    name: [u8; 11],
+    attr: u8,
+    ntres: u8,
+    cluster: u32,
+    size: u32,
+}
+
+struct Found {
+    // offset of the short entry, used as inode number
+    offset: u64,
+    // offset of the first long name entry, or of the short entry without one
+    first: u64,
+    raw: Raw,
+    name: Name,
+}
+
+struct Slots {
+    dir: Dir,
+    cluster: u32,
+    index: u64,
+    // clusters of the chain walked so far, a chain can't be longer than the
+    // volume so more means a corrupt FAT loops
+    walked: u32,
+}
+
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+impl Raw {
+    fn parse(buf: &[u8; 32]) -> Self {
+        let mut name = [0; 11];
+        name.copy_from_slice(&buf[0..11]);
+        Self {
+            name,
+            attr: buf[11],
+            ntres: buf[12],
+            cluster: (u16_at(buf, 20) as u32) << 16 | u16_at(buf, 26) as u32,
+            size: u32_at(buf, 28),
+        }
+    }
+
+    // timestamps are kept as they are, there is no clock to fill them
+    fn encode(&self, buf: &mut [u8; 32]) {
+        buf[0..11].copy_from_slice(&self.name);
+        buf[11] = self.attr;
+        buf[12] = self.ntres;
+        buf[20..22].copy_from_slice(&((self.cluster >> 16) as u16).to_le_bytes());
+        buf[26..28].copy_from_slice(&(self.cluster as u16).to_le_bytes());
+        buf[28..32].copy_from_slice(&self.size.to_le_bytes());
+    }
+
+    fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
+    fn short_name(&self) -> Name {
+        let mut name = Name::new();
+        let push = |name: &mut Name, bytes: &[u8], lower: bool| {
+            for (i, &byte) in bytes.iter().enumerate() {
+                let byte = if i == 0 && byte == KANJI_E5 {
+                    0xE5
+                } else {
+                    byte
+                };
+                let c = match byte {
+                    b' ' => continue,
+                    0x21..=0x7E if lower => byte.to_ascii_lowercase() as char,
+                    0x21..=0x7E => byte as char,
+                    _ => '?',
+                };
+                name.push_str(c.encode_utf8(&mut [0; 4]));
+            }
+        };
+        push(
+            &mut name,
+            &self.name[0..8],
+            self.ntres & NTRES_LOWER_BASE != 0,
+        );
+        if self.name[8..11] != *b"   " {
+            name.push_str(".");
+            push(
+                &mut name,
+                &self.name[8..11],
+                self.ntres & NTRES_LOWER_EXT != 0,
+            );
+        }
+        name
+    }
+}
+
+fn checksum(name: &[u8; 11]) -> u8 {
+    name.iter()
+        .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c))
+}
+
+// the name as is if it is a valid upper case 8.3 name
+fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    let valid =
+        |c: u8| c.is_ascii_uppercase() || c.is_ascii_digit() || b"!#$%&'()-@^_`{}~".contains(&c);
+    if base.is_empty()
+        || base.len() > 8
+        || ext.len() > 3
+        || (name.contains('.') && ext.is_empty())
+        || !base.bytes().chain(ext.bytes()).all(valid)
+    {
+        return None;
+    }
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    Some(short)
+}
+
+// upper case ASCII with anything unrepresentable replaced by '_'
+fn clean_short(part: &str, out: &mut [u8]) -> usize {
+    let mut len = 0;
+    for c in part.chars().filter(|&c| c != ' ' && c != '.') {
+        if len == out.len() {
+            break;
+        }
+        out[len] = match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase() as u8,
+            '!' | '#' | '$' | '%' | '&' | '\'' | '(' | ')' | '-' | '@' | '^' | '_' | '`' | '{'
+            | '}' | '~' => c as u8,
+            _ => b'_',
+        };
+        len += 1;
+    }
+    len
+}
+
+impl FatFs {
+    pub fn new(disk: Handle) -> Result<Self> {
+        let mut boot = [0; 512];
+        disk.read_bytes(0, &mut boot)?;
+        if boot[510..512] != [0x55, 0xAA] {
+            return Err(Error::Invalid);
+        }
+        let bytes_per_sector = u16_at(&boot, 11) as u64;
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved = u16_at(&boot, 14) as u64;
+        let fats = boot[16] as u64;
+        let root_entries = u16_at(&boot, 17) as u64;
+        let total = match u16_at(&boot, 19) {
+            0 => u32_at(&boot, 32) as u64,
+            total => total as u64,
+        };
+        let fat_sectors = match u16_at(&boot, 22) {
+            0 => u32_at(&boot, 36) as u64,
+            size => size as u64,
+        };
+        if !bytes_per_sector.is_power_of_two()
+            || !(512..=4096).contains(&bytes_per_sector)
+            || !sectors_per_cluster.is_power_of_two()
+            || fats == 0
+            || reserved == 0
+        {
+            return Err(Error::Invalid);
+        }
+
+        let root_sectors = (root_entries * ENTRY_SIZE).div_ceil(bytes_per_sector);
+        let meta = reserved + fats * fat_sectors + root_sectors;
+        let clusters = total.checked_sub(meta).ok_or(Error::Invalid)? / sectors_per_cluster;
+        let typ = match clusters {
+            0..4085 => FatType::Fat12,
+            4085..65525 => FatType::Fat16,
+            _ => FatType::Fat32,
+        };
+        let fs = Self {
+            disk,
+            typ,
+            cluster_size: sectors_per_cluster * bytes_per_sector,
+            fat_start: reserved * bytes_per_sector,
+            fat_size: fat_sectors * bytes_per_sector,
+            fats,
+            root_start: (reserved + fats * fat_sectors) * bytes_per_sector,
+            root_entries,
+            data_start: meta * bytes_per_sector,
+            clusters: clusters as u32,
+            root_cluster: if typ == FatType::Fat32 {
+                u32_at(&boot, 44)
+            } else {
+                0
+            },
+            next_free: 2,
+        };
+
+        // the free cluster count would go stale, mark it as unknown
+        if typ == FatType::Fat32 {
+            let fsinfo = u16_at(&boot, 48) as u64 * bytes_per_sector;
+            let mut signatures = [0; 4];
+            disk.read_bytes(fsinfo, &mut signatures)?;
+            if u32::from_le_bytes(signatures) == 0x41615252 {
+                disk.write_bytes(fsinfo + 488, &[0xFF; 8])?;
+            }
+        }
+        Ok(fs)
+    }
+
+    pub fn typ(&self) -> FatType {
+        self.typ
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.data_start + (cluster as u64 - 2) * self.cluster_size
+    }
+
+    fn is_valid(&self, cluster: u32) -> bool {
+        (2..self.clusters + 2).contains(&cluster)
+    }
+
+    fn fat_get(&self, cluster: u32) -> Result<u32> {
+        let mut buf = [0; 4];
+        match self.typ {
+            FatType::Fat12 => {
+                let offset = cluster as u64 + cluster as u64 / 2;
+                self.disk
+                    .read_bytes(self.fat_start + offset, &mut buf[..2])?;
+                let val = u16_at(&buf, 0) as u32;
+                Ok(if cluster & 1 != 0 {
+                    val >> 4
+                } else {
+                    val & 0xFFF
+                })
+            }
+            FatType::Fat16 => {
+                let offset = cluster as u64 * 2;
+                self.disk
+                    .read_bytes(self.fat_start + offset, &mut buf[..2])?;
+                Ok(u16_at(&buf, 0) as u32)
+            }
+            FatType::Fat32 => {
+                let offset = cluster as u64 * 4;
+                self.disk.read_bytes(self.fat_start + offset, &mut buf)?;
+                Ok(u32::from_le_bytes(buf) & 0x0FFFFFFF)
+            }
+        }
+    }
+
+    // every copy of the FAT is kept in sync
+    fn fat_set(&self, cluster: u32, val: u32) -> Result<()> {
+        for fat in 0..self.fats {
+            let start = self.fat_start + fat * self.fat_size;
+            match self.typ {
+                FatType::Fat12 => {
+                    let offset = start + cluster as u64 + cluster as u64 / 2;
+                    let mut buf = [0; 2];
+                    self.disk.read_bytes(offset, &mut buf)?;
+                    let old = u16::from_le_bytes(buf);
+                    let val = if cluster & 1 != 0 {
+                        (old & 0x000F) | (val as u16) << 4
+                    } else {
+                        (old & 0xF000) | (val as u16 & 0x0FFF)
+                    };
+                    self.disk.write_bytes(offset, &val.to_le_bytes())?;
+                }
+                FatType::Fat16 => {
+                    let offset = start + cluster as u64 * 2;
+                    self.disk.write_bytes(offset, &(val as u16).to_le_bytes())?;
+                }
+                FatType::Fat32 => {
+                    // the 4 upper bits are reserved and must be preserved
+                    let offset = start + cluster as u64 * 4;
+                    let mut buf = [0; 4];
+                    self.disk.read_bytes(offset, &mut buf)?;
+                    let val = (u32::from_le_bytes(buf) & 0xF0000000) | (val & 0x0FFFFFFF);
+                    self.disk.write_bytes(offset, &val.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn end_of_chain(&self) -> u32 {
+        match self.typ {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFFFFFF,
+        }
+    }
+
+    fn next(&self, cluster: u32) -> Result<Option<u32>> {
+        let next = self.fat_get(cluster)?;
+        Ok(self.is_valid(next).then_some(next))
+    }
+
+    fn zero_cluster(&self, cluster: u32) -> Result<()> {
+        let zeros = [0; 512];
+        let offset = self.cluster_offset(cluster);
+        let mut done = 0;
+        while done < self.cluster_size {
+            let len = (self.cluster_size - done).min(zeros.len() as u64) as usize;
+            self.disk.write_bytes(offset + done, &zeros[..len])?;
+            done += len as u64;
+        }
+        Ok(())
+    }
+
+    // allocates a zeroed cluster and links it after `prev`
+    fn alloc_cluster(&mut self, prev: Option<u32>) -> Result<u32> {
+        for i in 0..self.clusters {
+            let cluster = 2 + (self.next_free - 2 + i) % self.clusters;
+            if self.fat_get(cluster)? != 0 {
+                continue;
+            }
+            self.fat_set(cluster, self.end_of_chain())?;
+            if let Some(prev) = prev {
+                self.fat_set(prev, cluster)?;
+            }
+            self.zero_cluster(cluster)?;
+            self.next_free = cluster;
+            return Ok(cluster);
+        }
+        Err(Error::NoSpace)
+    }
+
+    fn free_chain(&mut self, first: u32) -> Result<()> {
+        let mut cluster = Some(first).filter(|&c| self.is_valid(c));
+        while let Some(current) = cluster {
+            cluster = self.next(current)?;
+            self.fat_set(current, 0)?;
+        }
+        Ok(())
+    }
+
+    fn read_slot(&self, offset: u64) -> Result<[u8; 32]> {
+        let mut buf = [0; 32];
+        self.disk.read_bytes(offset, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn write_slot(&self, offset: u64, buf: &[u8; 32]) -> Result<()> {
+        self.disk.write_bytes(offset, buf)?;
+        Ok(())
+    }
+
+    fn entry(&self, ino: u64) -> Result<Raw> {
+        Ok(Raw::parse(&self.read_slot(ino)?))
+    }
+
+    fn dir(&self, ino: u64) -> Result<Dir> {
+        if ino == ROOT {
+            return Ok(match self.typ {
+                FatType::Fat32 => Dir::Cluster(self.root_cluster),
+                _ => Dir::Root,
+            });
+        }
+        let raw = self.entry(ino)?;
+        if !raw.is_dir() {
+            return Err(Error::NotDir);
+        }
+        Ok(Dir::Cluster(raw.cluster))
+    }
+
+    fn slots(&self, dir: Dir) -> Slots {
+        Slots {
+            dir,
+            cluster: match dir {
+                Dir::Root => 0,
+                Dir::Cluster(cluster) => cluster,
+            },
+            index: 0,
+            walked: 0,
+        }
+    }
+
+    // offset of the next 32 bytes entry of the directory
+    fn next_slot(&self, slots: &mut Slots) -> Result<Option<u64>> {
+        match slots.dir {
+            Dir::Root => {
+                if slots.index >= self.root_entries {
+                    return Ok(None);
+                }
+            }
+            Dir::Cluster(_) => {
+                if slots.index == self.cluster_size / ENTRY_SIZE {
+                    slots.walked += 1;
+                    if slots.walked >= self.clusters {
+                        return Err(Error::Io);
+                    }
+                    match self.next(slots.cluster)? {
+                        Some(cluster) => {
+                            slots.cluster = cluster;
+                            slots.index = 0;
+                        }
+                        None => return Ok(None),
+                    }
+                }
+                if !self.is_valid(slots.cluster) {
+                    return Ok(None);
+                }
+            }
+        }
+        let start = match slots.dir {
+            Dir::Root => self.root_start,
+            Dir::Cluster(_) => self.cluster_offset(slots.cluster),
+        };
+        let offset = start + slots.index * ENTRY_SIZE;
+        slots.index += 1;
+        Ok(Some(offset))
+    }
+
+    // walks the directory assembling long names, returns the first entry accepted by `f`
+    fn scan(&self, dir: Dir, mut f: impl FnMut(&Found) -> bool) -> Result<Option<Found>> {
+        let mut slots = self.slots(dir);
+        let mut units = [0xFFFFu16; LFN_MAX_ENTRIES * LFN_CHARS];
+        let mut lfn: Option<(u64, u8, u8)> = None;
+        while let Some(offset) = self.next_slot(&mut slots)? {
+            let buf = self.read_slot(offset)?;
+            match buf[0] {
+                END => break,
+                DELETED => {
+                    lfn = None;
+                    continue;
+                }
+                _ => {}
+            }
+            if buf[11] & 0x3F == ATTR_LFN {
+                let seq = buf[0] & 0x1F;
+                if buf[0] & LFN_LAST != 0 {
+                    units.fill(0xFFFF);
+                    lfn = Some((offset, seq, buf[13]));
+                }
+                // long name entries come in reverse order, down to 1
+                match lfn {
+                    Some((_, expected, sum)) if seq == expected && sum == buf[13] && seq > 0 => {
+                        let start = (seq as usize - 1) * LFN_CHARS;
+                        if start + LFN_CHARS > units.len() {
+                            lfn = None;
+                            continue;
+                        }
+                        for (i, &at) in LFN_OFFSETS.iter().enumerate() {
+                            units[start + i] = u16_at(&buf, at);
+                        }
+                        lfn = lfn.map(|(first, _, sum)| (first, seq - 1, sum));
+                    }
+                    _ => lfn = None,
+                }
+                continue;
+            }
+            let raw = Raw::parse(&buf);
+            if raw.attr & ATTR_VOLUME_ID != 0 || raw.name[0] == b'.' {
+                lfn = None;
+                continue;
+            }
+
+            let (first, name) = match lfn.take() {
+                Some((first, 0, sum)) if sum == checksum(&raw.name) => {
+                    let len = units.iter().position(|&u| u == 0 || u == 0xFFFF);
+                    let units = &units[..len.unwrap_or(units.len())];
+                    let mut name = Name::new();
+                    for c in char::decode_utf16(units.iter().copied()) {
+                        let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+                        name.push_str(c.encode_utf8(&mut [0; 4]));
+                    }
+                    (first, name)
+                }
+                _ => (offset, raw.short_name()),
+            };
+            let found = Found {
+                offset,
+                first,
+                raw,
+                name,
+            };
+            if f(&found) {
+                return Ok(Some(found));
+            }
+        }
+        Ok(None)
+    }
+
+    fn find(&self, dir: Dir, name: &str) -> Result<Option<Found>> {
+        self.scan(dir, |found| found.name.eq_ignore_ascii_case(name))
+    }
+
+    fn short_name(&self, dir: Dir, name: &str) -> Result<([u8; 11], bool)> {
+        if let Some(short) = exact_short_name(name) {
+            if self.scan(dir, |found| found.raw.name == short)?.is_none() {
+                return Ok((short, false));
+            }
+        }
+        let (base, ext) = match name.rsplit_once('.') {
+            Some((base, ext)) if !base.trim_start_matches('.').is_empty() => (base, ext),
+            _ => (name, ""),
+        };
+        let mut clean_base = [0; 8];
+        let base_len = clean_short(base, &mut clean_base).max(1);
+        if clean_base[0] == 0 {
+            clean_base[0] = b'_';
+        }
+        let mut short = [b' '; 11];
+        let ext_len = clean_short(ext, &mut short[8..11]);
+        for c in short[8 + ext_len..11].iter_mut() {
+            *c = b' ';
+        }
+
+        // the basis name is made unique with a ~N tail
+        for n in 1..1000u32 {
+            let digits = if n < 10 {
+                1
+            } else if n < 100 {
+                2
+            } else {
+                3
+            };
+            let keep = base_len.min(8 - 1 - digits);
+            short[..8].fill(b' ');
+            short[..keep].copy_from_slice(&clean_base[..keep]);
+            short[keep] = b'~';
+            let mut value = n;
+            for i in (0..digits).rev() {
+                short[keep + 1 + i] = b'0' + (value % 10) as u8;
+                value /= 10;
+            }
+            if self.scan(dir, |found| found.raw.name == short)?.is_none() {
+                return Ok((short, true));
+            }
+        }
+        Err(Error::Exists)
+    }
+
+    // finds `count` consecutive free entries, growing the directory if needed
+    fn free_slots(&mut self, dir: Dir, count: usize, slots: &mut [u64]) -> Result<()> {
+        let mut iter = self.slots(dir);
+        let mut run = 0;
+        let mut ended = false;
+        loop {
+            let offset = match self.next_slot(&mut iter)? {
+                Some(offset) => offset,
+                None => match dir {
+                    Dir::Root => return Err(Error::NoSpace),
+                    Dir::Cluster(_) => {
+                        self.alloc_cluster(Some(iter.cluster))?;
+                        continue;
+                    }
+                },
+            };
+            let first = if ended {
+                END
+            } else {
+                self.read_slot(offset)?[0]
+            };
+            if first == END {
+                ended = true;
+            }
+            if first == END || first == DELETED {
+                slots[run] = offset;
+                run += 1;
+                if run == count {
+                    return Ok(());
+                }
+            } else {
+                run = 0;
+            }
+        }
+    }
+}
+
+impl FileSystem for FatFs {
+    fn root(&self) -> u64 {
+        ROOT
+    }
+
+    fn lookup(&mut self, dir: u64, name: &str) -> Result<u64> {
+        let dir = self.dir(dir)?;
+        match self.find(dir, name)? {
+            Some(found) => Ok(found.offset),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    fn stat(&mut self, ino: u64) -> Result<Stat> {
+        if ino == ROOT {
+            return Ok(Stat {
+                ino,
+                kind: Kind::Dir,
+                size: 0,
+            });
+        }
+        let raw = self.entry(ino)?;
+        Ok(Stat {
+            ino,
+            kind: if raw.is_dir() { Kind::Dir } else { Kind::File },
+            size: if raw.is_dir() { 0 } else { raw.size as u64 },
+        })
+    }
+
+    fn read(&mut self, ino: u64, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        if ino == ROOT {
+            return Err(Error::IsDir);
+        }
+        let raw = self.entry(ino)?;
+        if raw.is_dir() {
+            return Err(Error::IsDir);
+        }
+        let size = raw.size as u64;
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+        let mut cluster = raw.cluster;
+        for _ in 0..offset / self.cluster_size {
+            cluster = self.next(cluster)?.ok_or(Error::Io)?;
+        }
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let skip = pos % self.cluster_size;
+            if done > 0 && skip == 0 {
+                cluster = self.next(cluster)?.ok_or(Error::Io)?;
+            }
+            if !self.is_valid(cluster) {
+                return Err(Error::Io);
+            }
+            let chunk = ((self.cluster_size - skip) as usize).min(len - done);
+            let at = self.cluster_offset(cluster) + skip;
+            self.disk.read_bytes(at, &mut buf[done..done + chunk])?;
+            done += chunk;
+        }
+        Ok(len)
+    }
+
+    fn write(&mut self, ino: u64, offset: u64, buf: &[u8]) -> Result<usize> {
+        if ino == ROOT {
+            return Err(Error::IsDir);
+        }
+        let mut raw = self.entry(ino)?;
+        if raw.is_dir() {
+            return Err(Error::IsDir);
+        }
+        let end = offset + buf.len() as u64;
+        if end > u32::MAX as u64 {
+            return Err(Error::NoSpace);
+        }
+        // leave no stale bytes between the old end of file and `offset`
+        if offset > raw.size as u64 {
+            let zeros = [0; 512];
+            let mut pos = raw.size as u64;
+            while pos < offset {
+                let len = (offset - pos).min(zeros.len() as u64) as usize;
+                pos += self.write(ino, pos, &zeros[..len])? as u64;
+            }
+            raw = self.entry(ino)?;
+        }
+
+        // make the chain long enough for the new end of file
+        if raw.cluster == 0 && !buf.is_empty() {
+            raw.cluster = self.alloc_cluster(None)?;
+        }
+        let mut cluster = raw.cluster;
+        let mut done = 0;
+        let mut index = 0;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            while index < pos / self.cluster_size {
+                cluster = match self.next(cluster)? {
+                    Some(next) => next,
+                    None => self.alloc_cluster(Some(cluster))?,
+                };
+                index += 1;
+            }
+            let skip = pos % self.cluster_size;
+            let chunk = ((self.cluster_size - skip) as usize).min(buf.len() - done);
+            let at = self.cluster_offset(cluster) + skip;
+            self.disk.write_bytes(at, &buf[done..done + chunk])?;
+            done += chunk;
+        }
+
+        raw.size = raw.size.max(end as u32);
+        let mut slot = self.read_slot(ino)?;
+        raw.encode(&mut slot);
+        self.write_slot(ino, &slot)?;
+        Ok(buf.len())
+    }
+
+    fn readdir(&mut self, dir: u64, index: usize) -> Result<Option<DirEntry>> {
+        let dir = self.dir(dir)?;
+        let mut i = 0;
+        let found = self.scan(dir, |_| {
+            i += 1;
+            i > index
+        })?;
+        Ok(found.map(|found| DirEntry {
+            ino: found.offset,
+            kind: if found.raw.is_dir() {
+                Kind::Dir
+            } else {
+                Kind::File
+            },
+            name: found.name,
+        }))
+    }
+
+    fn create(&mut self, dir_ino: u64, name: &str, kind: Kind) -> Result<u64> {
+        if name.is_empty()
+            || name == "."
+            || name == ".."
+            || name.len() > 255
+            || name.contains(INVALID_CHARS)
+            || name.chars().any(|c| c.is_control())
+        {
+            return Err(Error::Invalid);
+        }
+        let attr = match kind {
+            Kind::File => ATTR_ARCHIVE,
+            Kind::Dir => ATTR_DIRECTORY,
+            _ => return Err(Error::Unsupported),
+        };
+        let dir = self.dir(dir_ino)?;
+        if self.find(dir, name)?.is_some() {
+            return Err(Error::Exists);
+        }
+
+        let (short, long) = self.short_name(dir, name)?;
+        let mut units = [0u16; LFN_MAX_ENTRIES * LFN_CHARS];
+        let mut len = 0;
+        for unit in name.encode_utf16() {
+            units[len] = unit;
+            len += 1;
+        }
+        let lfn_entries = if long { len.div_ceil(LFN_CHARS) } else { 0 };
+        let mut slots = [0; LFN_MAX_ENTRIES + 1];
+        let slots = &mut slots[..lfn_entries + 1];
+        self.free_slots(dir, lfn_entries + 1, slots)?;
+
+        let cluster = if kind == Kind::Dir {
+            let cluster = self.alloc_cluster(None)?;
+            let parent = match dir {
+                Dir::Root => 0,
+                Dir::Cluster(cluster) if cluster == self.root_cluster => 0,
+                Dir::Cluster(cluster) => cluster,
+            };
+            for (i, (dots, target)) in [(&b"."[..], cluster), (&b".."[..], parent)]
+                .iter()
+                .enumerate()
+            {
+                let mut slot = [0; 32];
+                let mut name = [b' '; 11];
+                name[..dots.len()].copy_from_slice(dots);
+                Raw {
+                    name,
+                    attr: ATTR_DIRECTORY,
+                    ntres: 0,
+                    cluster: *target,
+                    size: 0,
+                }
+                .encode(&mut slot);
+                self.write_slot(self.cluster_offset(cluster) + i as u64 * ENTRY_SIZE, &slot)?;
+            }
+            cluster
+        } else {
+            0
+        };
+
+        // long name entries are stored last part first
+        let sum = checksum(&short);
+        for (i, &offset) in slots[..lfn_entries].iter().enumerate() {
+            let seq = (lfn_entries - i) as u8;
+            let mut slot = [0; 32];
+            slot[0] = seq | if i == 0 { LFN_LAST } else { 0 };
+            slot[11] = ATTR_LFN;
+            slot[13] = sum;
+            let start = (seq as usize - 1) * LFN_CHARS;
+            for (j, &at) in LFN_OFFSETS.iter().enumerate() {
+                // the name is NUL terminated then padded with 0xFFFF
+                let unit = match start + j {
+                    k if k < len => units[k],
+                    k if k == len => 0,
+                    _ => 0xFFFF,
+                };
+                slot[at..at + 2].copy_from_slice(&unit.to_le_bytes());
+            }
+            self.write_slot(offset, &slot)?;
+        }
+        let offset = slots[lfn_entries];
+        let mut slot = [0; 32];
+        Raw {
+            name: short,
+            attr,
+            ntres: 0,
+            cluster,
+            size: 0,
+        }
+        .encode(&mut slot);
+        self.write_slot(offset, &slot)?;
+        Ok(offset)
+    }
+
+    fn unlink(&mut self, dir_ino: u64, name: &str) -> Result<()> {
+        let dir = self.dir(dir_ino)?;
+        let found = self.find(dir, name)?.ok_or(Error::NotFound)?;
+        if found.raw.is_dir()
+            && self
+                .scan(Dir::Cluster(found.raw.cluster), |_| true)?
+                .is_some()
+        {
+            return Err(Error::NotEmpty);
+        }
+        self.free_chain(found.raw.cluster)?;
+
+        // mark the long name entries and the short entry as deleted
+        let mut slots = self.slots(dir);
+        let mut marking = false;
+        while let Some(offset) = self.next_slot(&mut slots)? {
+            marking |= offset == found.first;
+            if marking {
+                let mut slot = self.read_slot(offset)?;
+                slot[0] = DELETED;
+                self.write_slot(offset, &slot)?;
+            }
+            if offset == found.offset {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.disk.sync()
+    }
+}
+
+pub fn mount(vfs: &mut Vfs, path: &str, disk: Handle) -> Result<()> {
+    if !VOLUMES.is_init() {
+        unsafe { VOLUMES.init([const { None }; MAX_VOLUMES]) };
+    }
+    let fs = FatFs::new(disk)?;
+    let volumes = VOLUMES.get_mut();
+    let i = volumes
+        .iter()
+        .position(|volume| volume.is_none())
+        .ok_or(Error::NoSpace)?;
+    if let Err(err) = vfs.mount(path, volumes[i].insert(fs)) {
+        VOLUMES.get_mut()[i] = None;
+        return Err(err);
+    }
+    Ok(())
+}
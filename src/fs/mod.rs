@@ -0,0 +1,312 @@
+#![allow(dead_code)]
+
+pub mod devfs;
+pub mod ext2;
+pub mod fat;
+pub mod pages;
+pub mod procfs;
+pub mod tar;
+
+use crate::utils::fixed::FixedStr;
+
+pub const MAX_MOUNTS: usize = 8;
+pub const MAX_PATH: usize = 256;
+pub const MAX_SYMLINKS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    NotFound,
+    NotDir,
+    IsDir,
+    Exists,
+    NotEmpty,
+    ReadOnly,
+    NoSpace,
+    Invalid,
+    Unsupported,
+    Busy,
+    Loop,
+    Io,
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    File,
+    Dir,
+    Symlink,
+    CharDevice,
+    BlockDevice,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Stat {
+    pub ino: u64,
+    pub kind: Kind,
+    pub size: u64,
+}
+
+pub type Name = FixedStr<255>;
+pub type Path = FixedStr<MAX_PATH>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DirEntry {
+    pub ino: u64,
+    pub kind: Kind,
+    pub name: Name,
+}
+
+pub trait FileSystem {
+    fn root(&self) -> u64;
+    fn lookup(&mut self, dir: u64, name: &str) -> Result<u64>;
+    fn stat(&mut self, ino: u64) -> Result<Stat>;
+    fn read(&mut self, ino: u64, offset: u64, buf: &mut [u8]) -> Result<usize>;
+    fn readdir(&mut self, dir: u64, index: usize) -> Result<Option<DirEntry>>;
+
+    fn write(&mut self, _ino: u64, _offset: u64, _buf: &[u8]) -> Result<usize> {
+        Err(Error::ReadOnly)
+    }
+    fn ioctl(&mut self, _ino: u64, _cmd: u32, _arg: usize) -> Result<usize> {
+        Err(Error::Unsupported)
+    }
+    fn readlink(&mut self, _ino: u64, _buf: &mut [u8]) -> Result<usize> {
+        Err(Error::Invalid)
+    }
+    fn create(&mut self, _dir: u64, _name: &str, _kind: Kind) -> Result<u64> {
+        Err(Error::ReadOnly)
+    }
+    fn unlink(&mut self, _dir: u64, _name: &str) -> Result<()> {
+        Err(Error::ReadOnly)
+    }
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct Mount {
+    path: FixedStr<64>,
+    fs: &'static mut dyn FileSystem,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct File {
+    mount: usize,
+    ino: u64,
+    offset: u64,
+}
+
+pub struct Vfs {
+    mounts: [Option<Mount>; MAX_MOUNTS],
+}
+
+// returns the path relative to the mount point if `path` lives under it
+fn strip_mount<'a>(path: &'a str, mount: &str) -> Option<&'a str> {
+    let mount = mount.trim_end_matches('/');
+    let rest = path.strip_prefix(mount)?;
+    (rest.is_empty() || rest.starts_with('/')).then_some(rest)
+}
+
+fn push(path: &mut Path, s: &str) -> Result<()> {
+    if path.len() + s.len() > MAX_PATH {
+        return Err(Error::Invalid);
+    }
+    path.push_str(s);
+    Ok(())
+}
+
+// absolute path without empty, "." and ".." components
+fn normalize(path: &str) -> Result<Path> {
+    if !path.starts_with('/') {
+        return Err(Error::Invalid);
+    }
+    let mut clean = Path::new();
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => clean.truncate(clean.rfind('/').unwrap_or(0)),
+            _ => {
+                push(&mut clean, "/")?;
+                push(&mut clean, name)?;
+            }
+        }
+    }
+    if clean.is_empty() {
+        push(&mut clean, "/")?;
+    }
+    Ok(clean)
+}
+
+impl Vfs {
+    pub const fn new() -> Self {
+        Self {
+            mounts: [const { None }; MAX_MOUNTS],
+        }
+    }
+
+    pub fn mount(&mut self, path: &str, fs: &'static mut dyn FileSystem) -> Result<()> {
+        if !path.starts_with('/') {
+            return Err(Error::Invalid);
+        }
+        if self.mounts.iter().flatten().any(|m| *m.path == *path) {
+            return Err(Error::Busy);
+        }
+        let slot = self
+            .mounts
+            .iter_mut()
+            .find(|m| m.is_none())
+            .ok_or(Error::NoSpace)?;
+        *slot = Some(Mount {
+            path: FixedStr::from(path),
+            fs,
+        });
+        Ok(())
+    }
+
+    pub fn unmount(&mut self, path: &str) -> Result<()> {
+        let index = self
+            .mounts
+            .iter()
+            .position(|m| m.as_ref().is_some_and(|m| *m.path == *path))
+            .ok_or(Error::NotFound)?;
+        if let Some(mount) = &mut self.mounts[index] {
+            mount.fs.sync()?;
+        }
+        self.mounts[index] = None;
+        pages::invalidate_mount(index);
+        Ok(())
+    }
+
+    // picks the deepest mount point containing `path`
+    fn find_mount<'a>(&self, path: &'a str) -> Result<(usize, &'a str)> {
+        let mut best: Option<(usize, &str, usize)> = None;
+        for (i, mount) in self.mounts.iter().enumerate() {
+            let Some(mount) = mount else { continue };
+            if let Some(rest) = strip_mount(path, &mount.path) {
+                if best.is_none_or(|(_, _, len)| mount.path.len() > len) {
+                    best = Some((i, rest, mount.path.len()));
+                }
+            }
+        }
+        best.map(|(i, rest, _)| (i, rest)).ok_or(Error::NotFound)
+    }
+
+    fn fs(&mut self, mount: usize) -> Result<&mut dyn FileSystem> {
+        match &mut self.mounts[mount] {
+            Some(mount) => Ok(mount.fs),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    fn resolve(&mut self, path: &str) -> Result<(usize, u64)> {
+        self.walk(path, true, 0)
+    }
+
+    // symlinks are followed, the last component only if `follow` is set
+    fn walk(&mut self, path: &str, follow: bool, depth: usize) -> Result<(usize, u64)> {
+        let path = normalize(path)?;
+        let (mount, rest) = self.find_mount(&path)?;
+        let fs = self.fs(mount)?;
+        let mut ino = fs.root();
+        let mut names = rest.split('/').filter(|name| !name.is_empty()).peekable();
+        while let Some(name) = names.next() {
+            if fs.stat(ino)?.kind != Kind::Dir {
+                return Err(Error::NotDir);
+            }
+            let next = fs.lookup(ino, name)?;
+            if fs.stat(next)?.kind != Kind::Symlink || (names.peek().is_none() && !follow) {
+                ino = next;
+                continue;
+            }
+            if depth == MAX_SYMLINKS {
+                return Err(Error::Loop);
+            }
+            let mut buf = [0; MAX_PATH];
+            let len = fs.readlink(next, &mut buf)?;
+            let target = core::str::from_utf8(&buf[..len]).map_err(|_| Error::Invalid)?;
+
+            // splice the target in place of the link, relative to its directory
+            let start = name.as_ptr() as usize - path.as_ptr() as usize;
+            let mut spliced = Path::new();
+            if !target.starts_with('/') {
+                push(&mut spliced, &path[..start])?;
+            }
+            push(&mut spliced, target)?;
+            push(&mut spliced, "/")?;
+            push(&mut spliced, &path[start + name.len()..])?;
+            return self.walk(&spliced, follow, depth + 1);
+        }
+        Ok((mount, ino))
+    }
+
+    pub fn open(&mut self, path: &str) -> Result<File> {
+        let (mount, ino) = self.resolve(path)?;
+        Ok(File {
+            mount,
+            ino,
+            offset: 0,
+        })
+    }
+
+    pub fn create(&mut self, path: &str, kind: Kind) -> Result<File> {
+        let (dir, name) = path.rsplit_once('/').ok_or(Error::Invalid)?;
+        let (mount, dir) = self.resolve(if dir.is_empty() { "/" } else { dir })?;
+        let ino = self.fs(mount)?.create(dir, name, kind)?;
+        Ok(File {
+            mount,
+            ino,
+            offset: 0,
+        })
+    }
+
+    pub fn unlink(&mut self, path: &str) -> Result<()> {
+        // the inode may be reused for another file
+        if let Ok((mount, ino)) = self.walk(path, false, 0) {
+            pages::invalidate(mount, ino);
+        }
+        let (dir, name) = path.rsplit_once('/').ok_or(Error::Invalid)?;
+        let (mount, dir) = self.resolve(if dir.is_empty() { "/" } else { dir })?;
+        self.fs(mount)?.unlink(dir, name)
+    }
+
+    pub fn readlink(&mut self, path: &str, buf: &mut [u8]) -> Result<usize> {
+        let (mount, ino) = self.walk(path, false, 0)?;
+        self.fs(mount)?.readlink(ino, buf)
+    }
+
+    pub fn stat(&mut self, file: &File) -> Result<Stat> {
+        self.fs(file.mount)?.stat(file.ino)
+    }
+
+    pub fn read(&mut self, file: &mut File, buf: &mut [u8]) -> Result<usize> {
+        let len = self.fs(file.mount)?.read(file.ino, file.offset, buf)?;
+        file.offset += len as u64;
+        Ok(len)
+    }
+
+    pub fn write(&mut self, file: &mut File, buf: &[u8]) -> Result<usize> {
+        let len = self.fs(file.mount)?.write(file.ino, file.offset, buf)?;
+        pages::invalidate(file.mount, file.ino);
+        file.offset += len as u64;
+        Ok(len)
+    }
+
+    pub fn seek(&mut self, file: &mut File, offset: u64) {
+        file.offset = offset;
+    }
+
+    pub fn readdir(&mut self, file: &File, index: usize) -> Result<Option<DirEntry>> {
+        self.fs(file.mount)?.readdir(file.ino, index)
+    }
+
+    pub fn ioctl(&mut self, file: &File, cmd: u32, arg: usize) -> Result<usize> {
+        self.fs(file.mount)?.ioctl(file.ino, cmd, arg)
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        for mount in self.mounts.iter_mut().flatten() {
+            mount.fs.sync()?;
+        }
+        Ok(())
+    }
+}
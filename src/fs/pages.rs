@@ -0,0 +1,149 @@
+use crate::fs::{Error, File, Result, Vfs};
+use crate::lazy::LazyMut;
+use crate::mem::frames::FRAME_SIZE;
+use crate::FRAMES;
+
+// file pages kept in memory for the mappings, each in its own frame
+pub const PAGES: usize = 128;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+    pub cached: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Page {
+    mount: usize,
+    ino: u64,
+    index: u64,
+    frame: usize,
+    // last use, the smallest one is evicted first
+    stamp: u64,
+}
+
+struct Cache {
+    pages: [Option<Page>; PAGES],
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+static CACHE: LazyMut<Cache> = LazyMut::new();
+
+pub fn init() {
+    unsafe {
+        CACHE.init(Cache {
+            pages: [None; PAGES],
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        })
+    };
+}
+
+// fills `frame` with page `index` of the file, zeroes past its end
+fn load(vfs: &mut Vfs, file: &File, index: u64, frame: usize) -> Result<()> {
+    let data = unsafe { core::slice::from_raw_parts_mut(frame as *mut u8, FRAME_SIZE) };
+    data.fill(0);
+    let fs = vfs.fs(file.mount)?;
+    let offset = index * FRAME_SIZE as u64;
+    let mut done = 0;
+    while done < FRAME_SIZE {
+        match fs.read(file.ino, offset + done as u64, &mut data[done..])? {
+            0 => break,
+            len => done += len,
+        }
+    }
+    Ok(())
+}
+
+// the frame holding page `index` of `file`, with a reference the caller
+// drops with `FRAMES.free`. The cache keeps one of its own while it can
+pub fn get(vfs: &mut Vfs, file: &File, index: u64) -> Result<usize> {
+    let cache = CACHE.get_mut();
+    cache.clock += 1;
+    let clock = cache.clock;
+    if let Some(page) = cache
+        .pages
+        .iter_mut()
+        .flatten()
+        .find(|page| page.mount == file.mount && page.ino == file.ino && page.index == index)
+    {
+        page.stamp = clock;
+        cache.hits += 1;
+        FRAMES.get_mut().share(page.frame);
+        return Ok(page.frame);
+    }
+    cache.misses += 1;
+
+    let frame = FRAMES.get_mut().alloc().ok_or(Error::NoSpace)?;
+    if let Err(err) = load(vfs, file, index, frame) {
+        FRAMES.get_mut().free(frame);
+        return Err(err);
+    }
+    // a free slot or the oldest page nobody maps anymore, without one the
+    // page simply isn't cached
+    let frames = FRAMES.get();
+    let victim = cache
+        .pages
+        .iter()
+        .position(|page| page.is_none())
+        .or_else(|| {
+            cache
+                .pages
+                .iter()
+                .enumerate()
+                .filter_map(|(i, page)| page.map(|page| (i, page)))
+                .filter(|(_, page)| frames.refs(page.frame) == 1)
+                .min_by_key(|(_, page)| page.stamp)
+                .map(|(i, _)| i)
+        });
+    if let Some(i) = victim {
+        if let Some(old) = cache.pages[i].take() {
+            FRAMES.get_mut().free(old.frame);
+        }
+        FRAMES.get_mut().share(frame);
+        cache.pages[i] = Some(Page {
+            mount: file.mount,
+            ino: file.ino,
+            index,
+            frame,
+            stamp: clock,
+        });
+    }
+    Ok(frame)
+}
+
+// forgets the pages of a file that changed, mappings keep the ones they have
+pub fn invalidate(mount: usize, ino: u64) {
+    drop_pages(|page| page.mount == mount && page.ino == ino);
+}
+
+// forgets every page of a mount going away, its index is reused by the next
+// mount and the pages would be taken for that filesystem's
+pub fn invalidate_mount(mount: usize) {
+    drop_pages(|page| page.mount == mount);
+}
+
+fn drop_pages(matches: impl Fn(&Page) -> bool) {
+    let Some(cache) = CACHE.try_get_mut() else {
+        return;
+    };
+    for slot in cache.pages.iter_mut() {
+        if let Some(page) = slot.filter(&matches) {
+            FRAMES.get_mut().free(page.frame);
+            *slot = None;
+        }
+    }
+}
+
+pub fn stats() -> Stats {
+    let cache = CACHE.get();
+    Stats {
+        hits: cache.hits,
+        misses: cache.misses,
+        cached: cache.pages.iter().flatten().count(),
+    }
+}
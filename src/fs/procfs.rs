@@ -0,0 +1,336 @@
+use core::fmt::{self, Write};
+
+use crate::arch::{irq, timer};
+use crate::dev::{block, pci};
+use crate::fs::{self, DirEntry, Error, FileSystem, Kind, Name, Result, Stat, Vfs};
+use crate::lazy::LazyMut;
+use crate::mem::frames::FRAME_SIZE;
+use crate::mem::shm as segments;
+use crate::mem::space::Backing;
+use crate::task;
+use crate::{CONTEXT, DEVICES, FRAMES};
+
+const ROOT: u64 = 0;
+
+static PROCFS: LazyMut<ProcFs> = LazyMut::new();
+
+type Generator = fn(&mut dyn Write) -> fmt::Result;
+
+// inode 0 is the directory, inode `i + 1` is the file `i` of this table.
+// Each process has a directory `(pid + 1) << PID_SHIFT` with the file `j` of
+// PID_FILES at its inode + j + 1
+const FILES: &[(&str, Generator)] = &[
+    ("cmdline", cmdline),
+    ("multiboot", multiboot),
+    ("mmap", mmap),
+    ("frames", frames),
+    ("interrupts", interrupts),
+    ("uptime", uptime),
+    ("devices", devices),
+    ("cache", cache),
+    ("pages", pages),
+    ("shm", shm),
+    ("pci", pci),
+    ("tasks", tasks),
+];
+
+type PidGenerator = fn(&mut dyn Write, task::Id) -> fmt::Result;
+
+const PID_SHIFT: u32 = 16;
+
+const PID_FILES: &[(&str, PidGenerator)] = &[("maps", maps)];
+
+pub struct ProcFs;
+
+fn cmdline(w: &mut dyn Write) -> fmt::Result {
+    match CONTEXT.get().info.get_cmdline() {
+        Some(cmdline) => writeln!(w, "{}", cmdline.to_str().unwrap_or("")),
+        None => Ok(()),
+    }
+}
+
+fn multiboot(w: &mut dyn Write) -> fmt::Result {
+    writeln!(w, "{:#?}", CONTEXT.get().info)
+}
+
+fn mmap(w: &mut dyn Write) -> fmt::Result {
+    for mmap in CONTEXT.get().mmaps {
+        writeln!(w, "{mmap:?}")?;
+    }
+    Ok(())
+}
+
+fn frames(w: &mut dyn Write) -> fmt::Result {
+    let stats = FRAMES.get().stats();
+    writeln!(w, "frame size: {FRAME_SIZE}")?;
+    writeln!(w, "total: {}", stats.total)?;
+    writeln!(w, "used: {}", stats.used)?;
+    writeln!(w, "free: {}", stats.free)
+}
+
+fn interrupts(w: &mut dyn Write) -> fmt::Result {
+    for i in 0..irq::COUNT as u8 {
+        writeln!(w, "{i:>3} {:#04x}: {}", irq::vector(i), irq::count(i))?;
+    }
+    writeln!(w, "spurious: {}", irq::spurious())
+}
+
+fn uptime(w: &mut dyn Write) -> fmt::Result {
+    let ms = timer::uptime_ms();
+    writeln!(w, "{}.{:03}", ms / 1000, ms % 1000)
+}
+
+fn devices(w: &mut dyn Write) -> fmt::Result {
+    for (_, node) in DEVICES.get().iter() {
+        writeln!(w, "{:?} {} {}", node.kind, node.name, node.dev.size())?;
+    }
+    Ok(())
+}
+
+fn cache(w: &mut dyn Write) -> fmt::Result {
+    let stats = block::cache::stats();
+    writeln!(w, "buffers: {}", block::cache::BUFFERS)?;
+    writeln!(w, "hits: {}", stats.hits)?;
+    writeln!(w, "misses: {}", stats.misses)?;
+    writeln!(w, "writebacks: {}", stats.writebacks)?;
+    writeln!(w, "dirty: {}", stats.dirty)
+}
+
+fn pages(w: &mut dyn Write) -> fmt::Result {
+    let stats = fs::pages::stats();
+    writeln!(w, "pages: {}", fs::pages::PAGES)?;
+    writeln!(w, "cached: {}", stats.cached)?;
+    writeln!(w, "hits: {}", stats.hits)?;
+    writeln!(w, "misses: {}", stats.misses)
+}
+
+fn shm(w: &mut dyn Write) -> fmt::Result {
+    writeln!(w, "name                             pages maps")?;
+    for segment in segments::iter() {
+        let name = segment.name.as_deref().unwrap_or("(unlinked)");
+        writeln!(w, "{name:<32} {:>5} {:>4}", segment.pages, segment.maps)?;
+    }
+    Ok(())
+}
+
+fn pci(w: &mut dyn Write) -> fmt::Result {
+    for function in pci::iter() {
+        writeln!(w, "{function}")?;
+        for (i, bar) in function.bars.iter().enumerate() {
+            match *bar {
+                pci::Bar::None => {}
+                pci::Bar::Io { port, size } => {
+                    writeln!(w, "  bar{i}: io {port:#06x} size {size:#x}")?
+                }
+                pci::Bar::Memory {
+                    addr,
+                    size,
+                    prefetchable,
+                } => {
+                    let prefetch = if prefetchable { " prefetchable" } else { "" };
+                    writeln!(w, "  bar{i}: memory {addr:#010x} size {size:#x}{prefetch}")?
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn tasks(w: &mut dyn Write) -> fmt::Result {
+    writeln!(
+        w,
+        "  id ppid name             state    prio   cpu ms switches"
+    )?;
+    for task in task::iter() {
+        let ms = task.ticks * 1000 / timer::HZ as u64;
+        match task.parent {
+            Some(parent) => write!(w, "{:>4} {:>4} ", task.id, parent)?,
+            None => write!(w, "{:>4} {:>4} ", task.id, "-")?,
+        }
+        writeln!(
+            w,
+            "{:<16} {:<8} {:>4} {:>8} {:>8}",
+            task.name,
+            task.state.name(),
+            task.priority,
+            ms,
+            task.switches
+        )?;
+    }
+    Ok(())
+}
+
+fn maps(w: &mut dyn Write, pid: task::Id) -> fmt::Result {
+    let Some(space) = task::iter()
+        .find(|task| task.id == pid)
+        .and_then(|task| task.space())
+    else {
+        return Ok(());
+    };
+    for region in space.regions() {
+        let flag = |set, c| if set { c } else { '-' };
+        write!(
+            w,
+            "{:08x}-{:08x} {}{}{}p ",
+            region.start,
+            region.end,
+            flag(region.readable(), 'r'),
+            flag(region.writable(), 'w'),
+            flag(region.executable(), 'x'),
+        )?;
+        match region.backing {
+            Backing::File { file, offset } => {
+                writeln!(w, "{offset:08x} {}:{}", file.mount, file.ino)?
+            }
+            Backing::Shared { segment, index } => {
+                let stat = segments::stat(segment);
+                let name = stat.name.as_deref().unwrap_or("(unlinked)");
+                writeln!(w, "{:08x} shm {name}", index * FRAME_SIZE)?
+            }
+            _ => writeln!(w, "{:08x} {}", 0, region.kind.name())?,
+        }
+    }
+    Ok(())
+}
+
+// keeps the bytes of the formatted text falling in `skip..skip + buf.len()`
+struct Window<'a> {
+    buf: &'a mut [u8],
+    skip: u64,
+    pos: u64,
+    len: usize,
+}
+
+impl Write for Window<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let start = self.pos;
+        self.pos += s.len() as u64;
+        if self.pos <= self.skip {
+            return Ok(());
+        }
+        let from = self.skip.saturating_sub(start) as usize;
+        let bytes = &s.as_bytes()[from..];
+        let len = bytes.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + len].copy_from_slice(&bytes[..len]);
+        self.len += len;
+        if self.len == self.buf.len() {
+            // stop formatting as soon as the buffer is full
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
+
+fn pid_dir(ino: u64) -> Option<task::Id> {
+    let pid = (ino >> PID_SHIFT).checked_sub(1)? as task::Id;
+    task::iter().any(|task| task.id == pid).then_some(pid)
+}
+
+fn generate(ino: u64, w: &mut dyn Write) -> Result<fmt::Result> {
+    if ino == ROOT {
+        return Err(Error::IsDir);
+    }
+    let i = (ino & ((1 << PID_SHIFT) - 1)) as usize;
+    if ino >> PID_SHIFT == 0 {
+        let (_, generator) = FILES.get(i - 1).ok_or(Error::NotFound)?;
+        return Ok(generator(w));
+    }
+    let pid = pid_dir(ino).ok_or(Error::NotFound)?;
+    let i = i.checked_sub(1).ok_or(Error::IsDir)?;
+    let (_, generator) = PID_FILES.get(i).ok_or(Error::NotFound)?;
+    Ok(generator(w, pid))
+}
+
+impl FileSystem for ProcFs {
+    fn root(&self) -> u64 {
+        ROOT
+    }
+
+    fn lookup(&mut self, dir: u64, name: &str) -> Result<u64> {
+        if dir != ROOT {
+            pid_dir(dir).ok_or(Error::NotDir)?;
+            if dir & ((1 << PID_SHIFT) - 1) != 0 {
+                return Err(Error::NotDir);
+            }
+            let j = PID_FILES
+                .iter()
+                .position(|(file, _)| *file == name)
+                .ok_or(Error::NotFound)?;
+            return Ok(dir + j as u64 + 1);
+        }
+        if let Some(i) = FILES.iter().position(|(file, _)| *file == name) {
+            return Ok(i as u64 + 1);
+        }
+        let pid: task::Id = name.parse().map_err(|_| Error::NotFound)?;
+        let ino = (pid as u64 + 1) << PID_SHIFT;
+        pid_dir(ino).ok_or(Error::NotFound)?;
+        Ok(ino)
+    }
+
+    fn stat(&mut self, ino: u64) -> Result<Stat> {
+        if ino == ROOT || (pid_dir(ino).is_some() && ino & ((1 << PID_SHIFT) - 1) == 0) {
+            return Ok(Stat {
+                ino,
+                kind: Kind::Dir,
+                size: 0,
+            });
+        }
+        // the size is only known by generating the whole text
+        let mut window = Window {
+            buf: &mut [],
+            skip: u64::MAX,
+            pos: 0,
+            len: 0,
+        };
+        let _ = generate(ino, &mut window)?;
+        Ok(Stat {
+            ino,
+            kind: Kind::File,
+            size: window.pos,
+        })
+    }
+
+    fn read(&mut self, ino: u64, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let mut window = Window {
+            buf,
+            skip: offset,
+            pos: 0,
+            len: 0,
+        };
+        let _ = generate(ino, &mut window)?;
+        Ok(window.len)
+    }
+
+    fn readdir(&mut self, dir: u64, index: usize) -> Result<Option<DirEntry>> {
+        if dir != ROOT {
+            pid_dir(dir).ok_or(Error::NotDir)?;
+            return Ok(PID_FILES.get(index).map(|(name, _)| DirEntry {
+                ino: dir + index as u64 + 1,
+                kind: Kind::File,
+                name: Name::from(*name),
+            }));
+        }
+        if let Some((name, _)) = FILES.get(index) {
+            return Ok(Some(DirEntry {
+                ino: index as u64 + 1,
+                kind: Kind::File,
+                name: Name::from(*name),
+            }));
+        }
+        // then a directory per process
+        Ok(task::iter().nth(index - FILES.len()).map(|task| {
+            let mut name = Name::from("");
+            let _ = write!(name, "{}", task.id);
+            DirEntry {
+                ino: (task.id as u64 + 1) << PID_SHIFT,
+                kind: Kind::Dir,
+                name,
+            }
+        }))
+    }
+}
+
+pub fn mount(vfs: &mut Vfs, path: &str) -> Result<()> {
+    unsafe { PROCFS.init(ProcFs) };
+    vfs.mount(path, PROCFS.get_mut())
+}
@@ -0,0 +1,221 @@
+use crate::fs::{DirEntry, Error, FileSystem, Kind, Name, Path, Result, Stat, Vfs};
+use crate::lazy::LazyMut;
+
+const BLOCK_SIZE: usize = 512;
+// the root has no header, inodes are the offsets of the headers otherwise
+const ROOT: u64 = u64::MAX;
+
+const TYPE_FILE: u8 = b'0';
+const TYPE_OLD_FILE: u8 = 0;
+const TYPE_SYMLINK: u8 = b'2';
+const TYPE_DIR: u8 = b'5';
+
+static TAR: LazyMut<TarFs> = LazyMut::new();
+
+// read-only view of an ustar archive in memory, like the initrd. Directories
+// need an entry of their own, as `tar` writes them
+pub struct TarFs {
+    data: &'static [u8],
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Header<'a> {
+    offset: usize,
+    block: &'a [u8],
+}
+
+fn field(block: &[u8], start: usize, len: usize) -> &[u8] {
+    let field = &block[start..start + len];
+    let len = field.iter().position(|c| *c == 0).unwrap_or(len);
+    &field[..len]
+}
+
+fn octal(field: &[u8]) -> Result<u64> {
+    let mut value: u64 = 0;
+    for c in field.iter().filter(|c| **c != b' ') {
+        if !(b'0'..=b'7').contains(c) {
+            return Err(Error::Invalid);
+        }
+        value = value.checked_mul(8).ok_or(Error::Invalid)? + (c - b'0') as u64;
+    }
+    Ok(value)
+}
+
+impl Header<'_> {
+    fn size(&self) -> Result<u64> {
+        octal(field(self.block, 124, 12))
+    }
+
+    fn typ(&self) -> u8 {
+        self.block[156]
+    }
+
+    fn kind(&self) -> Kind {
+        match self.typ() {
+            TYPE_DIR => Kind::Dir,
+            TYPE_SYMLINK => Kind::Symlink,
+            _ => Kind::File,
+        }
+    }
+
+    fn link(&self) -> &[u8] {
+        field(self.block, 157, 100)
+    }
+
+    fn is_valid(&self) -> bool {
+        field(self.block, 257, 6).starts_with(b"ustar")
+            && matches!(
+                self.typ(),
+                TYPE_FILE | TYPE_OLD_FILE | TYPE_SYMLINK | TYPE_DIR
+            )
+    }
+
+    // the path without the "./" prefix and the trailing "/" of directories
+    fn path(&self) -> Result<Path> {
+        let prefix =
+            core::str::from_utf8(field(self.block, 345, 155)).map_err(|_| Error::Invalid)?;
+        let name = core::str::from_utf8(field(self.block, 0, 100)).map_err(|_| Error::Invalid)?;
+        let mut path = Path::new();
+        path.push_str(prefix);
+        if !prefix.is_empty() {
+            path.push_str("/");
+        }
+        path.push_str(name);
+        let clean = path.trim_start_matches("./").trim_end_matches('/');
+        Ok(Path::from(clean))
+    }
+}
+
+// parent directory and name of a path relative to the root
+fn split(path: &str) -> (&str, &str) {
+    match path.rfind('/') {
+        Some(i) => (&path[..i], &path[i + 1..]),
+        None => ("", path),
+    }
+}
+
+impl TarFs {
+    fn headers(&self) -> impl Iterator<Item = Header<'_>> {
+        let mut offset = 0;
+        core::iter::from_fn(move || {
+            let block = self.data.get(offset..offset + BLOCK_SIZE)?;
+            let header = Header { offset, block };
+            if block.iter().all(|c| *c == 0) || !header.is_valid() {
+                return None;
+            }
+            let size = header.size().ok()? as usize;
+            offset += BLOCK_SIZE + size.next_multiple_of(BLOCK_SIZE);
+            Some(header)
+        })
+    }
+
+    fn header(&self, ino: u64) -> Result<Header<'_>> {
+        let offset = ino as usize;
+        let block = self
+            .data
+            .get(offset..offset + BLOCK_SIZE)
+            .ok_or(Error::NotFound)?;
+        Ok(Header { offset, block })
+    }
+
+    fn path(&self, ino: u64) -> Result<Path> {
+        if ino == ROOT {
+            return Ok(Path::new());
+        }
+        self.header(ino)?.path()
+    }
+
+    fn dir(&self, ino: u64) -> Result<Path> {
+        if ino != ROOT && self.header(ino)?.typ() != TYPE_DIR {
+            return Err(Error::NotDir);
+        }
+        self.path(ino)
+    }
+
+    // contents of a file, bounded by the archive
+    fn contents(&self, ino: u64) -> Result<&'static [u8]> {
+        let header = self.header(ino)?;
+        let start = header.offset + BLOCK_SIZE;
+        let size = header.size()? as usize;
+        self.data.get(start..start + size).ok_or(Error::Io)
+    }
+}
+
+impl FileSystem for TarFs {
+    fn root(&self) -> u64 {
+        ROOT
+    }
+
+    fn lookup(&mut self, dir: u64, name: &str) -> Result<u64> {
+        let dir = self.dir(dir)?;
+        for header in self.headers() {
+            let path = header.path()?;
+            if split(&path) == (dir.as_str(), name) {
+                return Ok(header.offset as u64);
+            }
+        }
+        Err(Error::NotFound)
+    }
+
+    fn stat(&mut self, ino: u64) -> Result<Stat> {
+        if ino == ROOT {
+            return Ok(Stat {
+                ino,
+                kind: Kind::Dir,
+                size: 0,
+            });
+        }
+        let header = self.header(ino)?;
+        Ok(Stat {
+            ino,
+            kind: header.kind(),
+            size: header.size()?,
+        })
+    }
+
+    fn read(&mut self, ino: u64, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        match self.header(ino)?.kind() {
+            Kind::Dir => return Err(Error::IsDir),
+            Kind::File => {}
+            _ => return Err(Error::Invalid),
+        }
+        let data = self.contents(ino)?;
+        let start = (offset as usize).min(data.len());
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        Ok(len)
+    }
+
+    fn readdir(&mut self, dir: u64, index: usize) -> Result<Option<DirEntry>> {
+        let dir = self.dir(dir)?;
+        let entry = self
+            .headers()
+            .filter_map(|header| {
+                let path = header.path().ok()?;
+                let (parent, name) = split(&path);
+                (parent == dir.as_str() && !name.is_empty()).then(|| DirEntry {
+                    ino: header.offset as u64,
+                    kind: header.kind(),
+                    name: Name::from(name),
+                })
+            })
+            .nth(index);
+        Ok(entry)
+    }
+
+    fn readlink(&mut self, ino: u64, buf: &mut [u8]) -> Result<usize> {
+        let header = self.header(ino)?;
+        if header.typ() != TYPE_SYMLINK {
+            return Err(Error::Invalid);
+        }
+        let link = header.link();
+        let len = link.len().min(buf.len());
+        buf[..len].copy_from_slice(&link[..len]);
+        Ok(len)
+    }
+}
+
+pub fn mount(vfs: &mut Vfs, path: &str, data: &'static [u8]) -> Result<()> {
+    unsafe { TAR.init(TarFs { data }) };
+    vfs.mount(path, TAR.get_mut())
+}
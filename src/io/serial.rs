@@ -1,6 +1,8 @@
 use core::fmt;
 
 use crate::arch::ports::Port;
+use crate::dev::Device;
+use crate::fs;
 use crate::io::WriteBytes;
 
 pub const PORT_COM1: u16 = 0x3F8;
@@ -20,29 +22,37 @@ impl Console {
 
     pub fn try_new(port: u16) -> Result<Self, ()> {
         let port = Port::new(port);
-        port.add(1).out_u8(0x00); // disable all interrupts
-        port.add(3).out_u8(0x80); // enable DLAB (set baud rate divisor)
-        port.add(0).out_u8(0x03); // set divisor to 3 (lo byte) 38400 baud
-        port.add(1).out_u8(0x00); //                  (hi byte)
-        port.add(3).out_u8(0x03); // 8 bits, no parity, one stop bit
-        port.add(2).out_u8(0xC7); // enable FIFO, clear them, with 14-byte threshold
-        port.add(4).out_u8(0x0B); // IRQs enabled, RTS/DSR set
-        port.add(4).out_u8(0x1E); // set in loopback mode, test the serial chip
-        port.add(0).out_u8(0xAE); // test serial chip (send byte 0xAE and check if serial returns same byte)
+        port.add(1).write(0x00); // disable all interrupts
+        port.add(3).write(0x80); // enable DLAB (set baud rate divisor)
+        port.add(0).write(0x03); // set divisor to 3 (lo byte) 38400 baud
+        port.add(1).write(0x00); //                  (hi byte)
+        port.add(3).write(0x03); // 8 bits, no parity, one stop bit
+        port.add(2).write(0xC7); // enable FIFO, clear them, with 14-byte threshold
+        port.add(4).write(0x0B); // IRQs enabled, RTS/DSR set
+        port.add(4).write(0x1E); // set in loopback mode, test the serial chip
+        port.add(0).write(0xAE); // test serial chip (send byte 0xAE and check if serial returns same byte)
 
         // check if serial is faulty (i.e: not same byte as sent)
-        if port.in_u8() != 0xAE {
+        if port.read() != 0xAE {
             return Err(());
         }
 
         // If serial is not faulty set it in normal operation mode
         // (not-loopback with IRQs enabled and OUT#1 and OUT#2 bits enabled)
-        port.add(4).out_u8(0x0F);
+        port.add(4).write(0x0F);
         Ok(Self { port })
     }
 
     fn is_transmit_ready(&self) -> bool {
-        self.port.add(5).in_u8() & 0x20 != 0
+        self.port.add(5).read() & 0x20 != 0
+    }
+
+    fn is_data_ready(&self) -> bool {
+        self.port.add(5).read() & 0x01 != 0
+    }
+
+    pub fn try_read_byte(&mut self) -> Option<u8> {
+        self.is_data_ready().then(|| self.port.read())
     }
 }
 
@@ -52,7 +62,7 @@ impl WriteBytes for Console {
             self.write_byte(b'\r');
         }
         while !self.is_transmit_ready() {}
-        self.port.out_u8(byte)
+        self.port.write(byte)
     }
 }
 
@@ -64,3 +74,23 @@ impl fmt::Write for Console {
         Ok(())
     }
 }
+
+impl Device for Console {
+    // returns whatever is pending in the receive FIFO without blocking
+    fn read(&mut self, _offset: u64, buf: &mut [u8]) -> fs::Result<usize> {
+        let mut len = 0;
+        while len < buf.len() {
+            match self.try_read_byte() {
+                Some(byte) => buf[len] = byte,
+                None => break,
+            }
+            len += 1;
+        }
+        Ok(len)
+    }
+
+    fn write(&mut self, _offset: u64, buf: &[u8]) -> fs::Result<usize> {
+        self.write_bytes(buf);
+        Ok(buf.len())
+    }
+}
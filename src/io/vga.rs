@@ -2,6 +2,8 @@ use core::fmt;
 use core::ops::Deref;
 
 use crate::arch::ports::Port;
+use crate::dev::{self, Device};
+use crate::fs;
 use crate::io::WriteBytes;
 
 const CRTC_INDEX: Port = Port::new(0x3D4);
@@ -132,15 +134,15 @@ impl Console {
     }
 
     pub fn enable_cursor(&mut self, start: u8, end: u8) {
-        CRTC_INDEX.out_u8(0x0A);
-        CRTC_DATA.out_u8((CRTC_DATA.in_u8() & 0xC0) | start);
-        CRTC_INDEX.out_u8(0x0B);
-        CRTC_DATA.out_u8((CRTC_DATA.in_u8() & 0xE0) | end);
+        CRTC_INDEX.write(0x0A);
+        CRTC_DATA.write((CRTC_DATA.read() & 0xC0) | start);
+        CRTC_INDEX.write(0x0B);
+        CRTC_DATA.write((CRTC_DATA.read() & 0xE0) | end);
     }
 
     pub fn disable_cursor(&mut self) {
-        CRTC_INDEX.out_u8(0x0A);
-        CRTC_DATA.out_u8(0x20);
+        CRTC_INDEX.write(0x0A);
+        CRTC_DATA.write(0x20);
     }
 
     pub fn get_cursor(&self) -> usize {
@@ -148,10 +150,10 @@ impl Console {
     }
 
     pub fn set_cursor(&mut self, pos: usize) {
-        CRTC_INDEX.out_u8(0x0F);
-        CRTC_DATA.out_u8((pos & 0xFF) as u8);
-        CRTC_INDEX.out_u8(0x0E);
-        CRTC_DATA.out_u8(((pos >> 8) & 0xFF) as u8);
+        CRTC_INDEX.write(0x0F);
+        CRTC_DATA.write((pos & 0xFF) as u8);
+        CRTC_INDEX.write(0x0E);
+        CRTC_DATA.write(((pos >> 8) & 0xFF) as u8);
     }
 
     pub fn update_cursor(&mut self) {
@@ -246,3 +248,29 @@ impl fmt::Write for Console {
         Ok(())
     }
 }
+
+impl Device for Console {
+    // there is no keyboard driver yet, so the screen has nothing to read
+    fn read(&mut self, _offset: u64, _buf: &mut [u8]) -> fs::Result<usize> {
+        Ok(0)
+    }
+
+    fn write(&mut self, _offset: u64, buf: &[u8]) -> fs::Result<usize> {
+        self.write_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn ioctl(&mut self, cmd: u32, _arg: usize) -> fs::Result<usize> {
+        match cmd {
+            dev::IOCTL_TTY_SIZE => Ok(self.height << 16 | self.width),
+            dev::IOCTL_TTY_CLEAR => {
+                self.clear();
+                self.x = 0;
+                self.y = 0;
+                self.update_cursor();
+                Ok(0)
+            }
+            _ => Err(fs::Error::Unsupported),
+        }
+    }
+}
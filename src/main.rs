@@ -1,22 +1,26 @@
 #![no_std]
 #![no_main]
 #![allow(clippy::identity_op)]
-#![feature(abi_x86_interrupt)]
 
 #[macro_use]
 mod utils;
 
 mod arch;
 mod builtins;
+mod dev;
+mod fs;
 mod io;
 mod lazy;
 mod mem;
 mod multiboot;
+mod syscall;
+mod task;
 
 use core::fmt::Write;
 use core::panic::PanicInfo;
 
-use arch::tables::{gdt, idt};
+use arch::tables::{gdt, idt, tss};
+use arch::{irq, sysenter, timer};
 use io::serial;
 use io::vga::{self, Border, Color};
 use io::WriteBytes;
@@ -36,7 +40,11 @@ static STDOUT: LazyMut<&mut dyn Write> = LazyMut::new();
 
 static CONTEXT: Lazy<Context> = Lazy::new();
 
-static GDT: LazyMut<[gdt::Entry; 5]> = LazyMut::new();
+static FRAMES: LazyMut<mem::frames::Allocator> = LazyMut::new();
+static DEVICES: LazyMut<dev::Registry> = LazyMut::new();
+static VFS: LazyMut<fs::Vfs> = LazyMut::new();
+
+static GDT: LazyMut<[gdt::Entry; 6]> = LazyMut::new();
 static IDT: LazyMut<[idt::Entry; 256]> = LazyMut::new();
 
 #[no_mangle]
@@ -55,6 +63,29 @@ pub extern "C" fn kernel_main(info: &'static multiboot::Info, magic: u32) {
     eprintln!("Multiboot flags: {:013b}", info.get_flags());
     eprintln!("Multiboot infos: {info:#?}");
 
+    if let Some(mmaps) = info.get_mmaps() {
+        unsafe { CONTEXT.init(Context { info, mmaps }) };
+    } else {
+        panic!("Could not get memory map entries");
+    }
+
+    // the initrd and the rest of what the boot loader left stay where they are
+    let initrd = info.get_modules().first().map(|module| module.data());
+    let mut reserved = [(0, 0); 16];
+    let mut len = 0;
+    for range in core::iter::once(mem::kernel_range()).chain(info.ranges()) {
+        if len == reserved.len() {
+            panic!("Too many boot ranges to reserve");
+        }
+        reserved[len] = range;
+        len += 1;
+    }
+    match mem::frames::Allocator::new(CONTEXT.get().mmaps, &reserved[..len]) {
+        Ok(frames) => unsafe { FRAMES.init(frames) },
+        Err(()) => panic!("Could not initialize frame allocator"),
+    }
+    eprintln!("Frames: {:?}", FRAMES.get().stats());
+
     if let Some(framebuffer) = info.get_framebuffer() {
         let mut console = vga::Console::new(framebuffer, Color::LightGrey, Color::Black);
         eprintln!("VGA console initialized");
@@ -66,25 +97,113 @@ pub extern "C" fn kernel_main(info: &'static multiboot::Info, magic: u32) {
         unsafe { STDOUT.init(SERIAL.get_mut()) };
     }
 
-    let segments = gdt::default_segments();
-    unsafe { GDT.init(segments) };
-    gdt::load(GDT.get());
     let code_sel = gdt::selector(1, false, u2::V00);
     let data_sel = gdt::selector(2, false, u2::V00);
+    let tss_sel = gdt::selector(5, false, u2::V00);
+    let [null, kernel_code, kernel_data, user_code, user_data] = gdt::default_segments();
+    let tss = tss::init(data_sel);
+    unsafe { GDT.init([null, kernel_code, kernel_data, user_code, user_data, tss]) };
+    gdt::load(GDT.get());
     gdt::reload_cs(code_sel);
     gdt::reload_ds(data_sel);
     gdt::reload_ss(data_sel);
     gdt::reload_es(data_sel);
     gdt::reload_fs(data_sel);
     gdt::reload_gs(data_sel);
+    gdt::load_tr(tss_sel);
+    sysenter::init(code_sel);
+    eprintln!("SYSENTER: {}", sysenter::enabled());
     eprintln!("GDT: {:#08X?}", GDT.get());
 
-
-    idt::init(0x20, 0x28);
+    irq::init();
     let gates = idt::default_gates(code_sel);
     unsafe { IDT.init(gates) };
     idt::load(IDT.get());
     eprintln!("IDT: {:#08X?}", IDT.get());
+    mem::paging::init();
+    mem::shm::init();
+    timer::init();
+    task::init();
+
+    unsafe { DEVICES.init(dev::Registry::new()) };
+    let devices = DEVICES.get_mut();
+    let _ = devices.register("ttyS0", dev::Kind::Char, SERIAL.get_mut());
+    if let Some(screen) = SCREEN.try_get_mut() {
+        let _ = devices.register("tty0", dev::Kind::Char, screen);
+    }
+    if let Err(err) = dev::mem::init(devices) {
+        panic!("Could not register memory devices: {err:?}");
+    }
+    if let Err(err) = dev::block::init() {
+        panic!("Could not initialize block layer: {err:?}");
+    }
+    dev::pci::init();
+    dev::pci::probe();
+    for function in dev::pci::iter() {
+        eprintln!("PCI {function}");
+    }
+    if let Err(err) = dev::ata::init() {
+        eprintln!("Could not register ATA drives: {err:?}");
+    }
+
+    unsafe { VFS.init(fs::Vfs::new()) };
+    fs::pages::init();
+    if let Err(err) = fs::devfs::mount(VFS.get_mut(), "/dev") {
+        panic!("Could not mount devfs: {err:?}");
+    }
+    if let Err(err) = fs::procfs::mount(VFS.get_mut(), "/proc") {
+        panic!("Could not mount procfs: {err:?}");
+    }
+    if let Some(initrd) = initrd {
+        if let Err(err) = fs::tar::mount(VFS.get_mut(), "/initrd", initrd) {
+            eprintln!("Could not mount the initrd: {err:?}");
+        }
+    }
+    // the first partition of a disk, or the whole disk without partitions
+    for name in ["hda1", "hda", "sda1", "sda", "vda1", "vda"] {
+        let Some(disk) = dev::block::find(name) else {
+            continue;
+        };
+        match fs::fat::mount(VFS.get_mut(), "/", disk)
+            .or_else(|_| fs::ext2::mount(VFS.get_mut(), "/", disk))
+        {
+            Ok(()) => break,
+            Err(err) => {
+                eprintln!("Could not mount {name} as FAT or ext2: {err:?}");
+            }
+        }
+    }
+    if let Ok(dir) = VFS.get_mut().open("/dev") {
+        let mut index = 0;
+        while let Ok(Some(entry)) = VFS.get_mut().readdir(&dir, index) {
+            eprintln!("/dev/{}: {:?}", entry.name, entry.kind);
+            index += 1;
+        }
+    }
+
+    let mut args = info
+        .get_cmdline()
+        .map(|cmdline| cmdline.to_bytes().split(|c| *c == b' '))
+        .into_iter()
+        .flatten();
+    if args.any(|arg| arg == b"syscall-bench") {
+        syscall::bench::spawn();
+    }
+    if VFS.get_mut().open("/initrd/init").is_ok() {
+        match task::elf::load(VFS.get_mut(), "/initrd/init", &["init"], &[]) {
+            Ok(image) => {
+                match task::spawn_user("init", image) {
+                    Ok(id) => task::process::set_init(id),
+                    Err(err) => {
+                        eprintln!("Could not start /initrd/init: {err:?}");
+                    }
+                }
+            }
+            Err(err) => {
+                eprintln!("Could not load /initrd/init: {err:?}");
+            }
+        }
+    }
 
     println!("Hello from CairnOS!");
     println!("{:b}", info.get_flags());
@@ -101,13 +220,9 @@ pub extern "C" fn kernel_main(info: &'static multiboot::Info, magic: u32) {
     println!("{:?}", info.get_vbe());
     println!("{:?}", info.get_framebuffer());
 
-    if let Some(mmaps) = info.get_mmaps() {
-        unsafe { CONTEXT.init(Context { info, mmaps }) };
-    } else {
-        panic!("Could not get memory map entries");
-    }
-
     println!("Bye!");
+    // other tasks keep running, the boot stack is never freed
+    task::exit(task::process::exited(0));
 }
 
 #[panic_handler]
@@ -0,0 +1,187 @@
+#![allow(dead_code)]
+
+use crate::mem::{Mmap, MmapType};
+
+pub const FRAME_SIZE: usize = 4096;
+// only the first GiB is managed so every frame stays reachable by the kernel
+pub const MAX_MEMORY: u64 = 0x4000_0000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub total: usize,
+    pub free: usize,
+    pub used: usize,
+}
+
+// one bit per frame, set when the frame is used or unavailable, and a count
+// of the address spaces sharing each used frame
+pub struct Allocator {
+    bitmap: &'static mut [u32],
+    refs: &'static mut [u16],
+    frames: usize,
+    total: usize,
+    free: usize,
+    next: usize,
+}
+
+fn available(mmaps: &[Mmap]) -> impl Iterator<Item = (u64, u64)> + '_ {
+    mmaps
+        .iter()
+        .filter(|mmap| matches!(mmap.typ, MmapType::Available))
+        .map(|mmap| (mmap.addr, (mmap.addr + mmap.len).min(MAX_MEMORY)))
+        .filter(|(start, end)| start < end)
+}
+
+impl Allocator {
+    // `reserved` lists the physical ranges already in use (kernel image, modules...)
+    pub fn new(mmaps: &[Mmap], reserved: &[(usize, usize)]) -> Result<Self, ()> {
+        let top = available(mmaps).map(|(_, end)| end).max().ok_or(())?;
+        let frames = top as usize / FRAME_SIZE;
+        let words = frames.div_ceil(32);
+        let size = words * 4 + frames * 2;
+
+        // the bitmap and the counts live in the first available range that
+        // can hold them
+        let mut placement = None;
+        for (start, end) in available(mmaps) {
+            let mut start = (start.max(0x100000) as usize).next_multiple_of(FRAME_SIZE);
+            while let Some(&(_, e)) = reserved
+                .iter()
+                .find(|&&(s, e)| start < e && s < start + size)
+            {
+                start = e.next_multiple_of(FRAME_SIZE);
+            }
+            if start + size <= end as usize {
+                placement = Some(start);
+                break;
+            }
+        }
+        let start = placement.ok_or(())?;
+
+        let bitmap = unsafe { core::slice::from_raw_parts_mut(start as *mut u32, words) };
+        bitmap.fill(u32::MAX);
+        let refs =
+            unsafe { core::slice::from_raw_parts_mut((start + words * 4) as *mut u16, frames) };
+        refs.fill(0);
+        let mut allocator = Self {
+            bitmap,
+            refs,
+            frames,
+            total: 0,
+            free: 0,
+            next: 0,
+        };
+        for (start, end) in available(mmaps) {
+            let first = (start as usize).div_ceil(FRAME_SIZE);
+            let last = end as usize / FRAME_SIZE;
+            for frame in first..last {
+                allocator.release(frame);
+                allocator.total += 1;
+            }
+        }
+        // the first MiB is left to the BIOS, VGA memory and friends
+        allocator.reserve(0, 0x100000);
+        for &(start, end) in reserved {
+            allocator.reserve(start, end);
+        }
+        allocator.reserve(start, start + size);
+        Ok(allocator)
+    }
+
+    fn is_used(&self, frame: usize) -> bool {
+        self.bitmap[frame / 32] & (1 << (frame % 32)) != 0
+    }
+
+    fn take(&mut self, frame: usize) {
+        self.bitmap[frame / 32] |= 1 << (frame % 32);
+        self.free -= 1;
+    }
+
+    fn release(&mut self, frame: usize) {
+        self.bitmap[frame / 32] &= !(1 << (frame % 32));
+        self.free += 1;
+    }
+
+    pub fn reserve(&mut self, start: usize, end: usize) {
+        let last = end.div_ceil(FRAME_SIZE).min(self.frames);
+        for frame in start / FRAME_SIZE..last {
+            if !self.is_used(frame) {
+                self.take(frame);
+            }
+        }
+    }
+
+    // returns the physical address of a free frame
+    pub fn alloc(&mut self) -> Option<usize> {
+        self.alloc_contiguous(1)
+    }
+
+    pub fn alloc_contiguous(&mut self, count: usize) -> Option<usize> {
+        if count == 0 || self.free < count {
+            return None;
+        }
+        let mut run = 0;
+        for i in 0..self.frames {
+            let frame = (self.next + i) % self.frames;
+            // a run can't wrap around the end of memory
+            if frame == 0 {
+                run = 0;
+            }
+            if self.is_used(frame) {
+                run = 0;
+                continue;
+            }
+            run += 1;
+            if run == count {
+                let first = frame + 1 - count;
+                for frame in first..=frame {
+                    self.take(frame);
+                    self.refs[frame] = 1;
+                }
+                self.next = frame + 1;
+                return Some(first * FRAME_SIZE);
+            }
+        }
+        None
+    }
+
+    // drops a reference, the frame is only freed with the last one
+    pub fn free(&mut self, addr: usize) {
+        self.free_contiguous(addr, 1)
+    }
+
+    pub fn free_contiguous(&mut self, addr: usize, count: usize) {
+        for frame in addr / FRAME_SIZE..addr / FRAME_SIZE + count {
+            if frame >= self.frames || !self.is_used(frame) {
+                panic!("Double free of frame {:#x}", frame * FRAME_SIZE);
+            }
+            self.refs[frame] = self.refs[frame].saturating_sub(1);
+            if self.refs[frame] == 0 {
+                self.release(frame);
+            }
+        }
+    }
+
+    // one more owner for an allocated frame, each one calls `free`
+    pub fn share(&mut self, addr: usize) {
+        let frame = addr / FRAME_SIZE;
+        if frame >= self.frames || self.refs[frame] == 0 {
+            panic!("Sharing unallocated frame {addr:#x}");
+        }
+        self.refs[frame] += 1;
+    }
+
+    pub fn refs(&self, addr: usize) -> usize {
+        self.refs
+            .get(addr / FRAME_SIZE)
+            .map_or(0, |refs| *refs as usize)
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            total: self.total,
+            free: self.free,
+            used: self.total - self.free,
+        }
+    }
+}
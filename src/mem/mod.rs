@@ -1,4 +1,10 @@
+pub mod frames;
+pub mod paging;
+pub mod shm;
+pub mod space;
+
 use core::fmt;
+use core::ptr::addr_of;
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
@@ -31,3 +37,13 @@ impl fmt::Debug for Mmap {
         )
     }
 }
+
+extern "C" {
+    static kernel_start: u8;
+    static kernel_end: u8;
+}
+
+// physical range occupied by the kernel image, see linker.ld
+pub fn kernel_range() -> (usize, usize) {
+    (addr_of!(kernel_start) as usize, addr_of!(kernel_end) as usize)
+}
@@ -0,0 +1,96 @@
+#![allow(dead_code)]
+
+use core::arch::asm;
+
+use crate::arch::cpu;
+use crate::lazy::LazyMut;
+use crate::mem::frames::FRAME_SIZE;
+use crate::FRAMES;
+
+pub const PAGE_SIZE: usize = FRAME_SIZE;
+pub const ENTRIES: usize = 1024;
+pub const LARGE_PAGE_SIZE: usize = PAGE_SIZE * ENTRIES;
+
+// the kernel sees physical memory and devices identity mapped everywhere but
+// in this window, which belongs to user programs. Frames are all below 1 GiB
+// and devices are expected above 2 GiB
+pub const USER_START: usize = 0x4000_0000;
+pub const USER_END: usize = 0x8000_0000;
+
+pub const PRESENT: u32 = 1 << 0;
+pub const WRITABLE: u32 = 1 << 1;
+pub const USER: u32 = 1 << 2;
+pub const LARGE: u32 = 1 << 7;
+// available to the system: a read-only page shared until someone writes it
+pub const COW: u32 = 1 << 9;
+pub const ADDRESS_MASK: u32 = !0xFFF;
+
+const CPUID_PSE: u32 = 1 << 3;
+const CR4_PSE: u32 = 1 << 4;
+// the kernel faults on read-only pages too, instead of writing in shared frames
+const CR0_WP: u32 = 1 << 16;
+const CR0_PG: u32 = 1 << 31;
+
+// page directory every address space starts from
+static KERNEL_DIRECTORY: LazyMut<usize> = LazyMut::new();
+
+pub type Table = [u32; ENTRIES];
+
+pub fn table(addr: usize) -> &'static mut Table {
+    unsafe { &mut *(addr as *mut Table) }
+}
+
+pub const fn directory_index(addr: usize) -> usize {
+    addr / LARGE_PAGE_SIZE
+}
+
+pub const fn table_index(addr: usize) -> usize {
+    addr / PAGE_SIZE % ENTRIES
+}
+
+pub fn init() {
+    if cpu::cpuid(1).edx & CPUID_PSE == 0 {
+        panic!("4 MiB pages are not supported");
+    }
+    let Some(directory) = FRAMES.get_mut().alloc() else {
+        panic!("Could not allocate the kernel page directory");
+    };
+    let entries = table(directory);
+    for (i, entry) in entries.iter_mut().enumerate() {
+        let addr = i * LARGE_PAGE_SIZE;
+        *entry = if (USER_START..USER_END).contains(&addr) {
+            0
+        } else {
+            addr as u32 | PRESENT | WRITABLE | LARGE
+        };
+    }
+    unsafe { KERNEL_DIRECTORY.init(directory) };
+    unsafe {
+        asm!("mov {0:e}, cr4", "or {0:e}, {1:e}", "mov cr4, {0:e}", out(reg) _, in(reg) CR4_PSE);
+        asm!("mov cr3, {:e}", in(reg) directory as u32);
+        asm!("mov {0:e}, cr0", "or {0:e}, {1:e}", "mov cr0, {0:e}", out(reg) _, in(reg) CR0_PG | CR0_WP);
+    }
+}
+
+pub fn kernel_directory() -> usize {
+    *KERNEL_DIRECTORY.get()
+}
+
+pub fn current() -> usize {
+    let cr3: u32;
+    unsafe { asm!("mov {:e}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags)) };
+    cr3 as usize
+}
+
+// reloading cr3 also flushes the TLB, so it's skipped when nothing changes
+pub fn activate(directory: usize) {
+    if current() != directory {
+        unsafe {
+            asm!("mov cr3, {:e}", in(reg) directory as u32, options(nostack, preserves_flags))
+        };
+    }
+}
+
+pub fn invalidate(addr: usize) {
+    unsafe { asm!("invlpg [{}]", in(reg) addr, options(nostack, preserves_flags)) };
+}
@@ -0,0 +1,169 @@
+#![allow(dead_code)]
+
+use crate::lazy::LazyMut;
+use crate::mem::frames::FRAME_SIZE;
+use crate::utils::fixed::FixedStr;
+use crate::FRAMES;
+
+// named memory that any number of spaces map, each where it likes. A
+// segment lives as long as a region maps it, its name can go before
+pub const MAX_SEGMENTS: usize = 16;
+pub const MAX_PAGES: usize = 256;
+pub const MAX_NAME: usize = 32;
+
+pub type Name = FixedStr<MAX_NAME>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    TooManySegments,
+    NotFound,
+    // no size for a new segment, or more than MAX_PAGES
+    Invalid,
+    NoMemory,
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Stat {
+    pub name: Option<Name>,
+    pub pages: usize,
+    pub maps: usize,
+}
+
+struct Segment {
+    // None once unlinked, the mappings keep it alive
+    name: Option<Name>,
+    // zeroed when the segment is made, each mapped page shares its frame
+    frames: [usize; MAX_PAGES],
+    pages: usize,
+    // regions of any space backed by the segment
+    maps: usize,
+}
+
+static SEGMENTS: LazyMut<[Option<Segment>; MAX_SEGMENTS]> = LazyMut::new();
+
+pub fn init() {
+    unsafe { SEGMENTS.init([const { None }; MAX_SEGMENTS]) };
+}
+
+fn get(segment: usize) -> &'static mut Segment {
+    match SEGMENTS.get_mut()[segment].as_mut() {
+        Some(segment) => segment,
+        None => panic!("Shared memory segment {segment} used after its last unmap"),
+    }
+}
+
+fn find(name: &str) -> Option<usize> {
+    SEGMENTS.get().iter().position(|segment| {
+        segment
+            .as_ref()
+            .is_some_and(|segment| segment.name.is_some_and(|other| other.as_str() == name))
+    })
+}
+
+fn free_frames(frames: &[usize]) {
+    for frame in frames {
+        FRAMES.get_mut().free(*frame);
+    }
+}
+
+// the segment called `name`, made with `pages` zeroed pages if there is none,
+// 0 takes an existing one whatever its size. A new one has no mapping yet:
+// the caller maps it right away or abandons it
+pub fn open(name: &str, pages: usize) -> Result<usize> {
+    if let Some(segment) = find(name) {
+        if pages > get(segment).pages {
+            return Err(Error::Invalid);
+        }
+        return Ok(segment);
+    }
+    if pages == 0 || pages > MAX_PAGES {
+        return Err(Error::Invalid);
+    }
+    let segments = SEGMENTS.get_mut();
+    let index = segments
+        .iter()
+        .position(|segment| segment.is_none())
+        .ok_or(Error::TooManySegments)?;
+    let mut frames = [0; MAX_PAGES];
+    for i in 0..pages {
+        let Some(frame) = FRAMES.get_mut().alloc() else {
+            free_frames(&frames[..i]);
+            return Err(Error::NoMemory);
+        };
+        unsafe { core::ptr::write_bytes(frame as *mut u8, 0, FRAME_SIZE) };
+        frames[i] = frame;
+    }
+    segments[index] = Some(Segment {
+        name: Some(Name::from(name)),
+        frames,
+        pages,
+        maps: 0,
+    });
+    Ok(index)
+}
+
+// frees a segment open made that no region ended up mapping
+pub fn abandon(segment: usize) {
+    if get(segment).maps == 0 {
+        let segment = SEGMENTS.get_mut()[segment].take();
+        if let Some(segment) = segment {
+            free_frames(&segment.frames[..segment.pages]);
+        }
+    }
+}
+
+// the name goes, the memory stays with its mappings
+pub fn unlink(name: &str) -> Result<()> {
+    let segment = find(name).ok_or(Error::NotFound)?;
+    get(segment).name = None;
+    Ok(())
+}
+
+pub fn pages(segment: usize) -> usize {
+    get(segment).pages
+}
+
+// frame of page `index`, with a reference for the caller
+pub fn frame(segment: usize, index: usize) -> Option<usize> {
+    let segment = get(segment);
+    let frame = *segment.frames[..segment.pages].get(index)?;
+    FRAMES.get_mut().share(frame);
+    Some(frame)
+}
+
+// a region starts mapping the segment
+pub fn acquire(segment: usize) {
+    get(segment).maps += 1;
+}
+
+// a region stopped mapping it, the last one frees it
+pub fn release(segment: usize) {
+    let maps = {
+        let segment = get(segment);
+        segment.maps -= 1;
+        segment.maps
+    };
+    if maps == 0 {
+        abandon(segment);
+    }
+}
+
+pub fn stat(segment: usize) -> Stat {
+    let segment = get(segment);
+    Stat {
+        name: segment.name,
+        pages: segment.pages,
+        maps: segment.maps,
+    }
+}
+
+pub fn iter() -> impl Iterator<Item = Stat> {
+    SEGMENTS
+        .get()
+        .iter()
+        .enumerate()
+        .filter(|(_, segment)| segment.is_some())
+        .map(|(i, _)| stat(i))
+}
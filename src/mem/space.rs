@@ -0,0 +1,837 @@
+#![allow(dead_code)]
+
+use crate::fs::{self, File};
+use crate::mem::frames::FRAME_SIZE;
+use crate::mem::paging::{
+    self, COW, LARGE, PAGE_SIZE, PRESENT, USER, USER_END, USER_START, WRITABLE,
+};
+use crate::mem::shm;
+use crate::{FRAMES, VFS};
+
+pub const MAX_REGIONS: usize = 16;
+
+// fixed layout of the user window: the vDSO page on top, the stack right
+// under it, anonymous mappings from the middle and the heap after the program
+pub const VDSO_ADDR: usize = USER_END - PAGE_SIZE;
+pub const STACK_TOP: usize = VDSO_ADDR - PAGE_SIZE;
+pub const STACK_SIZE: usize = 64 * 1024;
+pub const MMAP_BASE: usize = USER_START + (USER_END - USER_START) / 2;
+
+// permissions of a region, the same values as mmap. Every page the program
+// can reach is readable and executable, the CPU doesn't tell them apart
+pub const PROT_NONE: u32 = 0;
+pub const PROT_READ: u32 = 1 << 0;
+pub const PROT_WRITE: u32 = 1 << 1;
+pub const PROT_EXEC: u32 = 1 << 2;
+pub const PROT_ALL: u32 = PROT_READ | PROT_WRITE | PROT_EXEC;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    NoMemory,
+    TooManyRegions,
+    // not page aligned, out of the user window or over another region
+    Invalid,
+    // the file behind a mapping could not be read
+    Io,
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Code,
+    Data,
+    Stack,
+    Heap,
+    Anonymous,
+    File,
+    Shared,
+    Vdso,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Code => "[code]",
+            Kind::Data => "[data]",
+            Kind::Stack => "[stack]",
+            Kind::Heap => "[heap]",
+            Kind::Anonymous => "[anon]",
+            Kind::File => "[file]",
+            Kind::Shared => "[shm]",
+            Kind::Vdso => "[vdso]",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Backing {
+    // zeroed frames allocated on first touch
+    Anonymous,
+    // pages of a file shared through the page cache until written, starting
+    // at a page aligned offset
+    File { file: File, offset: u64 },
+    // pages of a shared memory segment from `index` on, every space sees
+    // the writes of the others
+    Shared { segment: usize, index: usize },
+    // frames mapped upfront that the space doesn't own, like the vDSO
+    Fixed,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    pub kind: Kind,
+    pub prot: u32,
+    pub backing: Backing,
+}
+
+// address space of a user program: its own page directory sharing the kernel
+// mappings, and the regions mapped in the user window
+#[derive(Debug)]
+pub struct Space {
+    directory: usize,
+    regions: [Option<Region>; MAX_REGIONS],
+    heap: usize,
+    brk: usize,
+}
+
+impl From<fs::Error> for Error {
+    fn from(err: fs::Error) -> Self {
+        match err {
+            fs::Error::NoSpace => Error::NoMemory,
+            _ => Error::Io,
+        }
+    }
+}
+
+impl Region {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn contains(&self, addr: usize, len: usize) -> bool {
+        addr >= self.start && addr.checked_add(len).is_some_and(|end| end <= self.end)
+    }
+
+    fn overlaps(&self, start: usize, end: usize) -> bool {
+        start < self.end && self.start < end
+    }
+
+    pub fn readable(&self) -> bool {
+        self.prot & PROT_READ != 0
+    }
+
+    pub fn writable(&self) -> bool {
+        self.prot & PROT_WRITE != 0
+    }
+
+    pub fn executable(&self) -> bool {
+        self.prot & PROT_EXEC != 0
+    }
+
+    fn segment(&self) -> Option<usize> {
+        match self.backing {
+            Backing::Shared { segment, .. } => Some(segment),
+            _ => None,
+        }
+    }
+
+    // frames behind owned regions are freed with them
+    fn owned(&self) -> bool {
+        !matches!(self.backing, Backing::Fixed)
+    }
+
+    // page table flags of one of its pages, shared ones are never writable
+    fn flags(&self, shared: bool) -> u32 {
+        let mut flags = PRESENT;
+        if self.readable() || self.writable() {
+            flags |= USER;
+        }
+        if shared {
+            flags |= COW;
+        } else if self.writable() {
+            flags |= WRITABLE;
+        }
+        flags
+    }
+
+    // the part of the region from `addr` on, with the file offset moved along
+    fn tail(&self, addr: usize) -> Self {
+        let backing = match self.backing {
+            Backing::File { file, offset } => Backing::File {
+                file,
+                offset: offset + (addr - self.start) as u64,
+            },
+            Backing::Shared { segment, index } => Backing::Shared {
+                segment,
+                index: index + (addr - self.start) / PAGE_SIZE,
+            },
+            backing => backing,
+        };
+        Self {
+            start: addr,
+            backing,
+            ..*self
+        }
+    }
+}
+
+impl Space {
+    pub fn new() -> Result<Self> {
+        let directory = FRAMES.get_mut().alloc().ok_or(Error::NoMemory)?;
+        paging::table(directory).copy_from_slice(paging::table(paging::kernel_directory()));
+        let mut space = Self {
+            directory,
+            regions: [None; MAX_REGIONS],
+            heap: USER_START,
+            brk: USER_START,
+        };
+        let (vdso, _) = crate::arch::sysenter::page();
+        if let Err(err) = space.map_frames(VDSO_ADDR, &[vdso], Kind::Vdso) {
+            space.release();
+            return Err(err);
+        }
+        Ok(space)
+    }
+
+    pub fn directory(&self) -> usize {
+        self.directory
+    }
+
+    pub fn activate(&self) {
+        paging::activate(self.directory);
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = &Region> {
+        self.regions.iter().flatten()
+    }
+
+    pub fn contains(&self, addr: usize, len: usize) -> bool {
+        self.regions().any(|region| region.contains(addr, len))
+    }
+
+    // physical address behind a user address
+    pub fn translate(&self, addr: usize) -> Option<usize> {
+        let entry = paging::table(self.directory)[paging::directory_index(addr)];
+        if entry & PRESENT == 0 || entry & LARGE != 0 {
+            return None;
+        }
+        let entry =
+            paging::table((entry & paging::ADDRESS_MASK) as usize)[paging::table_index(addr)];
+        (entry & PRESENT != 0).then_some((entry & paging::ADDRESS_MASK) as usize + addr % PAGE_SIZE)
+    }
+
+    fn entry(&mut self, addr: usize) -> Result<&mut u32> {
+        let directory = paging::table(self.directory);
+        let index = paging::directory_index(addr);
+        if directory[index] & PRESENT == 0 {
+            let table = FRAMES.get_mut().alloc().ok_or(Error::NoMemory)?;
+            paging::table(table).fill(0);
+            directory[index] = table as u32 | PRESENT | WRITABLE | USER;
+        }
+        let table = paging::table((directory[index] & paging::ADDRESS_MASK) as usize);
+        Ok(&mut table[paging::table_index(addr)])
+    }
+
+    fn is_active(&self) -> bool {
+        paging::current() == self.directory
+    }
+
+    fn check(&self, start: usize, end: usize) -> Result<()> {
+        if !start.is_multiple_of(PAGE_SIZE)
+            || !end.is_multiple_of(PAGE_SIZE)
+            || start >= end
+            || start < USER_START
+            || end > USER_END
+            || self.regions().any(|region| region.overlaps(start, end))
+        {
+            return Err(Error::Invalid);
+        }
+        Ok(())
+    }
+
+    // no two regions share a page
+    fn disjoint(&self) -> bool {
+        self.regions().enumerate().all(|(i, a)| {
+            self.regions()
+                .skip(i + 1)
+                .all(|b| !b.overlaps(a.start, a.end))
+        })
+    }
+
+    fn insert(&mut self, region: Region) -> Result<()> {
+        self.check(region.start, region.end)?;
+        let slot = self
+            .regions
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(Error::TooManyRegions)?;
+        *slot = Some(region);
+        debug_assert!(self.disjoint());
+        if let Some(segment) = region.segment() {
+            shm::acquire(segment);
+        }
+        Ok(())
+    }
+
+    // unmaps the pages of a region taken out of the table
+    fn remove(&mut self, region: Region) {
+        self.unmap_pages(region.start, region.end, region.owned());
+        if let Some(segment) = region.segment() {
+            shm::release(segment);
+        }
+    }
+
+    // zeroed memory over `start..end`, both page aligned. Frames are only
+    // allocated when a page is first touched
+    pub fn map(&mut self, start: usize, end: usize, kind: Kind, prot: u32) -> Result<()> {
+        self.insert(Region {
+            start,
+            end,
+            kind,
+            prot,
+            backing: Backing::Anonymous,
+        })
+    }
+
+    // `file` from `offset` over `start..end`, read as pages are touched.
+    // Writes stay private to the space
+    pub fn map_file(
+        &mut self,
+        start: usize,
+        end: usize,
+        prot: u32,
+        file: File,
+        offset: u64,
+    ) -> Result<()> {
+        if !offset.is_multiple_of(PAGE_SIZE as u64) {
+            return Err(Error::Invalid);
+        }
+        self.insert(Region {
+            start,
+            end,
+            kind: Kind::File,
+            prot,
+            backing: Backing::File { file, offset },
+        })
+    }
+
+    // maps frames the space doesn't own, read-only, they are left alone on release
+    pub fn map_frames(&mut self, start: usize, frames: &[usize], kind: Kind) -> Result<()> {
+        let region = Region {
+            start,
+            end: start + frames.len() * PAGE_SIZE,
+            kind,
+            prot: PROT_READ | PROT_EXEC,
+            backing: Backing::Fixed,
+        };
+        self.insert(region)?;
+        for (i, frame) in frames.iter().enumerate() {
+            match self.entry(start + i * PAGE_SIZE) {
+                Ok(entry) => *entry = *frame as u32 | region.flags(false),
+                Err(err) => {
+                    self.unmap(start)?;
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn region(&self, addr: usize) -> Option<Region> {
+        self.regions()
+            .find(|region| region.contains(addr, 1))
+            .copied()
+    }
+
+    // backs the page at `addr` with a frame: a zeroed one or one from the
+    // page cache if it was never touched, a copy of its own on writes if it
+    // is shared copy-on-write. Returns false when there was nothing to do
+    fn fill(&mut self, addr: usize, region: &Region, write: bool) -> Result<bool> {
+        let page = addr - addr % PAGE_SIZE;
+        let active = self.is_active();
+        let entry = self.entry(page)?;
+        if *entry & PRESENT == 0 {
+            *entry = match region.backing {
+                Backing::Anonymous => {
+                    let frame = FRAMES.get_mut().alloc().ok_or(Error::NoMemory)?;
+                    unsafe { core::ptr::write_bytes(frame as *mut u8, 0, FRAME_SIZE) };
+                    frame as u32 | region.flags(false)
+                }
+                Backing::File { file, offset } => {
+                    let index = (offset + (page - region.start) as u64) / PAGE_SIZE as u64;
+                    let frame = fs::pages::get(VFS.get_mut(), &file, index)?;
+                    frame as u32 | region.flags(true)
+                }
+                Backing::Shared { segment, index } => {
+                    let index = index + (page - region.start) / PAGE_SIZE;
+                    let frame = shm::frame(segment, index).ok_or(Error::Invalid)?;
+                    frame as u32 | region.flags(false)
+                }
+                Backing::Fixed => return Err(Error::Invalid),
+            };
+            if !write || *entry & COW == 0 {
+                return Ok(true);
+            }
+        } else if !write || *entry & COW == 0 {
+            return Ok(false);
+        }
+
+        let frame = (*entry & paging::ADDRESS_MASK) as usize;
+        let frames = FRAMES.get_mut();
+        // the others already made their own copy
+        if frames.refs(frame) == 1 {
+            *entry = frame as u32 | region.flags(false);
+        } else {
+            let copy = frames.alloc().ok_or(Error::NoMemory)?;
+            unsafe {
+                core::ptr::copy_nonoverlapping(frame as *const u8, copy as *mut u8, PAGE_SIZE)
+            };
+            frames.free(frame);
+            *entry = copy as u32 | region.flags(false);
+        }
+        if active {
+            paging::invalidate(page);
+        }
+        Ok(true)
+    }
+
+    fn allows(region: &Region, write: bool) -> bool {
+        if write {
+            region.writable()
+        } else {
+            region.readable() || region.writable()
+        }
+    }
+
+    // resolves a page fault from an access the region allows, the others
+    // are reported as Invalid
+    pub fn fault(&mut self, addr: usize, write: bool) -> Result<()> {
+        let region = self.region(addr).ok_or(Error::Invalid)?;
+        if !Self::allows(&region, write) {
+            return Err(Error::Invalid);
+        }
+        match self.fill(addr, &region, write)? {
+            true => Ok(()),
+            false => Err(Error::Invalid),
+        }
+    }
+
+    // checks an access to `addr..addr + len` and backs it with frames, so the
+    // kernel can use it from a system call without faulting
+    pub fn prepare(&mut self, addr: usize, len: usize, write: bool) -> Result<()> {
+        let end = addr.checked_add(len).ok_or(Error::Invalid)?;
+        let mut page = addr - addr % PAGE_SIZE;
+        while page < end {
+            let region = self.region(page.max(addr)).ok_or(Error::Invalid)?;
+            if !Self::allows(&region, write) {
+                return Err(Error::Invalid);
+            }
+            self.fill(page, &region, write)?;
+            page += PAGE_SIZE;
+        }
+        Ok(())
+    }
+
+    fn unmap_pages(&mut self, start: usize, end: usize, owned: bool) {
+        let active = self.is_active();
+        for page in (start..end).step_by(PAGE_SIZE) {
+            let Some(frame) = self.translate(page) else {
+                continue;
+            };
+            if owned {
+                FRAMES.get_mut().free(frame);
+            }
+            if let Ok(entry) = self.entry(page) {
+                *entry = 0;
+            }
+            if active {
+                paging::invalidate(page);
+            }
+        }
+    }
+
+    // removes the region starting at `start`
+    pub fn unmap(&mut self, start: usize) -> Result<()> {
+        let slot = self
+            .regions
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|region| region.start == start))
+            .ok_or(Error::Invalid)?;
+        let Some(region) = slot.take() else {
+            return Err(Error::Invalid);
+        };
+        self.remove(region);
+        Ok(())
+    }
+
+    // cuts the region containing `addr` in two, so a region starts there
+    fn split(&mut self, addr: usize) -> Result<()> {
+        let Some(i) = self
+            .regions
+            .iter()
+            .position(|slot| slot.is_some_and(|region| region.start < addr && addr < region.end))
+        else {
+            return Ok(());
+        };
+        let slot = self
+            .regions
+            .iter()
+            .position(|slot| slot.is_none())
+            .ok_or(Error::TooManyRegions)?;
+        if let Some(region) = self.regions[i].as_mut() {
+            let tail = region.tail(addr);
+            region.end = addr;
+            self.regions[slot] = Some(tail);
+            if let Some(segment) = tail.segment() {
+                shm::acquire(segment);
+            }
+        }
+        Ok(())
+    }
+
+    // splits the regions at both ends of `start..end` and returns the slots
+    // of those in between, fixed ones can't be touched
+    fn carve(&mut self, start: usize, end: usize) -> Result<[bool; MAX_REGIONS]> {
+        if !start.is_multiple_of(PAGE_SIZE) || start < USER_START || end > USER_END {
+            return Err(Error::Invalid);
+        }
+        let end = end.next_multiple_of(PAGE_SIZE);
+        let inside = |region: &Region| start <= region.start && region.end <= end;
+        if self
+            .regions()
+            .any(|region| region.overlaps(start, end) && !region.owned())
+        {
+            return Err(Error::Invalid);
+        }
+        self.split(start)?;
+        self.split(end)?;
+        let mut slots = [false; MAX_REGIONS];
+        for (i, slot) in self.regions.iter().enumerate() {
+            slots[i] = slot.as_ref().is_some_and(inside);
+        }
+        Ok(slots)
+    }
+
+    // removes every mapping in `start..end`, parts of regions included
+    pub fn unmap_range(&mut self, start: usize, end: usize) -> Result<()> {
+        let slots = self.carve(start, end)?;
+        for (i, inside) in slots.iter().enumerate() {
+            if let Some(region) = self.regions[i].filter(|_| *inside) {
+                self.regions[i] = None;
+                self.remove(region);
+                if region.kind == Kind::Heap {
+                    self.brk = self.brk.min(region.start).max(self.heap);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // changes the permissions over `start..end`, pages already there follow
+    pub fn protect(&mut self, start: usize, end: usize, prot: u32) -> Result<()> {
+        let end = end.next_multiple_of(PAGE_SIZE);
+        let mut page = start;
+        while page < end {
+            if self.region(page).is_none() {
+                return Err(Error::NoMemory);
+            }
+            page += PAGE_SIZE;
+        }
+        let slots = self.carve(start, end)?;
+        let active = self.is_active();
+        for (i, inside) in slots.iter().enumerate() {
+            let Some(region) = self.regions[i].as_mut().filter(|_| *inside) else {
+                continue;
+            };
+            region.prot = prot;
+            let region = *region;
+            for page in (region.start..region.end).step_by(PAGE_SIZE) {
+                let Ok(entry) = self.entry(page) else {
+                    continue;
+                };
+                if *entry & PRESENT != 0 {
+                    let frame = *entry & paging::ADDRESS_MASK;
+                    *entry = frame | region.flags(*entry & COW != 0);
+                    if active {
+                        paging::invalidate(page);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // copies `data` at a user address through the physical frames, so the
+    // space doesn't need to be active
+    pub fn write(&mut self, addr: usize, data: &[u8]) -> Result<()> {
+        let mut done = 0;
+        while done < data.len() {
+            let at = addr + done;
+            let page = self.page_mut(at)?;
+            let from = at % PAGE_SIZE;
+            let len = (PAGE_SIZE - from).min(data.len() - done);
+            page[from..from + len].copy_from_slice(&data[done..done + len]);
+            done += len;
+        }
+        Ok(())
+    }
+
+    // the kernel side of the frame behind a user page, writable even in a
+    // read-only region and no longer shared with anyone
+    pub fn page_mut(&mut self, addr: usize) -> Result<&'static mut [u8]> {
+        let region = self.region(addr).ok_or(Error::Invalid)?;
+        self.fill(addr, &region, true)?;
+        let frame = self
+            .translate(addr - addr % PAGE_SIZE)
+            .ok_or(Error::Invalid)?;
+        Ok(unsafe { core::slice::from_raw_parts_mut(frame as *mut u8, PAGE_SIZE) })
+    }
+
+    // the heap starts empty right after the program
+    pub fn set_heap(&mut self, start: usize) {
+        self.heap = start.next_multiple_of(PAGE_SIZE);
+        self.brk = self.heap;
+    }
+
+    // the heap may be cut in pieces by mprotect and munmap, only the one
+    // ending at the break moves with it
+    fn heap_top(&mut self) -> Option<&mut Region> {
+        let end = self.brk.next_multiple_of(PAGE_SIZE);
+        self.regions
+            .iter_mut()
+            .flatten()
+            .find(|region| region.kind == Kind::Heap && region.end == end)
+    }
+
+    // moves the end of the heap, returns the current end when `addr` can't
+    // be reached so 0 queries it
+    pub fn brk(&mut self, addr: usize) -> usize {
+        if addr < self.heap || addr > MMAP_BASE {
+            return self.brk;
+        }
+        let old_end = self.brk.next_multiple_of(PAGE_SIZE);
+        let new_end = addr.next_multiple_of(PAGE_SIZE);
+        if new_end > old_end {
+            if self.check(old_end, new_end).is_err() {
+                return self.brk;
+            }
+            // a top made read-only keeps its permissions, the rest is new
+            match self.heap_top().filter(|top| top.prot == PROT_ALL) {
+                Some(top) => top.end = new_end,
+                None => {
+                    if self.map(old_end, new_end, Kind::Heap, PROT_ALL).is_err() {
+                        return self.brk;
+                    }
+                }
+            }
+            debug_assert!(self.disjoint());
+        } else if new_end < old_end && self.unmap_range(new_end, old_end).is_err() {
+            return self.brk;
+        }
+        self.brk = addr;
+        self.brk
+    }
+
+    // the first spot from MMAP_BASE where `len` bytes fit
+    fn find_free(&self, len: usize) -> Result<usize> {
+        let mut start = MMAP_BASE;
+        while let Some(region) = self
+            .regions()
+            .find(|region| region.overlaps(start, start + len))
+        {
+            start = region.end;
+        }
+        if start + len > STACK_TOP - STACK_SIZE {
+            return Err(Error::NoMemory);
+        }
+        Ok(start)
+    }
+
+    // maps `len` bytes of zeroed memory, or of `file` from `offset`, at
+    // `addr` if given, replacing what was there, or at a free spot otherwise
+    pub fn mmap(
+        &mut self,
+        addr: Option<usize>,
+        len: usize,
+        prot: u32,
+        file: Option<(File, u64)>,
+    ) -> Result<usize> {
+        if len == 0 || len > USER_END - USER_START {
+            return Err(Error::Invalid);
+        }
+        let len = len.next_multiple_of(PAGE_SIZE);
+        let start = match addr {
+            Some(addr) => {
+                let end = addr.checked_add(len).ok_or(Error::Invalid)?;
+                self.unmap_range(addr, end)?;
+                addr
+            }
+            None => self.find_free(len)?,
+        };
+        match file {
+            Some((file, offset)) => self.map_file(start, start + len, prot, file, offset)?,
+            None => self.map(start, start + len, Kind::Anonymous, prot)?,
+        }
+        Ok(start)
+    }
+
+    // unmaps the page at `addr` and hands its frame over, with the reference
+    // the space had on it. The page is made private first so the frame
+    // belongs to no one else
+    pub fn take_page(&mut self, addr: usize) -> Result<usize> {
+        if !addr.is_multiple_of(PAGE_SIZE) {
+            return Err(Error::Invalid);
+        }
+        let region = self.region(addr).ok_or(Error::Invalid)?;
+        if !region.writable() || region.segment().is_some() {
+            return Err(Error::Invalid);
+        }
+        self.prepare(addr, PAGE_SIZE, true)?;
+        let frame = self.translate(addr).ok_or(Error::Invalid)?;
+        FRAMES.get_mut().share(frame);
+        if let Err(err) = self.unmap_range(addr, addr + PAGE_SIZE) {
+            FRAMES.get_mut().free(frame);
+            return Err(err);
+        }
+        Ok(frame)
+    }
+
+    // maps a frame from take_page read-write at `addr`, or at a free spot,
+    // and returns where. The space owns the frame once it succeeded
+    pub fn place_page(&mut self, addr: Option<usize>, frame: usize) -> Result<usize> {
+        let addr = match addr {
+            Some(addr) => addr,
+            None => self.find_free(PAGE_SIZE)?,
+        };
+        self.map(
+            addr,
+            addr + PAGE_SIZE,
+            Kind::Anonymous,
+            PROT_READ | PROT_WRITE,
+        )?;
+        let flags = PRESENT | USER | WRITABLE;
+        let active = self.is_active();
+        match self.entry(addr) {
+            Ok(entry) => *entry = frame as u32 | flags,
+            Err(err) => {
+                let _ = self.unmap(addr);
+                return Err(err);
+            }
+        }
+        if active {
+            paging::invalidate(addr);
+        }
+        Ok(addr)
+    }
+
+    // all of a shared memory segment at `addr` if given, replacing what was
+    // there, or at a free spot otherwise
+    pub fn map_shared(&mut self, addr: Option<usize>, segment: usize, prot: u32) -> Result<usize> {
+        let len = shm::pages(segment) * PAGE_SIZE;
+        let start = match addr {
+            Some(addr) => {
+                let end = addr.checked_add(len).ok_or(Error::Invalid)?;
+                self.unmap_range(addr, end)?;
+                addr
+            }
+            None => self.find_free(len)?,
+        };
+        self.insert(Region {
+            start,
+            end: start + len,
+            kind: Kind::Shared,
+            prot,
+            backing: Backing::Shared { segment, index: 0 },
+        })?;
+        Ok(start)
+    }
+
+    // the same regions sharing the same frames, for fork. Both sides see
+    // them read-only and copy a page on their first write to it, but for
+    // shared memory. Fixed regions are only the vDSO and the new space maps
+    // it already
+    pub fn fork(&mut self) -> Result<Self> {
+        let mut child = Self::new()?;
+        child.heap = self.heap;
+        child.brk = self.brk;
+        let active = self.is_active();
+        for i in 0..MAX_REGIONS {
+            let Some(region) = self.regions[i].filter(|region| region.owned()) else {
+                continue;
+            };
+            if let Err(err) = child.insert(region) {
+                child.release();
+                return Err(err);
+            }
+            for page in (region.start..region.end).step_by(PAGE_SIZE) {
+                let Some(frame) = self.translate(page) else {
+                    continue;
+                };
+                let cow = region.segment().is_none();
+                let shared = frame as u32 | region.flags(cow);
+                match child.entry(page) {
+                    Ok(entry) => *entry = shared,
+                    Err(err) => {
+                        child.release();
+                        return Err(err);
+                    }
+                }
+                FRAMES.get_mut().share(frame);
+                if !cow {
+                    continue;
+                }
+                if let Ok(entry) = self.entry(page) {
+                    *entry = shared;
+                }
+                if active {
+                    paging::invalidate(page);
+                }
+            }
+        }
+        Ok(child)
+    }
+
+    // lets ring 3 reach the kernel mappings over `start..end`, for the
+    // benchmark running kernel code in user mode, nothing else
+    pub fn expose_kernel(&mut self, start: usize, end: usize) {
+        let directory = paging::table(self.directory);
+        for addr in (start..end).step_by(paging::LARGE_PAGE_SIZE) {
+            directory[paging::directory_index(addr)] |= USER;
+        }
+        directory[paging::directory_index(end - 1)] |= USER;
+    }
+
+    pub fn release(&mut self) {
+        if self.is_active() {
+            paging::activate(paging::kernel_directory());
+        }
+        for i in 0..MAX_REGIONS {
+            if let Some(region) = self.regions[i].take() {
+                if region.owned() {
+                    for page in (region.start..region.end).step_by(PAGE_SIZE) {
+                        if let Some(frame) = self.translate(page) {
+                            FRAMES.get_mut().free(frame);
+                        }
+                    }
+                }
+                if let Some(segment) = region.segment() {
+                    shm::release(segment);
+                }
+            }
+        }
+        let directory = paging::table(self.directory);
+        for entry in
+            &directory[paging::directory_index(USER_START)..paging::directory_index(USER_END)]
+        {
+            if entry & PRESENT != 0 {
+                FRAMES
+                    .get_mut()
+                    .free((entry & paging::ADDRESS_MASK) as usize);
+            }
+        }
+        FRAMES.get_mut().free(self.directory);
+    }
+}
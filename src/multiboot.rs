@@ -72,7 +72,63 @@ const_assert!(@size ApmTable == 20);
 #[derive(Debug)]
 pub struct Vbe;
 
+#[derive(Debug)]
+#[repr(C, packed)]
+pub struct Module {
+    start: u32,
+    end: u32,
+    cmdline: u32,
+    reserved: u32,
+}
+const_assert!(@size Module == 16);
+
+impl Module {
+    pub fn range(&self) -> (usize, usize) {
+        (self.start as usize, self.end as usize)
+    }
+
+    pub fn data(&self) -> &'static [u8] {
+        let (start, end) = self.range();
+        unsafe { core::slice::from_raw_parts(start as *const u8, end - start) }
+    }
+
+    pub fn cmdline(&self) -> Option<&CStr> {
+        (self.cmdline != 0).then_some(unsafe { CStr::from_ptr(self.cmdline as _) })
+    }
+}
+
+fn str_range(s: &CStr) -> (usize, usize) {
+    let start = s.as_ptr() as usize;
+    (start, start + s.count_bytes() + 1)
+}
+
 impl Info {
+    // what the boot loader wrote and is still read once the frame allocator
+    // runs: this structure, the memory map, the module list, the modules and
+    // the strings
+    pub fn ranges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let info = self as *const Self as usize;
+        let mmaps = self.is_flag_set(6).then_some((
+            self.mmpa_addr as usize,
+            (self.mmpa_addr + self.mmap_length) as usize,
+        ));
+        let mods = self.get_mods().map(|(count, addr)| {
+            let len = count as usize * core::mem::size_of::<Module>();
+            (addr as usize, addr as usize + len)
+        });
+        let strings = [self.get_cmdline(), self.get_boot_loader_name()]
+            .into_iter()
+            .flatten()
+            .chain(self.get_modules().iter().filter_map(Module::cmdline))
+            .map(str_range);
+        [(info, info + core::mem::size_of::<Self>())]
+            .into_iter()
+            .chain(mmaps)
+            .chain(mods)
+            .chain(self.get_modules().iter().map(Module::range))
+            .chain(strings)
+    }
+
     pub fn is_flag_set(&self, bit: u32) -> bool {
         self.flags & (1 << bit) != 0
     }
@@ -100,6 +156,15 @@ impl Info {
             .then_some((self.mods_count, self.mods_addr))
     }
 
+    pub fn get_modules(&self) -> &[Module] {
+        match self.get_mods() {
+            Some((count, addr)) => unsafe {
+                core::slice::from_raw_parts(addr as *const Module, count as usize)
+            },
+            None => &[],
+        }
+    }
+
     pub fn get_syms(&self) -> Option<Symbols> {
         match (self.is_flag_set(4), self.is_flag_set(5)) {
             (true, false) => Some(Symbols::AOut {
@@ -0,0 +1,102 @@
+use core::arch::asm;
+use core::fmt::Write;
+
+use crate::arch::{cpu, sysenter};
+use crate::mem::space::{Kind, Space, PROT_ALL, STACK_SIZE, STACK_TOP};
+use crate::syscall::{EXIT, GETPID, WRITE};
+use crate::task;
+use crate::utils::fixed::FixedStr;
+
+const ITERATIONS: u64 = 100_000;
+
+// compares int 0x80 with the vDSO entry from ring 3, the result is
+// written on the serial line
+pub fn spawn() {
+    if let Err(err) = task::spawn("syscall-bench", run, 0) {
+        crate::eprintln!("Could not spawn the syscall benchmark: {err:?}");
+    }
+}
+
+fn run(_: usize) {
+    let mut space = match Space::new() {
+        Ok(space) => space,
+        Err(err) => {
+            crate::eprintln!("Could not set up the syscall benchmark: {err:?}");
+            return;
+        }
+    };
+    // the benchmark is kernel code, ring 3 is let in on the kernel image
+    let (start, end) = crate::mem::kernel_range();
+    space.expose_kernel(start, end);
+    let bottom = STACK_TOP - STACK_SIZE;
+    // cdecl frame: a return address that is never used, then the argument
+    let top = STACK_TOP - 16;
+    let fast = (sysenter::enabled() as u32).to_le_bytes();
+    if let Err(err) = space
+        .map(bottom, STACK_TOP, Kind::Stack, PROT_ALL)
+        .and_then(|_| space.write(top + 4, &fast))
+    {
+        crate::eprintln!("Could not set up the syscall benchmark: {err:?}");
+        space.release();
+        return;
+    }
+    *task::files() = task::files::Files::console();
+    task::enter_user(space, user_main as *const () as u32, top as u32)
+}
+
+// ebx is reserved by the compiler, the first argument is swapped in and out
+fn int80(number: u32, a: u32, b: u32, c: u32) -> u32 {
+    let ret;
+    unsafe {
+        asm!(
+            "xchg {a:e}, ebx",
+            "int 0x80",
+            "xchg {a:e}, ebx",
+            a = inout(reg) a => _,
+            inlateout("eax") number => ret,
+            in("ecx") b,
+            in("edx") c,
+        )
+    };
+    ret
+}
+
+fn vdso(number: u32, a: u32, b: u32, c: u32) -> u32 {
+    let ret;
+    unsafe {
+        asm!(
+            "xchg {a:e}, ebx",
+            "call {entry:e}",
+            "xchg {a:e}, ebx",
+            a = inout(reg) a => _,
+            entry = in(reg) sysenter::entry(),
+            inlateout("eax") number => ret,
+            in("ecx") b,
+            in("edx") c,
+        )
+    };
+    ret
+}
+
+fn measure(syscall: fn(u32, u32, u32, u32) -> u32) -> u64 {
+    let start = cpu::rdtsc();
+    for _ in 0..ITERATIONS {
+        syscall(GETPID, 0, 0, 0);
+    }
+    (cpu::rdtsc() - start) / ITERATIONS
+}
+
+// runs in ring 3, everything it touches must be in its space
+extern "C" fn user_main(fast: u32) -> ! {
+    let int80_cycles = measure(int80);
+    let vdso_cycles = measure(vdso);
+    let path = if fast != 0 { "sysenter" } else { "int 0x80" };
+    let mut line = FixedStr::<128>::default();
+    let _ = writeln!(
+        line,
+        "syscall-bench: int 0x80 {int80_cycles} cycles, vDSO ({path}) {vdso_cycles} cycles per getpid"
+    );
+    int80(WRITE, 2, line.as_ptr() as u32, line.len() as u32);
+    int80(EXIT, 0, 0, 0);
+    unreachable!()
+}
@@ -0,0 +1,728 @@
+#![allow(dead_code)]
+
+pub mod bench;
+
+use crate::arch::usermode;
+use crate::fs;
+use crate::mem::paging::PAGE_SIZE;
+use crate::mem::{shm, space};
+use crate::task::elf::{self, MAX_ARGS};
+use crate::task::files::Object;
+use crate::task::ipc::{self, Message};
+use crate::task::signal::{self, Action};
+use crate::task::{self, files, pipe, process, NoPreempt};
+use crate::VFS;
+
+pub const VECTOR: u8 = 0x80;
+
+// the number goes in eax, up to five arguments in ebx, ecx, edx, esi and
+// edi, the result comes back in eax with errors as negated `Error` values
+pub const EXIT: u32 = 0;
+pub const READ: u32 = 1;
+pub const WRITE: u32 = 2;
+pub const GETPID: u32 = 3;
+pub const SLEEP: u32 = 4;
+pub const BRK: u32 = 5;
+pub const MMAP: u32 = 6;
+pub const FORK: u32 = 7;
+pub const EXEC: u32 = 8;
+pub const WAITPID: u32 = 9;
+pub const GETPPID: u32 = 10;
+pub const OPEN: u32 = 11;
+pub const CLOSE: u32 = 12;
+pub const MUNMAP: u32 = 13;
+pub const MPROTECT: u32 = 14;
+pub const PIPE: u32 = 15;
+pub const DUP2: u32 = 16;
+pub const SIGRETURN: u32 = 17;
+pub const SIGNAL: u32 = 18;
+pub const SIGPROCMASK: u32 = 19;
+pub const KILL: u32 = 20;
+pub const PORT_CREATE: u32 = 21;
+pub const PORT_OPEN: u32 = 22;
+pub const SEND: u32 = 23;
+pub const RECEIVE: u32 = 24;
+pub const CALL: u32 = 25;
+pub const REPLY: u32 = 26;
+pub const SHM_MAP: u32 = 27;
+pub const SHM_UNLINK: u32 = 28;
+
+// waitpid option: return 0 instead of blocking when no child has exited
+pub const WNOHANG: u32 = 1;
+
+// mmap flags, one of MAP_SHARED and MAP_PRIVATE is required. Shared
+// mappings can't be written, nothing would carry the writes to the file
+pub const MAP_SHARED: u32 = 0x01;
+pub const MAP_PRIVATE: u32 = 0x02;
+pub const MAP_FIXED: u32 = 0x10;
+pub const MAP_ANONYMOUS: u32 = 0x20;
+
+// signal handlers that aren't addresses
+pub const SIG_DFL: u32 = 0;
+pub const SIG_IGN: u32 = 1;
+
+// how sigprocmask changes the blocked signals
+pub const SIG_BLOCK: u32 = 0;
+pub const SIG_UNBLOCK: u32 = 1;
+pub const SIG_SETMASK: u32 = 2;
+
+// chunks copied through the kernel stack on their way to or from a file,
+// drivers may hand buffers to DMA, which only knows physical addresses
+const BOUNCE_SIZE: usize = 512;
+
+type Handler = fn(&mut Registers) -> Result<usize>;
+
+const TABLE: [Handler; 29] = [
+    sys_exit,
+    sys_read,
+    sys_write,
+    sys_getpid,
+    sys_sleep,
+    sys_brk,
+    sys_mmap,
+    sys_fork,
+    sys_exec,
+    sys_waitpid,
+    sys_getppid,
+    sys_open,
+    sys_close,
+    sys_munmap,
+    sys_mprotect,
+    sys_pipe,
+    sys_dup2,
+    sys_sigreturn,
+    sys_signal,
+    sys_sigprocmask,
+    sys_kill,
+    sys_port_create,
+    sys_port_open,
+    sys_send,
+    sys_receive,
+    sys_call,
+    sys_reply,
+    sys_shm_map,
+    sys_shm_unlink,
+];
+
+// part of the user ABI, the values never change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Error {
+    NoSys = 1,
+    Fault,
+    BadFd,
+    Invalid,
+    NoMemory,
+    NotFound,
+    NotDir,
+    IsDir,
+    Exists,
+    NotEmpty,
+    ReadOnly,
+    NoSpace,
+    Unsupported,
+    Busy,
+    Loop,
+    Io,
+    TooManyFiles,
+    NoChild,
+    // out of tasks, trying again later may work
+    Again,
+    NoExec,
+    TooBig,
+    // written to a pipe without readers
+    BrokenPipe,
+    // a signal came in while blocked
+    Interrupted,
+    // the other side of an IPC port is gone
+    Closed,
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl From<fs::Error> for Error {
+    fn from(err: fs::Error) -> Self {
+        match err {
+            fs::Error::NotFound => Error::NotFound,
+            fs::Error::NotDir => Error::NotDir,
+            fs::Error::IsDir => Error::IsDir,
+            fs::Error::Exists => Error::Exists,
+            fs::Error::NotEmpty => Error::NotEmpty,
+            fs::Error::ReadOnly => Error::ReadOnly,
+            fs::Error::NoSpace => Error::NoSpace,
+            fs::Error::Invalid => Error::Invalid,
+            fs::Error::Unsupported => Error::Unsupported,
+            fs::Error::Busy => Error::Busy,
+            fs::Error::Loop => Error::Loop,
+            fs::Error::Io => Error::Io,
+        }
+    }
+}
+
+impl From<space::Error> for Error {
+    fn from(err: space::Error) -> Self {
+        match err {
+            space::Error::NoMemory | space::Error::TooManyRegions => Error::NoMemory,
+            space::Error::Invalid => Error::Invalid,
+            space::Error::Io => Error::Io,
+        }
+    }
+}
+
+impl From<task::Error> for Error {
+    fn from(err: task::Error) -> Self {
+        match err {
+            task::Error::TooManyTasks => Error::Again,
+            task::Error::NoMemory => Error::NoMemory,
+            task::Error::NotFound => Error::NotFound,
+            task::Error::NoChild => Error::NoChild,
+            task::Error::Invalid => Error::Invalid,
+            task::Error::Interrupted => Error::Interrupted,
+        }
+    }
+}
+
+impl From<files::Error> for Error {
+    fn from(err: files::Error) -> Self {
+        match err {
+            files::Error::BadFd => Error::BadFd,
+            files::Error::TooManyFiles => Error::TooManyFiles,
+            files::Error::NotFile => Error::Invalid,
+            files::Error::Io(err) => err.into(),
+        }
+    }
+}
+
+impl From<pipe::Error> for Error {
+    fn from(err: pipe::Error) -> Self {
+        match err {
+            pipe::Error::TooManyPipes => Error::TooManyFiles,
+            pipe::Error::NoMemory => Error::NoMemory,
+            pipe::Error::Broken => Error::BrokenPipe,
+            pipe::Error::Interrupted => Error::Interrupted,
+        }
+    }
+}
+
+impl From<ipc::Error> for Error {
+    fn from(err: ipc::Error) -> Self {
+        match err {
+            ipc::Error::TooManyPorts => Error::TooManyFiles,
+            ipc::Error::NotFound => Error::NotFound,
+            ipc::Error::Exists => Error::Exists,
+            ipc::Error::Closed => Error::Closed,
+            ipc::Error::Busy => Error::Busy,
+            ipc::Error::Invalid => Error::Invalid,
+            ipc::Error::NoMemory => Error::NoMemory,
+            ipc::Error::Interrupted => Error::Interrupted,
+        }
+    }
+}
+
+impl From<shm::Error> for Error {
+    fn from(err: shm::Error) -> Self {
+        match err {
+            shm::Error::TooManySegments => Error::NoSpace,
+            shm::Error::NotFound => Error::NotFound,
+            shm::Error::Invalid => Error::Invalid,
+            shm::Error::NoMemory => Error::NoMemory,
+        }
+    }
+}
+
+impl From<elf::Error> for Error {
+    fn from(err: elf::Error) -> Self {
+        match err {
+            elf::Error::Io(err) => err.into(),
+            elf::Error::NotElf | elf::Error::Unsupported | elf::Error::Malformed => Error::NoExec,
+            elf::Error::NoMemory => Error::NoMemory,
+            elf::Error::ArgsTooLong => Error::TooBig,
+        }
+    }
+}
+
+// laid out by syscall.s: pushad, the data segments, then the frame pushed
+// by the CPU on the way from ring 3, or rebuilt the same by sysenter_entry
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct Registers {
+    pub edi: u32,
+    pub esi: u32,
+    pub ebp: u32,
+    pub kernel_esp: u32,
+    pub ebx: u32,
+    pub edx: u32,
+    pub ecx: u32,
+    pub eax: u32,
+    pub gs: u32,
+    pub fs: u32,
+    pub es: u32,
+    pub ds: u32,
+    pub eip: u32,
+    pub cs: u32,
+    pub eflags: u32,
+    pub esp: u32,
+    pub ss: u32,
+}
+const_assert!(@size Registers == 68);
+
+const EFLAGS_RESERVED: u32 = 1 << 1;
+const EFLAGS_IF: u32 = 1 << 9;
+// arithmetic flags, TF, DF, OF and AC, what ring 3 may change by itself
+const EFLAGS_USER: u32 = 0x0004_0DD5;
+
+impl Registers {
+    // a program about to run its first instruction
+    pub fn user(entry: u32, stack: u32) -> Self {
+        let data = usermode::DATA_SELECTOR as u32;
+        Self {
+            gs: data,
+            fs: data,
+            es: data,
+            ds: data,
+            eip: entry,
+            cs: usermode::CODE_SELECTOR as u32,
+            eflags: EFLAGS_RESERVED | EFLAGS_IF,
+            esp: stack,
+            ss: data,
+            ..Self::default()
+        }
+    }
+
+    // saved from ring 3, the CPU pushed esp and ss too
+    pub fn is_user(&self) -> bool {
+        self.cs & 3 == 3
+    }
+
+    // registers handed back by a program can only take it back to ring 3
+    // with interrupts enabled
+    pub fn sanitize(&mut self) {
+        let code = usermode::CODE_SELECTOR as u32;
+        let data = usermode::DATA_SELECTOR as u32;
+        (self.cs, self.ss) = (code, data);
+        (self.ds, self.es, self.fs, self.gs) = (data, data, data, data);
+        self.eflags = self.eflags & EFLAGS_USER | EFLAGS_RESERVED | EFLAGS_IF;
+    }
+}
+
+extern "C" {
+    fn syscall_resume(regs: *const Registers) -> !;
+}
+
+// returns to ring 3 with `regs`, the kernel stack below them is dropped
+pub fn resume(regs: &Registers) -> ! {
+    unsafe { syscall_resume(regs) }
+}
+
+#[no_mangle]
+extern "C" fn syscall_dispatch(regs: &mut Registers) {
+    let ret = match TABLE.get(regs.eax as usize) {
+        Some(handler) => handler(regs),
+        None => Err(Error::NoSys),
+    };
+    regs.eax = match ret {
+        Ok(value) => value as u32,
+        Err(err) => (err as u32).wrapping_neg(),
+    };
+    signal::deliver(regs);
+}
+
+// user buffers are only trusted once they fall in the caller's space, and
+// backed by frames so the kernel never faults on them
+fn prepare(addr: usize, len: usize, write: bool) -> Result<()> {
+    let space = task::space().ok_or(Error::Fault)?;
+    let _guard = NoPreempt::new();
+    match space.prepare(addr, len, write) {
+        Err(space::Error::Invalid) => Err(Error::Fault),
+        result => Ok(result?),
+    }
+}
+
+fn user_slice(addr: u32, len: u32) -> Result<&'static [u8]> {
+    prepare(addr as usize, len as usize, false)?;
+    Ok(unsafe { core::slice::from_raw_parts(addr as *const u8, len as usize) })
+}
+
+fn user_slice_mut(addr: u32, len: u32) -> Result<&'static mut [u8]> {
+    prepare(addr as usize, len as usize, true)?;
+    Ok(unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len as usize) })
+}
+
+// a NUL terminated string of at most `max` bytes
+fn user_str(addr: u32, max: usize) -> Result<&'static str> {
+    let mut len = 0;
+    loop {
+        if len == max {
+            return Err(Error::TooBig);
+        }
+        let at = addr as usize + len;
+        if len == 0 || at.is_multiple_of(PAGE_SIZE) {
+            prepare(at, 1, false)?;
+        }
+        if unsafe { *(at as *const u8) } == 0 {
+            break;
+        }
+        len += 1;
+    }
+    let bytes = unsafe { core::slice::from_raw_parts(addr as *const u8, len) };
+    core::str::from_utf8(bytes).map_err(|_| Error::Invalid)
+}
+
+// a NULL terminated array of strings, a NULL array is an empty one
+fn user_strs<'a>(addr: u32, strs: &'a mut [&'static str; MAX_ARGS]) -> Result<&'a [&'static str]> {
+    if addr == 0 {
+        return Ok(&[]);
+    }
+    for i in 0..=MAX_ARGS {
+        let pointer = user_slice(addr + 4 * i as u32, 4)?;
+        let pointer = u32::from_le_bytes([pointer[0], pointer[1], pointer[2], pointer[3]]);
+        if pointer == 0 {
+            return Ok(&strs[..i]);
+        }
+        if i == MAX_ARGS {
+            break;
+        }
+        strs[i] = user_str(pointer, elf::MAX_ARGS_SIZE)?;
+    }
+    Err(Error::TooBig)
+}
+
+fn sys_exit(regs: &mut Registers) -> Result<usize> {
+    task::exit(process::exited(regs.ebx as u8))
+}
+
+// pipes block, outside of NoPreempt so other tasks keep being scheduled
+fn sys_read(regs: &mut Registers) -> Result<usize> {
+    let buf = user_slice_mut(regs.ecx, regs.edx)?;
+    let file = match task::files().get(regs.ebx as usize)? {
+        Object::File(file) => file,
+        Object::Pipe(end) if !end.write => return Ok(pipe::read(*end, buf)?),
+        Object::Pipe(_) | Object::Port(_) => return Err(Error::BadFd),
+    };
+    let _guard = NoPreempt::new();
+    let mut bounce = [0; BOUNCE_SIZE];
+    let mut done = 0;
+    for chunk in buf.chunks_mut(BOUNCE_SIZE) {
+        let len = VFS.get_mut().read(file, &mut bounce[..chunk.len()])?;
+        chunk[..len].copy_from_slice(&bounce[..len]);
+        done += len;
+        if len < chunk.len() {
+            break;
+        }
+    }
+    Ok(done)
+}
+
+fn sys_write(regs: &mut Registers) -> Result<usize> {
+    let buf = user_slice(regs.ecx, regs.edx)?;
+    let file = match task::files().get(regs.ebx as usize)? {
+        Object::File(file) => file,
+        Object::Pipe(end) if end.write => {
+            let result = pipe::write(*end, buf);
+            if result == Err(pipe::Error::Broken) {
+                signal::raise(signal::SIGPIPE);
+            }
+            return Ok(result?);
+        }
+        Object::Pipe(_) | Object::Port(_) => return Err(Error::BadFd),
+    };
+    let _guard = NoPreempt::new();
+    let mut bounce = [0; BOUNCE_SIZE];
+    let mut done = 0;
+    for chunk in buf.chunks(BOUNCE_SIZE) {
+        bounce[..chunk.len()].copy_from_slice(chunk);
+        let len = VFS.get_mut().write(file, &bounce[..chunk.len()])?;
+        done += len;
+        if len < chunk.len() {
+            break;
+        }
+    }
+    Ok(done)
+}
+
+fn sys_getpid(_regs: &mut Registers) -> Result<usize> {
+    Ok(task::current_id())
+}
+
+fn sys_sleep(regs: &mut Registers) -> Result<usize> {
+    task::sleep_ms(regs.ebx as u64);
+    Ok(0)
+}
+
+fn sys_brk(regs: &mut Registers) -> Result<usize> {
+    let space = task::space().ok_or(Error::Fault)?;
+    let _guard = NoPreempt::new();
+    Ok(space.brk(regs.ebx as usize))
+}
+
+// takes a pointer to addr, len, prot, flags, fd and offset like the old
+// i386 mmap: six arguments don't fit in the registers sysenter leaves
+fn sys_mmap(regs: &mut Registers) -> Result<usize> {
+    let args = user_slice(regs.ebx, 24)?;
+    let mut words = [0u32; 6];
+    for (word, bytes) in words.iter_mut().zip(args.chunks_exact(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    let [addr, len, prot, flags, fd, offset] = words;
+    let shared = flags & MAP_SHARED != 0;
+    if shared == (flags & MAP_PRIVATE != 0) || prot & !space::PROT_ALL != 0 {
+        return Err(Error::Invalid);
+    }
+    if shared && (prot & space::PROT_WRITE != 0 || flags & MAP_ANONYMOUS != 0) {
+        return Err(Error::Unsupported);
+    }
+    let file = if flags & MAP_ANONYMOUS != 0 {
+        None
+    } else {
+        let file = *task::files().file(fd as usize)?;
+        if VFS.get_mut().stat(&file)?.kind != fs::Kind::File {
+            return Err(Error::Invalid);
+        }
+        Some((file, offset as u64))
+    };
+    let addr = (flags & MAP_FIXED != 0).then_some(addr as usize);
+    let space = task::space().ok_or(Error::Fault)?;
+    let _guard = NoPreempt::new();
+    Ok(space.mmap(addr, len as usize, prot, file)?)
+}
+
+fn sys_munmap(regs: &mut Registers) -> Result<usize> {
+    let (addr, len) = (regs.ebx as usize, regs.ecx as usize);
+    let end = addr.checked_add(len).ok_or(Error::Invalid)?;
+    let space = task::space().ok_or(Error::Fault)?;
+    let _guard = NoPreempt::new();
+    space.unmap_range(addr, end)?;
+    Ok(0)
+}
+
+fn sys_mprotect(regs: &mut Registers) -> Result<usize> {
+    let (addr, len, prot) = (regs.ebx as usize, regs.ecx as usize, regs.edx);
+    let end = addr.checked_add(len).ok_or(Error::Invalid)?;
+    if prot & !space::PROT_ALL != 0 {
+        return Err(Error::Invalid);
+    }
+    let space = task::space().ok_or(Error::Fault)?;
+    let _guard = NoPreempt::new();
+    space.protect(addr, end, prot)?;
+    Ok(0)
+}
+
+fn sys_fork(regs: &mut Registers) -> Result<usize> {
+    Ok(process::fork(regs)?)
+}
+
+// path, argv and envp, the last two NULL terminated and possibly NULL
+fn sys_exec(regs: &mut Registers) -> Result<usize> {
+    let path = user_str(regs.ebx, fs::MAX_PATH)?;
+    let mut argv = [""; MAX_ARGS];
+    let mut envp = [""; MAX_ARGS];
+    let argv = user_strs(regs.ecx, &mut argv)?;
+    let envp = user_strs(regs.edx, &mut envp)?;
+    process::exec(regs, path, argv, envp)?;
+    Ok(0)
+}
+
+// pid or -1 for any child, where to store the wait status if not NULL, and
+// options, returns the pid of the child that exited
+fn sys_waitpid(regs: &mut Registers) -> Result<usize> {
+    let pid = match regs.ebx as i32 {
+        -1 => None,
+        pid if pid > 0 => Some(pid as task::Id),
+        _ => return Err(Error::Invalid),
+    };
+    let status = match regs.ecx {
+        0 => None,
+        addr => Some(user_slice_mut(addr, 4)?),
+    };
+    match process::wait(pid, regs.edx & WNOHANG == 0)? {
+        Some((id, code)) => {
+            if let Some(status) = status {
+                status.copy_from_slice(&code.to_le_bytes());
+            }
+            Ok(id)
+        }
+        None => Ok(0),
+    }
+}
+
+fn sys_getppid(_regs: &mut Registers) -> Result<usize> {
+    Ok(process::parent().unwrap_or(0))
+}
+
+fn sys_open(regs: &mut Registers) -> Result<usize> {
+    let path = user_str(regs.ebx, fs::MAX_PATH)?;
+    let _guard = NoPreempt::new();
+    let file = VFS.get_mut().open(path)?;
+    Ok(task::files().open(Object::File(file))?)
+}
+
+fn sys_close(regs: &mut Registers) -> Result<usize> {
+    task::files().close(regs.ebx as usize)?;
+    Ok(0)
+}
+
+// the read end then the write end, in the two ints at ebx
+fn sys_pipe(regs: &mut Registers) -> Result<usize> {
+    let fds = user_slice_mut(regs.ebx, 8)?;
+    let (read, write) = pipe::create()?;
+    let files = task::files();
+    let read = match files.open(Object::Pipe(read)) {
+        Ok(fd) => fd,
+        Err(err) => {
+            pipe::close(read);
+            pipe::close(write);
+            return Err(err.into());
+        }
+    };
+    let write = match files.open(Object::Pipe(write)) {
+        Ok(fd) => fd,
+        Err(err) => {
+            let _ = files.close(read);
+            pipe::close(write);
+            return Err(err.into());
+        }
+    };
+    fds[..4].copy_from_slice(&(read as u32).to_le_bytes());
+    fds[4..].copy_from_slice(&(write as u32).to_le_bytes());
+    Ok(0)
+}
+
+fn sys_dup2(regs: &mut Registers) -> Result<usize> {
+    Ok(task::files().dup2(regs.ebx as usize, regs.ecx as usize)?)
+}
+
+// the frame enter_handler left on the stack, the handler returned from it
+fn sys_sigreturn(regs: &mut Registers) -> Result<usize> {
+    let frame = user_slice(regs.esp.wrapping_sub(4), signal::frame_size() as u32)?;
+    Ok(signal::sigreturn(regs, frame) as usize)
+}
+
+// the handler of a signal, SIG_DFL, SIG_IGN or an address, returns the old one
+fn sys_signal(regs: &mut Registers) -> Result<usize> {
+    let action = match regs.ecx {
+        SIG_DFL => Action::Default,
+        SIG_IGN => Action::Ignore,
+        handler => Action::Handler(handler),
+    };
+    let signal = u8::try_from(regs.ebx).map_err(|_| Error::Invalid)?;
+    Ok(match signal::action(signal, action)? {
+        Action::Default => SIG_DFL,
+        Action::Ignore => SIG_IGN,
+        Action::Handler(handler) => handler,
+    } as usize)
+}
+
+// changes the blocked signals, returns the old mask
+fn sys_sigprocmask(regs: &mut Registers) -> Result<usize> {
+    let set = regs.ecx;
+    let old = match regs.ebx {
+        SIG_BLOCK => signal::mask(|old| old | set),
+        SIG_UNBLOCK => signal::mask(|old| old & !set),
+        SIG_SETMASK => signal::mask(|_| set),
+        _ => return Err(Error::Invalid),
+    };
+    Ok(old as usize)
+}
+
+fn sys_kill(regs: &mut Registers) -> Result<usize> {
+    let signal = u8::try_from(regs.ecx).map_err(|_| Error::Invalid)?;
+    signal::send(regs.ebx as usize, signal)?;
+    Ok(0)
+}
+
+// a port named by the string at ebx, or anonymous if NULL, as a descriptor
+fn sys_port_create(regs: &mut Registers) -> Result<usize> {
+    let name = match regs.ebx {
+        0 => None,
+        addr => Some(user_str(addr, ipc::MAX_NAME + 1)?),
+    };
+    let port = ipc::create(name)?;
+    task::files().open(Object::Port(port)).map_err(|err| {
+        ipc::close(port);
+        err.into()
+    })
+}
+
+fn sys_port_open(regs: &mut Registers) -> Result<usize> {
+    let name = user_str(regs.ebx, ipc::MAX_NAME + 1)?;
+    let port = ipc::open(name)?;
+    task::files().open(Object::Port(port)).map_err(|err| {
+        ipc::close(port);
+        err.into()
+    })
+}
+
+fn port(fd: u32) -> Result<usize> {
+    match task::files().get(fd as usize)? {
+        Object::Port(port) => Ok(*port),
+        _ => Err(Error::BadFd),
+    }
+}
+
+fn read_message(addr: u32) -> Result<Message> {
+    let bytes = user_slice(addr, core::mem::size_of::<Message>() as u32)?;
+    Ok(unsafe { (bytes.as_ptr() as *const Message).read_unaligned() })
+}
+
+fn write_message(addr: u32, message: &Message) -> Result<()> {
+    let bytes = user_slice_mut(addr, core::mem::size_of::<Message>() as u32)?;
+    unsafe { (bytes.as_mut_ptr() as *mut Message).write_unaligned(*message) };
+    Ok(())
+}
+
+// port descriptor in ebx, message in ecx for all of these
+fn sys_send(regs: &mut Registers) -> Result<usize> {
+    let mut message = read_message(regs.ecx)?;
+    ipc::send(port(regs.ebx)?, &mut message, false)?;
+    Ok(0)
+}
+
+fn sys_receive(regs: &mut Registers) -> Result<usize> {
+    // checked before blocking, a message can't be lost on the way out
+    user_slice_mut(regs.ecx, core::mem::size_of::<Message>() as u32)?;
+    let message = ipc::receive(port(regs.ebx)?)?;
+    write_message(regs.ecx, &message)?;
+    Ok(0)
+}
+
+// sends and waits for the reply, written over the message
+fn sys_call(regs: &mut Registers) -> Result<usize> {
+    let mut message = read_message(regs.ecx)?;
+    user_slice_mut(regs.ecx, core::mem::size_of::<Message>() as u32)?;
+    ipc::send(port(regs.ebx)?, &mut message, true)?;
+    write_message(regs.ecx, &message)?;
+    Ok(0)
+}
+
+// the reply token of the call in ebx
+fn sys_reply(regs: &mut Registers) -> Result<usize> {
+    let message = read_message(regs.ecx)?;
+    ipc::reply(regs.ebx as usize, &message)?;
+    Ok(0)
+}
+
+// maps the segment named at ebx, made with ecx bytes if it doesn't exist, 0
+// to only take an existing one, with the permissions in edx. munmap unmaps
+// it, the last unmap frees it
+fn sys_shm_map(regs: &mut Registers) -> Result<usize> {
+    let name = user_str(regs.ebx, shm::MAX_NAME + 1)?;
+    if regs.edx & !space::PROT_ALL != 0 {
+        return Err(Error::Invalid);
+    }
+    let pages = (regs.ecx as usize).div_ceil(PAGE_SIZE);
+    let space = task::space().ok_or(Error::Fault)?;
+    let _guard = NoPreempt::new();
+    let segment = shm::open(name, pages)?;
+    space.map_shared(None, segment, regs.edx).map_err(|err| {
+        shm::abandon(segment);
+        err.into()
+    })
+}
+
+// the name goes, the mappings keep the memory
+fn sys_shm_unlink(regs: &mut Registers) -> Result<usize> {
+    let name = user_str(regs.ebx, shm::MAX_NAME + 1)?;
+    let _guard = NoPreempt::new();
+    shm::unlink(name)?;
+    Ok(0)
+}
@@ -0,0 +1,354 @@
+use crate::fs::{self, File, Kind as FileKind, Vfs};
+use crate::mem::paging::{PAGE_SIZE, USER_START};
+use crate::mem::space::{
+    self, Kind, Space, MMAP_BASE, PROT_EXEC, PROT_READ, PROT_WRITE, STACK_SIZE, STACK_TOP,
+};
+
+const MAGIC: &[u8] = b"\x7FELF";
+const CLASS_32: u8 = 1;
+const DATA_LSB: u8 = 1;
+const VERSION_CURRENT: u8 = 1;
+const TYPE_EXEC: u16 = 2;
+const MACHINE_386: u16 = 3;
+
+const PT_LOAD: u32 = 1;
+const PT_INTERP: u32 = 3;
+const PT_PHDR: u32 = 6;
+
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const AT_NULL: u32 = 0;
+const AT_PHDR: u32 = 3;
+const AT_PHENT: u32 = 4;
+const AT_PHNUM: u32 = 5;
+const AT_PAGESZ: u32 = 6;
+const AT_ENTRY: u32 = 9;
+const AT_SYSINFO: u32 = 32;
+
+pub const MAX_PROGRAM_HEADERS: usize = 16;
+pub const MAX_ARGS: usize = 32;
+// strings and pointers of argv and envp together
+pub const MAX_ARGS_SIZE: usize = 4 * PAGE_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Io(fs::Error),
+    NotElf,
+    // a valid ELF we can't run: 64 bits, big endian, dynamically linked...
+    Unsupported,
+    Malformed,
+    NoMemory,
+    ArgsTooLong,
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl From<fs::Error> for Error {
+    fn from(err: fs::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<space::Error> for Error {
+    fn from(err: space::Error) -> Self {
+        match err {
+            space::Error::NoMemory | space::Error::TooManyRegions => Error::NoMemory,
+            space::Error::Invalid => Error::Malformed,
+            space::Error::Io => Error::Io(fs::Error::Io),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct Header {
+    ident: [u8; 16],
+    typ: u16,
+    machine: u16,
+    version: u32,
+    entry: u32,
+    phoff: u32,
+    shoff: u32,
+    flags: u32,
+    ehsize: u16,
+    phentsize: u16,
+    phnum: u16,
+    shentsize: u16,
+    shnum: u16,
+    shstrndx: u16,
+}
+const_assert!(@size Header == 52);
+
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+struct ProgramHeader {
+    typ: u32,
+    offset: u32,
+    vaddr: u32,
+    paddr: u32,
+    filesz: u32,
+    memsz: u32,
+    flags: u32,
+    align: u32,
+}
+const_assert!(@size ProgramHeader == 32);
+
+// a program ready to run: its address space, where it starts and its stack
+// holding argc, argv, envp and auxv
+#[derive(Debug)]
+pub struct Image {
+    pub space: Space,
+    pub entry: u32,
+    pub stack: u32,
+}
+
+fn read_at(vfs: &mut Vfs, file: &mut File, offset: u64, buf: &mut [u8]) -> Result<()> {
+    vfs.seek(file, offset);
+    let mut done = 0;
+    while done < buf.len() {
+        match vfs.read(file, &mut buf[done..])? {
+            0 => return Err(Error::Malformed),
+            len => done += len,
+        }
+    }
+    Ok(())
+}
+
+impl Header {
+    fn read(vfs: &mut Vfs, file: &mut File) -> Result<Self> {
+        let mut buf = [0; core::mem::size_of::<Header>()];
+        read_at(vfs, file, 0, &mut buf).map_err(|_| Error::NotElf)?;
+        let header = unsafe { core::ptr::read_unaligned(buf.as_ptr() as *const Header) };
+        if &header.ident[..4] != MAGIC {
+            return Err(Error::NotElf);
+        }
+        if header.ident[4] != CLASS_32
+            || header.ident[5] != DATA_LSB
+            || header.ident[6] != VERSION_CURRENT
+            || header.typ != TYPE_EXEC
+            || header.machine != MACHINE_386
+        {
+            return Err(Error::Unsupported);
+        }
+        if header.phentsize as usize != core::mem::size_of::<ProgramHeader>()
+            || header.phnum == 0
+            || header.phnum as usize > MAX_PROGRAM_HEADERS
+        {
+            return Err(Error::Malformed);
+        }
+        Ok(header)
+    }
+}
+
+fn program_headers(
+    vfs: &mut Vfs,
+    file: &mut File,
+    header: &Header,
+) -> Result<[ProgramHeader; MAX_PROGRAM_HEADERS]> {
+    let mut headers = [ProgramHeader::default(); MAX_PROGRAM_HEADERS];
+    let size = core::mem::size_of::<ProgramHeader>();
+    let mut buf = [0; core::mem::size_of::<ProgramHeader>()];
+    for (i, ph) in headers[..header.phnum as usize].iter_mut().enumerate() {
+        read_at(vfs, file, header.phoff as u64 + (i * size) as u64, &mut buf)?;
+        *ph = unsafe { core::ptr::read_unaligned(buf.as_ptr() as *const ProgramHeader) };
+    }
+    Ok(headers)
+}
+
+// a PT_LOAD segment lies within the file and user memory, even an empty one
+// since other headers are found through it. Returns where it ends in memory
+fn check_segment(ph: &ProgramHeader, size: u64) -> Result<usize> {
+    let vaddr = ph.vaddr as usize;
+    let end = vaddr
+        .checked_add(ph.memsz as usize)
+        .ok_or(Error::Malformed)?;
+    if ph.filesz > ph.memsz
+        || ph.offset as u64 + ph.filesz as u64 > size
+        || vaddr < USER_START
+        || end > MMAP_BASE
+    {
+        return Err(Error::Malformed);
+    }
+    Ok(end)
+}
+
+// maps a PT_LOAD segment and copies its bytes from the file, the rest up to
+// its memory size stays zeroed
+fn load_segment(
+    vfs: &mut Vfs,
+    file: &mut File,
+    size: u64,
+    space: &mut Space,
+    ph: &ProgramHeader,
+) -> Result<usize> {
+    let vaddr = ph.vaddr as usize;
+    let end = check_segment(ph, size)?;
+    let start = vaddr - vaddr % PAGE_SIZE;
+    let kind = if ph.flags & PF_X != 0 {
+        Kind::Code
+    } else {
+        Kind::Data
+    };
+    let prot = [(PF_R, PROT_READ), (PF_W, PROT_WRITE), (PF_X, PROT_EXEC)]
+        .iter()
+        .filter(|(flag, _)| ph.flags & flag != 0)
+        .fold(0, |prot, (_, bit)| prot | bit);
+    space.map(start, end.next_multiple_of(PAGE_SIZE), kind, prot)?;
+
+    let mut done = 0;
+    while done < ph.filesz as usize {
+        let addr = vaddr + done;
+        let page = space.page_mut(addr)?;
+        let from = addr % PAGE_SIZE;
+        let len = (PAGE_SIZE - from).min(ph.filesz as usize - done);
+        let offset = ph.offset as u64 + done as u64;
+        read_at(vfs, file, offset, &mut page[from..from + len])?;
+        done += len;
+    }
+    Ok(end)
+}
+
+// where the program headers end up in memory, for AT_PHDR, 0 if they aren't
+// loaded. The PT_LOAD headers were checked already
+fn phdr_addr(header: &Header, headers: &[ProgramHeader]) -> Result<u32> {
+    if let Some(ph) = headers.iter().find(|ph| ph.typ == PT_PHDR) {
+        return Ok(ph.vaddr);
+    }
+    for ph in headers.iter().filter(|ph| ph.typ == PT_LOAD) {
+        let end = ph.offset.checked_add(ph.filesz).ok_or(Error::Malformed)?;
+        if (ph.offset..end).contains(&header.phoff) {
+            return ph
+                .vaddr
+                .checked_add(header.phoff - ph.offset)
+                .ok_or(Error::Malformed);
+        }
+    }
+    Ok(0)
+}
+
+// pushes the strings, then the argc/argv/envp/auxv vector the entry point
+// finds at esp, as the i386 System V ABI lays it out
+fn setup_stack(
+    space: &mut Space,
+    argv: &[&str],
+    envp: &[&str],
+    auxv: &[(u32, u32)],
+) -> Result<u32> {
+    if argv.len() > MAX_ARGS || envp.len() > MAX_ARGS {
+        return Err(Error::ArgsTooLong);
+    }
+    let strings: usize = argv.iter().chain(envp).map(|s| s.len() + 1).sum();
+    let words = 1 + argv.len() + 1 + envp.len() + 1 + 2 * (auxv.len() + 1);
+    if strings + words * 4 + 16 > MAX_ARGS_SIZE {
+        return Err(Error::ArgsTooLong);
+    }
+    space.map(
+        STACK_TOP - STACK_SIZE,
+        STACK_TOP,
+        Kind::Stack,
+        PROT_READ | PROT_WRITE,
+    )?;
+
+    let mut top = STACK_TOP;
+    let mut pointers = [0u32; 2 * MAX_ARGS];
+    for (i, s) in argv.iter().chain(envp).enumerate() {
+        top -= s.len() + 1;
+        space.write(top, s.as_bytes())?;
+        space.write(top + s.len(), &[0])?;
+        pointers[i] = top as u32;
+    }
+
+    let sp = (top - words * 4) & !0xF;
+    let mut at = sp;
+    let mut push = |value: u32| -> Result<()> {
+        space.write(at, &value.to_le_bytes())?;
+        at += 4;
+        Ok(())
+    };
+    push(argv.len() as u32)?;
+    for pointer in &pointers[..argv.len()] {
+        push(*pointer)?;
+    }
+    push(0)?;
+    for pointer in &pointers[argv.len()..argv.len() + envp.len()] {
+        push(*pointer)?;
+    }
+    push(0)?;
+    for (key, value) in auxv.iter().chain(&[(AT_NULL, 0)]) {
+        push(*key)?;
+        push(*value)?;
+    }
+    Ok(sp as u32)
+}
+
+fn load_into(
+    vfs: &mut Vfs,
+    file: &mut File,
+    size: u64,
+    space: &mut Space,
+    argv: &[&str],
+    envp: &[&str],
+) -> Result<(u32, u32)> {
+    let header = Header::read(vfs, file)?;
+    let headers = program_headers(vfs, file, &header)?;
+    let headers = &headers[..header.phnum as usize];
+    if headers.iter().any(|ph| ph.typ == PT_INTERP) {
+        return Err(Error::Unsupported);
+    }
+
+    for ph in headers.iter().filter(|ph| ph.typ == PT_LOAD) {
+        check_segment(ph, size)?;
+    }
+    let mut heap = USER_START;
+    for ph in headers
+        .iter()
+        .filter(|ph| ph.typ == PT_LOAD && ph.memsz > 0)
+    {
+        heap = heap.max(load_segment(vfs, file, size, space, ph)?);
+    }
+    let entry = header.entry as usize;
+    if !space
+        .regions()
+        .any(|region| region.kind == Kind::Code && region.contains(entry, 1))
+    {
+        return Err(Error::Malformed);
+    }
+    space.set_heap(heap);
+
+    let auxv = [
+        (AT_PHDR, phdr_addr(&header, headers)?),
+        (AT_PHENT, header.phentsize as u32),
+        (AT_PHNUM, header.phnum as u32),
+        (AT_PAGESZ, PAGE_SIZE as u32),
+        (AT_ENTRY, header.entry),
+        (AT_SYSINFO, crate::arch::sysenter::entry()),
+    ];
+    let stack = setup_stack(space, argv, envp, &auxv)?;
+    Ok((header.entry, stack))
+}
+
+// loads the executable at `path` in a fresh address space, malformed binaries
+// are reported and leave nothing behind
+pub fn load(vfs: &mut Vfs, path: &str, argv: &[&str], envp: &[&str]) -> Result<Image> {
+    let mut file = vfs.open(path)?;
+    let stat = vfs.stat(&file)?;
+    match stat.kind {
+        FileKind::File => {}
+        FileKind::Dir => return Err(Error::Io(fs::Error::IsDir)),
+        _ => return Err(Error::NotElf),
+    }
+    let mut space = Space::new()?;
+    match load_into(vfs, &mut file, stat.size, &mut space, argv, envp) {
+        Ok((entry, stack)) => Ok(Image {
+            space,
+            entry,
+            stack,
+        }),
+        Err(err) => {
+            space.release();
+            Err(err)
+        }
+    }
+}
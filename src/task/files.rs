@@ -0,0 +1,188 @@
+#![allow(dead_code)]
+
+use crate::fs::{self, File};
+use crate::lazy::LazyMut;
+use crate::task::{ipc, pipe, NoPreempt};
+use crate::VFS;
+
+// descriptors of a process, and open files in the whole system
+pub const MAX_FILES: usize = 16;
+pub const MAX_OPEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    BadFd,
+    TooManyFiles,
+    // the descriptor is a pipe or a port where something else is needed
+    NotFile,
+    Io(fs::Error),
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl From<fs::Error> for Error {
+    fn from(err: fs::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+// what a descriptor reads and writes
+#[derive(Debug, Clone, Copy)]
+pub enum Object {
+    File(File),
+    Pipe(pipe::End),
+    // index of an IPC port
+    Port(usize),
+}
+
+// shared by all the descriptors duplicated or inherited from the one that
+// opened it, so they move the same offset
+#[derive(Debug)]
+struct Open {
+    object: Object,
+    refs: usize,
+}
+
+static OPEN: LazyMut<[Option<Open>; MAX_OPEN]> = LazyMut::new();
+
+pub fn init() {
+    unsafe { OPEN.init([const { None }; MAX_OPEN]) };
+}
+
+// the table is shared by every process, a switch in the middle of a change
+// would let another one see it half done. Closing pipes and ports never blocks
+fn insert(object: Object) -> Result<usize> {
+    let _guard = NoPreempt::new();
+    let open = OPEN.get_mut();
+    let index = open
+        .iter()
+        .position(|slot| slot.is_none())
+        .ok_or(Error::TooManyFiles)?;
+    open[index] = Some(Open { object, refs: 1 });
+    Ok(index)
+}
+
+fn acquire(index: usize) {
+    let _guard = NoPreempt::new();
+    if let Some(open) = OPEN.get_mut()[index].as_mut() {
+        open.refs += 1;
+    }
+}
+
+fn release(index: usize) {
+    let _guard = NoPreempt::new();
+    let slot = &mut OPEN.get_mut()[index];
+    if let Some(open) = slot {
+        open.refs -= 1;
+        if open.refs == 0 {
+            match open.object {
+                Object::File(_) => {}
+                Object::Pipe(end) => pipe::close(end),
+                Object::Port(port) => ipc::close(port),
+            }
+            *slot = None;
+        }
+    }
+}
+
+// descriptor table of a process, each entry points in the open files
+#[derive(Debug)]
+pub struct Files {
+    fds: [Option<usize>; MAX_FILES],
+}
+
+impl Files {
+    pub const fn new() -> Self {
+        Self {
+            fds: [None; MAX_FILES],
+        }
+    }
+
+    // stdin and stderr on the serial line, stdout on the screen if any, the
+    // way processes started by the kernel get them
+    pub fn console() -> Self {
+        let mut files = Self::new();
+        let vfs = VFS.get_mut();
+        let stdout = if vfs.open("/dev/tty0").is_ok() {
+            "/dev/tty0"
+        } else {
+            "/dev/ttyS0"
+        };
+        for (fd, path) in ["/dev/ttyS0", stdout, "/dev/ttyS0"].iter().enumerate() {
+            if let Ok(index) = vfs
+                .open(path)
+                .map_err(Error::from)
+                .and_then(|file| insert(Object::File(file)))
+            {
+                files.fds[fd] = Some(index);
+            }
+        }
+        files
+    }
+
+    // takes the lowest free descriptor
+    pub fn open(&mut self, object: Object) -> Result<usize> {
+        let fd = self
+            .fds
+            .iter()
+            .position(|fd| fd.is_none())
+            .ok_or(Error::TooManyFiles)?;
+        self.fds[fd] = Some(insert(object)?);
+        Ok(fd)
+    }
+
+    pub fn get(&self, fd: usize) -> Result<&'static mut Object> {
+        let index = self.fds.get(fd).copied().flatten().ok_or(Error::BadFd)?;
+        match OPEN.get_mut()[index].as_mut() {
+            Some(open) => Ok(&mut open.object),
+            None => Err(Error::BadFd),
+        }
+    }
+
+    // only for descriptors of files in the VFS
+    pub fn file(&self, fd: usize) -> Result<&'static mut File> {
+        match self.get(fd)? {
+            Object::File(file) => Ok(file),
+            _ => Err(Error::NotFile),
+        }
+    }
+
+    // makes `new` another descriptor for what `old` refers to, closing what
+    // `new` referred to first
+    pub fn dup2(&mut self, old: usize, new: usize) -> Result<usize> {
+        let index = self.fds.get(old).copied().flatten().ok_or(Error::BadFd)?;
+        if new >= MAX_FILES {
+            return Err(Error::BadFd);
+        }
+        if old != new {
+            let _ = self.close(new);
+            acquire(index);
+            self.fds[new] = Some(index);
+        }
+        Ok(new)
+    }
+
+    pub fn close(&mut self, fd: usize) -> Result<()> {
+        let index = self
+            .fds
+            .get_mut(fd)
+            .and_then(|fd| fd.take())
+            .ok_or(Error::BadFd)?;
+        release(index);
+        Ok(())
+    }
+
+    pub fn close_all(&mut self) {
+        for fd in 0..MAX_FILES {
+            let _ = self.close(fd);
+        }
+    }
+
+    // the table of a forked child, sharing every open file with its parent
+    pub fn fork(&self) -> Self {
+        for index in self.fds.iter().flatten() {
+            acquire(*index);
+        }
+        Self { fds: self.fds }
+    }
+}
@@ -0,0 +1,399 @@
+#![allow(dead_code)]
+
+use crate::arch::cpu;
+use crate::lazy::LazyMut;
+use crate::task::{self, current, get_mut, handoff, signal, Id, State, MAX_TASKS};
+use crate::utils::fixed::FixedStr;
+use crate::FRAMES;
+
+// synchronous message passing: a sender blocks until a receiver takes its
+// message from the port, a caller until the receiver replies to it. Ports
+// are reached through descriptors, like files and pipes
+pub const MAX_PORTS: usize = 32;
+pub const MAX_INLINE: usize = 64;
+pub const MAX_NAME: usize = 32;
+
+pub type Name = FixedStr<MAX_NAME>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    TooManyPorts,
+    // no port with that name, or no caller waiting for that reply
+    NotFound,
+    Exists,
+    // the last handle on the port was closed
+    Closed,
+    // someone else is already receiving on the port
+    Busy,
+    // inline payload too long, or a page that can't be moved
+    Invalid,
+    NoMemory,
+    Interrupted,
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// part of the user ABI. `page` is the page aligned address of a page that
+// moves along with the message, 0 for none, and where it ended up once
+// received. `sender` and `reply` are filled in by the kernel, `reply` is the
+// token to answer a call with, 0 after a plain send
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Message {
+    pub tag: u32,
+    pub len: u32,
+    pub page: u32,
+    pub sender: u32,
+    pub reply: u32,
+    pub data: [u8; MAX_INLINE],
+}
+
+impl Message {
+    pub const fn empty() -> Self {
+        Self {
+            tag: 0,
+            len: 0,
+            page: 0,
+            sender: 0,
+            reply: 0,
+            data: [0; MAX_INLINE],
+        }
+    }
+}
+
+// where a task is in an exchange
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Wait {
+    Idle,
+    Send { port: usize, call: bool },
+    Receive { port: usize },
+    Reply { server: Id },
+}
+
+// IPC state of a task: the message it sends, then the one it gets
+#[derive(Debug, Clone, Copy)]
+pub struct Slot {
+    wait: Wait,
+    message: Message,
+    // set by the other side when the exchange failed
+    error: Option<Error>,
+}
+
+impl Slot {
+    pub const fn new() -> Self {
+        Self {
+            wait: Wait::Idle,
+            message: Message::empty(),
+            error: None,
+        }
+    }
+}
+
+struct Port {
+    name: Option<Name>,
+    // handles on the port, see files::Object::Port
+    refs: usize,
+    // tasks blocked sending, oldest first
+    senders: [Id; MAX_TASKS],
+    len: usize,
+    receiver: Option<Id>,
+}
+
+static PORTS: LazyMut<[Option<Port>; MAX_PORTS]> = LazyMut::new();
+
+pub fn init() {
+    unsafe { PORTS.init([const { None }; MAX_PORTS]) };
+}
+
+fn port(index: usize) -> Result<&'static mut Port> {
+    PORTS.get_mut()[index].as_mut().ok_or(Error::Closed)
+}
+
+fn slot(id: Id) -> Option<&'static mut Slot> {
+    get_mut(id).map(|task| &mut task.ipc)
+}
+
+impl Port {
+    fn push(&mut self, id: Id) {
+        self.senders[self.len] = id;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<Id> {
+        if self.len == 0 {
+            return None;
+        }
+        let id = self.senders[0];
+        self.senders.copy_within(1..self.len, 0);
+        self.len -= 1;
+        Some(id)
+    }
+
+    fn remove(&mut self, id: Id) {
+        if let Some(i) = self.senders[..self.len].iter().position(|s| *s == id) {
+            self.senders.copy_within(i + 1..self.len, i);
+            self.len -= 1;
+        }
+    }
+}
+
+// a new port, named so others can open it or reachable through inherited
+// descriptors only
+pub fn create(name: Option<&str>) -> Result<usize> {
+    cpu::without_interrupts(|| {
+        let ports = PORTS.get_mut();
+        if let Some(name) = name {
+            if ports
+                .iter()
+                .flatten()
+                .any(|port| port.name.is_some_and(|other| other.as_str() == name))
+            {
+                return Err(Error::Exists);
+            }
+        }
+        let index = ports
+            .iter()
+            .position(|port| port.is_none())
+            .ok_or(Error::TooManyPorts)?;
+        ports[index] = Some(Port {
+            name: name.map(Name::from),
+            refs: 1,
+            senders: [0; MAX_TASKS],
+            len: 0,
+            receiver: None,
+        });
+        Ok(index)
+    })
+}
+
+// another handle on the port called `name`
+pub fn open(name: &str) -> Result<usize> {
+    cpu::without_interrupts(|| {
+        let ports = PORTS.get_mut();
+        let index = ports
+            .iter()
+            .position(|port| {
+                port.as_ref()
+                    .is_some_and(|port| port.name.is_some_and(|other| other.as_str() == name))
+            })
+            .ok_or(Error::NotFound)?;
+        if let Some(port) = ports[index].as_mut() {
+            port.refs += 1;
+        }
+        Ok(index)
+    })
+}
+
+// the port goes away with its last handle, whoever waits on it fails
+pub fn close(index: usize) {
+    cpu::without_interrupts(|| {
+        let Ok(port) = port(index) else {
+            return;
+        };
+        port.refs -= 1;
+        if port.refs > 0 {
+            return;
+        }
+        while let Some(id) = port.pop().or_else(|| port.receiver.take()) {
+            fail(id, Error::Closed);
+        }
+        PORTS.get_mut()[index] = None;
+    })
+}
+
+// ends the exchange of a blocked task with `error`
+fn fail(id: Id, error: Error) {
+    if let Some(slot) = slot(id) {
+        slot.wait = Wait::Idle;
+        slot.error = Some(error);
+        task::wake(id);
+    }
+}
+
+// copies `message` from `from` into the slot of `to`, moving its page from
+// one space to the other
+fn deliver(from: Id, to: Id, mut message: Message, reply: bool) -> Result<()> {
+    if message.page != 0 {
+        let page = message.page as usize;
+        let (Some(source), Some(target)) = (
+            get_mut(from).and_then(|task| task.space.as_mut()),
+            get_mut(to).and_then(|task| task.space.as_mut()),
+        ) else {
+            return Err(Error::Invalid);
+        };
+        let frame = source.take_page(page).map_err(|_| Error::Invalid)?;
+        match target.place_page(None, frame) {
+            Ok(addr) => message.page = addr as u32,
+            Err(_) => {
+                // put back where it was, the slot it used is free again
+                if source.place_page(Some(page), frame).is_err() {
+                    FRAMES.get_mut().free(frame);
+                }
+                return Err(Error::NoMemory);
+            }
+        }
+    }
+    message.sender = from as u32;
+    message.reply = if reply { from as u32 } else { 0 };
+    if let Some(slot) = slot(to) {
+        slot.message = message;
+    }
+    Ok(())
+}
+
+// blocks the current task while `wait` stays its state, a signal ends the
+// wait with Interrupted after `cancel` undid the registration
+fn block(wait: Wait, cancel: impl FnOnce()) -> Result<()> {
+    let me = current();
+    loop {
+        if me.ipc.wait != wait {
+            return match me.ipc.error.take() {
+                Some(error) => Err(error),
+                None => Ok(()),
+            };
+        }
+        if signal::pending() {
+            me.ipc.wait = Wait::Idle;
+            cancel();
+            return Err(Error::Interrupted);
+        }
+        task::block();
+    }
+}
+
+// sends `message` on `index`, waiting for a reply that replaces it if `call`
+pub fn send(index: usize, message: &mut Message, call: bool) -> Result<()> {
+    if message.len as usize > MAX_INLINE {
+        return Err(Error::Invalid);
+    }
+    cpu::without_interrupts(|| {
+        let me = current().id;
+        let port = port(index)?;
+        if let Some(receiver) = port.receiver {
+            deliver(me, receiver, *message, call)?;
+            port.receiver = None;
+            if let Some(slot) = slot(receiver) {
+                slot.wait = Wait::Idle;
+            }
+            task::wake(receiver);
+            if !call {
+                handoff(State::Ready, receiver);
+                return Ok(());
+            }
+            current().ipc.wait = Wait::Reply { server: receiver };
+            handoff(State::Blocked, receiver);
+        } else {
+            let slot = &mut current().ipc;
+            slot.message = *message;
+            slot.wait = Wait::Send { port: index, call };
+            port.push(me);
+            block(Wait::Send { port: index, call }, || port.remove(me))?;
+            if !call {
+                return Ok(());
+            }
+        }
+        // a receiver took the message and owes a reply now
+        let wait = current().ipc.wait;
+        if let Wait::Reply { .. } = wait {
+            block(wait, || {})?;
+        }
+        *message = current().ipc.message;
+        Ok(())
+    })
+}
+
+// waits for a message on `index`, from the oldest blocked sender if any
+pub fn receive(index: usize) -> Result<Message> {
+    cpu::without_interrupts(|| {
+        let me = current().id;
+        loop {
+            let port = port(index)?;
+            let Some(sender) = port.pop() else {
+                break;
+            };
+            let Some(Slot {
+                wait: Wait::Send { call, .. },
+                message,
+                ..
+            }) = slot(sender).copied()
+            else {
+                continue;
+            };
+            if let Err(error) = deliver(sender, me, message, call) {
+                fail(sender, error);
+                continue;
+            }
+            if let Some(slot) = slot(sender) {
+                if call {
+                    slot.wait = Wait::Reply { server: me };
+                } else {
+                    slot.wait = Wait::Idle;
+                    task::wake(sender);
+                }
+            }
+            return Ok(current().ipc.message);
+        }
+
+        let port = port(index)?;
+        if port.receiver.is_some() {
+            return Err(Error::Busy);
+        }
+        port.receiver = Some(me);
+        current().ipc.wait = Wait::Receive { port: index };
+        block(Wait::Receive { port: index }, || {
+            if let Ok(port) = self::port(index) {
+                port.receiver = None;
+            }
+        })?;
+        Ok(current().ipc.message)
+    })
+}
+
+// forgets an exiting task: the ports stop waiting for it, and so do the
+// callers expecting its reply
+pub(super) fn exit(id: Id) {
+    cpu::without_interrupts(|| {
+        for port in PORTS.get_mut().iter_mut().flatten() {
+            port.remove(id);
+            if port.receiver == Some(id) {
+                port.receiver = None;
+            }
+        }
+        let callers = task::iter()
+            .filter(|task| task.ipc.wait == Wait::Reply { server: id })
+            .map(|task| task.id);
+        let mut ids = [0; MAX_TASKS];
+        let mut len = 0;
+        for caller in callers {
+            ids[len] = caller;
+            len += 1;
+        }
+        for caller in &ids[..len] {
+            fail(*caller, Error::Closed);
+        }
+    })
+}
+
+// answers the call identified by `token`, the caller runs right away
+pub fn reply(token: Id, message: &Message) -> Result<()> {
+    if message.len as usize > MAX_INLINE {
+        return Err(Error::Invalid);
+    }
+    cpu::without_interrupts(|| {
+        let me = current().id;
+        match slot(token) {
+            Some(slot) if slot.wait == Wait::Reply { server: me } => {}
+            _ => return Err(Error::NotFound),
+        }
+        if let Err(error) = deliver(me, token, *message, false) {
+            fail(token, error);
+            return Err(error);
+        }
+        if let Some(slot) = slot(token) {
+            slot.wait = Wait::Idle;
+        }
+        task::wake(token);
+        handoff(State::Ready, token);
+        Ok(())
+    })
+}
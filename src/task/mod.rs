@@ -0,0 +1,611 @@
+#![allow(dead_code)]
+
+use crate::arch::tables::tss;
+use crate::arch::{cpu, sysenter, timer, usermode};
+use crate::lazy::LazyMut;
+use crate::mem::frames::FRAME_SIZE;
+use crate::mem::space::Space;
+use crate::syscall::Registers;
+use crate::utils::fixed::FixedStr;
+use crate::FRAMES;
+
+pub mod elf;
+pub mod files;
+pub mod ipc;
+pub mod pipe;
+pub mod process;
+pub mod signal;
+pub mod sync;
+
+use files::Files;
+
+pub const MAX_TASKS: usize = 32;
+pub const STACK_FRAMES: usize = 4;
+pub const STACK_SIZE: usize = STACK_FRAMES * FRAME_SIZE;
+
+// ticks a task runs before being preempted by one of the same priority
+pub const TIME_SLICE: u32 = 5;
+
+// higher runs first, a task only runs when no higher priority one is ready
+pub const PRIORITY_IDLE: u8 = 0;
+pub const PRIORITY_LOW: u8 = 1;
+pub const PRIORITY_NORMAL: u8 = 2;
+pub const PRIORITY_HIGH: u8 = 3;
+
+pub type Id = usize;
+pub type Entry = fn(usize);
+pub type Name = FixedStr<16>;
+
+extern "C" {
+    fn switch_context(old: *mut Context, new: *const Context);
+    fn task_start();
+}
+
+static TASKS: LazyMut<Tasks> = LazyMut::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    TooManyTasks,
+    NoMemory,
+    NotFound,
+    NoChild,
+    Invalid,
+    // a signal came in while waiting
+    Interrupted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Running,
+    Ready,
+    // waiting for `wake`
+    Blocked,
+    // in the sleep queue until its wake up tick
+    Sleeping,
+    // by a signal, until SIGCONT or SIGKILL
+    Stopped,
+    // exited, its stack is freed by the next task to run
+    Zombie,
+}
+
+impl State {
+    pub fn name(&self) -> &'static str {
+        match self {
+            State::Running => "running",
+            State::Ready => "ready",
+            State::Blocked => "blocked",
+            State::Sleeping => "sleeping",
+            State::Stopped => "stopped",
+            State::Zombie => "zombie",
+        }
+    }
+}
+
+// callee-saved registers, the rest is saved by the caller of switch_context
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+struct Context {
+    ebx: u32,
+    esi: u32,
+    edi: u32,
+    ebp: u32,
+    esp: u32,
+}
+
+#[derive(Debug)]
+pub struct Task {
+    pub id: Id,
+    pub name: Name,
+    pub state: State,
+    pub priority: u8,
+    // timer ticks spent running
+    pub ticks: u64,
+    pub switches: u64,
+    wake_at: u64,
+    slice: u32,
+    context: Context,
+    // base of the stack frames, the boot task keeps the stack from boot.s
+    stack: Option<usize>,
+    entry: Option<(Entry, usize)>,
+    // memory reachable from ring 3, once the task entered user mode
+    space: Option<Space>,
+    // registers a user task starts with, its first run returns to ring 3
+    user_start: Option<Registers>,
+    // the process that waits for this one, tasks without one are reaped as
+    // soon as they exit
+    pub parent: Option<Id>,
+    // wait status once a zombie, see process::exited
+    status: u32,
+    files: Files,
+    signals: signal::Signals,
+    ipc: ipc::Slot,
+}
+
+struct Tasks {
+    list: [Option<Task>; MAX_TASKS],
+    current: usize,
+    next_id: Id,
+    // slots of the sleeping tasks, soonest wake up first
+    sleep_queue: [usize; MAX_TASKS],
+    sleepers: usize,
+    // preemption only happens when no one holds it off
+    preempt_count: usize,
+    need_resched: bool,
+    // adopts the orphans
+    init: Option<Id>,
+}
+
+impl Task {
+    fn new(id: Id, name: &str, priority: u8) -> Self {
+        Self {
+            id,
+            name: Name::from(name),
+            state: State::Ready,
+            priority,
+            ticks: 0,
+            switches: 0,
+            wake_at: 0,
+            slice: TIME_SLICE,
+            context: Context::default(),
+            stack: None,
+            entry: None,
+            space: None,
+            user_start: None,
+            parent: None,
+            status: 0,
+            files: Files::new(),
+            signals: signal::Signals::new(),
+            ipc: ipc::Slot::new(),
+        }
+    }
+
+    // the address space of a process, None for kernel tasks and zombies
+    pub fn space(&self) -> Option<&Space> {
+        self.space.as_ref()
+    }
+}
+
+// the flow running kernel_main becomes the first task
+pub fn init() {
+    files::init();
+    pipe::init();
+    ipc::init();
+    let mut list = [const { None }; MAX_TASKS];
+    list[0] = Some(Task {
+        state: State::Running,
+        ..Task::new(0, "main", PRIORITY_NORMAL)
+    });
+    unsafe {
+        TASKS.init(Tasks {
+            list,
+            current: 0,
+            next_id: 1,
+            sleep_queue: [0; MAX_TASKS],
+            sleepers: 0,
+            preempt_count: 0,
+            need_resched: false,
+            init: None,
+        })
+    };
+    match spawn("idle", idle, 0) {
+        Ok(id) => {
+            let _ = set_priority(id, PRIORITY_IDLE);
+        }
+        Err(err) => panic!("Could not spawn the idle task: {err:?}"),
+    }
+}
+
+// runs when nothing else is ready
+fn idle(_: usize) {
+    loop {
+        cpu::enable_and_halt();
+    }
+}
+
+fn current() -> &'static mut Task {
+    let tasks = TASKS.get_mut();
+    match tasks.list[tasks.current].as_mut() {
+        Some(task) => task,
+        None => panic!("No current task"),
+    }
+}
+
+pub fn current_id() -> Id {
+    current().id
+}
+
+pub fn iter() -> impl Iterator<Item = &'static Task> {
+    TASKS.get().list.iter().flatten()
+}
+
+fn get_mut(id: Id) -> Option<&'static mut Task> {
+    TASKS
+        .get_mut()
+        .list
+        .iter_mut()
+        .flatten()
+        .find(|task| task.id == id)
+}
+
+fn slot(id: Id) -> Result<usize, Error> {
+    TASKS
+        .get()
+        .list
+        .iter()
+        .position(|task| task.as_ref().is_some_and(|task| task.id == id))
+        .ok_or(Error::NotFound)
+}
+
+// frees the stacks of exited tasks, never called on one of those stacks.
+// The slot stays until the parent collects the status, if there is one
+fn reap() {
+    let tasks = TASKS.get_mut();
+    for (i, slot) in tasks.list.iter_mut().enumerate() {
+        if i == tasks.current {
+            continue;
+        }
+        if let Some(Task {
+            state: State::Zombie,
+            stack,
+            parent,
+            ..
+        }) = slot
+        {
+            if let Some(stack) = stack.take() {
+                FRAMES.get_mut().free_contiguous(stack, STACK_FRAMES);
+            }
+            if parent.is_none() {
+                *slot = None;
+            }
+        }
+    }
+}
+
+pub fn spawn(name: &str, entry: Entry, arg: usize) -> Result<Id, Error> {
+    let _guard = NoPreempt::new();
+    reap();
+    let tasks = TASKS.get_mut();
+    let slot = tasks
+        .list
+        .iter()
+        .position(|task| task.is_none())
+        .ok_or(Error::TooManyTasks)?;
+    let stack = FRAMES
+        .get_mut()
+        .alloc_contiguous(STACK_FRAMES)
+        .ok_or(Error::NoMemory)?;
+
+    // switch_context returns into task_start with an aligned empty stack
+    let top = stack + STACK_SIZE - 16;
+    let esp = top - 4;
+    unsafe { (esp as *mut u32).write(task_start as *const () as u32) };
+
+    let id = tasks.next_id;
+    tasks.next_id += 1;
+    tasks.list[slot] = Some(Task {
+        context: Context {
+            esp: esp as u32,
+            ..Context::default()
+        },
+        stack: Some(stack),
+        entry: Some((entry, arg)),
+        ..Task::new(id, name, PRIORITY_NORMAL)
+    });
+    Ok(id)
+}
+
+pub fn set_priority(id: Id, priority: u8) -> Result<(), Error> {
+    let slot = slot(id)?;
+    if let Some(task) = TASKS.get_mut().list[slot].as_mut() {
+        task.priority = priority;
+    }
+    Ok(())
+}
+
+#[no_mangle]
+extern "C" fn task_entry() -> ! {
+    reap();
+    let Some((entry, arg)) = current().entry else {
+        panic!("Task {} has no entry point", current_id());
+    };
+    cpu::enable_interrupts();
+    entry(arg);
+    exit(process::exited(0))
+}
+
+// the highest priority ready task, round-robin among equals starting after current
+fn pick_next(tasks: &Tasks) -> Option<usize> {
+    let mut best: Option<(usize, u8)> = None;
+    for i in 1..=MAX_TASKS {
+        let slot = (tasks.current + i) % MAX_TASKS;
+        let Some(task) = &tasks.list[slot] else {
+            continue;
+        };
+        if task.state == State::Ready && best.is_none_or(|(_, prio)| task.priority > prio) {
+            best = Some((slot, task.priority));
+        }
+    }
+    best.map(|(slot, _)| slot)
+}
+
+// leaves the current task in `state` and runs the next ready one
+fn switch(state: State) {
+    switch_to(state, None)
+}
+
+// leaves the current task in `state` for the task `id` if it is ready, before
+// anything of higher priority: the IPC side waiting for a message gets the
+// CPU its sender gives up
+fn handoff(state: State, id: Id) {
+    switch_to(state, Some(id))
+}
+
+fn switch_to(state: State, prefer: Option<Id>) {
+    let enabled = cpu::interrupts_enabled();
+    cpu::disable_interrupts();
+    let tasks = TASKS.get_mut();
+    let current = tasks.current;
+    tasks.need_resched = false;
+    if let Some(task) = tasks.list[current].as_mut() {
+        task.state = state;
+    }
+
+    let preferred = prefer.and_then(|id| {
+        tasks.list.iter().position(|task| {
+            task.as_ref()
+                .is_some_and(|task| task.id == id && task.state == State::Ready)
+        })
+    });
+    let Some(next) = preferred.or_else(|| pick_next(tasks)) else {
+        // only possible before the idle task exists, keep running
+        if state == State::Zombie {
+            loop {
+                cpu::enable_and_halt();
+            }
+        }
+        if let Some(task) = tasks.list[current].as_mut() {
+            task.state = State::Running;
+        }
+        if enabled {
+            cpu::enable_interrupts();
+        }
+        return;
+    };
+
+    let new = match tasks.list[next].as_mut() {
+        Some(task) => {
+            task.state = State::Running;
+            task.slice = TIME_SLICE;
+            // interrupts from ring 3 land at the top of the task's own stack
+            if let Some(stack) = task.stack {
+                tss::set_kernel_stack((stack + STACK_SIZE) as u32);
+                sysenter::set_kernel_stack((stack + STACK_SIZE) as u32);
+            }
+            // kernel only tasks run on any directory, they all share the kernel
+            if let Some(space) = &task.space {
+                space.activate();
+            }
+            &task.context as *const Context
+        }
+        None => unreachable!(),
+    };
+    if next != current {
+        let old = match tasks.list[current].as_mut() {
+            Some(task) => {
+                task.switches += 1;
+                &mut task.context as *mut Context
+            }
+            None => panic!("No current task"),
+        };
+        tasks.current = next;
+        unsafe { switch_context(old, new) };
+        // back on this task, another one may have exited meanwhile
+        reap();
+    }
+    if enabled {
+        cpu::enable_interrupts();
+    }
+}
+
+pub fn yield_now() {
+    switch(State::Ready);
+}
+
+// parks the current task until someone calls `wake` on it, interrupts must be
+// disabled since the registration in a wait queue and this call
+pub fn block() {
+    switch(State::Blocked);
+}
+
+// makes a blocked task ready again, safe to call from IRQ handlers
+pub fn wake(id: Id) -> bool {
+    let Ok(slot) = slot(id) else {
+        return false;
+    };
+    let tasks = TASKS.get_mut();
+    let current_priority = tasks.list[tasks.current]
+        .as_ref()
+        .map_or(0, |task| task.priority);
+    match tasks.list[slot].as_mut() {
+        Some(task) if task.state == State::Blocked => {
+            task.state = State::Ready;
+            if task.priority > current_priority {
+                tasks.need_resched = true;
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+// ends the current task with a wait status for its parent, see process::exited
+pub fn exit(status: u32) -> ! {
+    {
+        let _guard = NoPreempt::new();
+        let task = current();
+        if let Some(mut space) = task.space.take() {
+            space.release();
+        }
+        task.files.close_all();
+        ipc::exit(task.id);
+        task.status = status;
+        process::orphan(task.id);
+        if let Some(parent) = task.parent {
+            let _ = signal::send(parent, signal::SIGCHLD);
+        }
+    }
+    // the parent only looks once this one is a zombie
+    cpu::disable_interrupts();
+    process::EXITED.wake_all();
+    switch(State::Zombie);
+    unreachable!("Zombie task {} was scheduled", current_id());
+}
+
+// runs the current task in ring 3 from now on, its kernel stack is only
+// used again by interrupts and exceptions raised from user code
+pub fn enter_user(space: Space, entry: u32, stack: u32) -> ! {
+    let task = current();
+    if task.stack.is_none() {
+        panic!("Task {} has no kernel stack to come back to", task.id);
+    }
+    space.activate();
+    task.space = Some(space);
+    usermode::enter(entry, stack)
+}
+
+fn start_user(_: usize) {
+    let task = current();
+    let (Some(space), Some(regs)) = (&task.space, task.user_start.take()) else {
+        panic!("Task {} has no program to run", task.id);
+    };
+    space.activate();
+    crate::syscall::resume(&regs)
+}
+
+// runs `space` in a new task from `regs`, the space is released if the task
+// can't be created
+fn spawn_space(name: &str, mut space: Space, regs: Registers) -> Result<&'static mut Task, Error> {
+    let _guard = NoPreempt::new();
+    let id = match spawn(name, start_user, 0) {
+        Ok(id) => id,
+        Err(err) => {
+            space.release();
+            return Err(err);
+        }
+    };
+    let Some(task) = get_mut(id) else {
+        unreachable!("Task {id} vanished before its first run");
+    };
+    task.space = Some(space);
+    task.user_start = Some(regs);
+    Ok(task)
+}
+
+// runs a loaded program in a new process with the consoles as standard files
+pub fn spawn_user(name: &str, image: elf::Image) -> Result<Id, Error> {
+    let _guard = NoPreempt::new();
+    let regs = Registers::user(image.entry, image.stack);
+    let task = spawn_space(name, image.space, regs)?;
+    task.files = Files::console();
+    Ok(task.id)
+}
+
+pub fn space() -> Option<&'static mut Space> {
+    current().space.as_mut()
+}
+
+pub fn files() -> &'static mut Files {
+    &mut current().files
+}
+
+pub fn sleep_ms(ms: u64) {
+    sleep_ticks(ms.div_ceil(1000 / timer::HZ as u64));
+}
+
+pub fn sleep_ticks(ticks: u64) {
+    let enabled = cpu::interrupts_enabled();
+    cpu::disable_interrupts();
+    let tasks = TASKS.get_mut();
+    let current = tasks.current;
+    let wake_at = timer::ticks() + ticks.max(1);
+    if let Some(task) = tasks.list[current].as_mut() {
+        task.wake_at = wake_at;
+    }
+    // insertion in the sorted queue
+    let mut i = tasks.sleepers;
+    while i > 0 && wake_of(tasks, tasks.sleep_queue[i - 1]) > wake_at {
+        tasks.sleep_queue[i] = tasks.sleep_queue[i - 1];
+        i -= 1;
+    }
+    tasks.sleep_queue[i] = current;
+    tasks.sleepers += 1;
+    switch(State::Sleeping);
+    if enabled {
+        cpu::enable_interrupts();
+    }
+}
+
+fn wake_of(tasks: &Tasks, slot: usize) -> u64 {
+    tasks.list[slot].as_ref().map_or(0, |task| task.wake_at)
+}
+
+// called on every timer interrupt, with interrupts disabled
+pub fn tick() {
+    if !TASKS.is_init() {
+        return;
+    }
+    let tasks = TASKS.get_mut();
+    let now = timer::ticks();
+    let current_priority = match tasks.list[tasks.current].as_mut() {
+        Some(task) => {
+            task.ticks += 1;
+            task.slice = task.slice.saturating_sub(1);
+            if task.slice == 0 {
+                tasks.need_resched = true;
+            }
+            task.priority
+        }
+        None => 0,
+    };
+
+    let mut woken = 0;
+    while woken < tasks.sleepers && wake_of(tasks, tasks.sleep_queue[woken]) <= now {
+        if let Some(task) = tasks.list[tasks.sleep_queue[woken]].as_mut() {
+            task.state = State::Ready;
+            if task.priority > current_priority {
+                tasks.need_resched = true;
+            }
+        }
+        woken += 1;
+    }
+    tasks.sleep_queue.copy_within(woken..tasks.sleepers, 0);
+    tasks.sleepers -= woken;
+
+    if tasks.need_resched && tasks.preempt_count == 0 {
+        switch(State::Ready);
+    }
+}
+
+// holds off preemption while alive, for code touching shared kernel state
+pub struct NoPreempt(());
+
+impl NoPreempt {
+    pub fn new() -> Self {
+        if let Some(tasks) = TASKS.try_get_mut() {
+            tasks.preempt_count += 1;
+        }
+        Self(())
+    }
+}
+
+impl Drop for NoPreempt {
+    fn drop(&mut self) {
+        if let Some(tasks) = TASKS.try_get_mut() {
+            tasks.preempt_count -= 1;
+            // catch up with a preemption that was held off
+            if tasks.preempt_count == 0 && tasks.need_resched && cpu::interrupts_enabled() {
+                yield_now();
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+#![allow(dead_code)]
+
+use crate::arch::cpu;
+use crate::lazy::LazyMut;
+use crate::mem::frames::FRAME_SIZE;
+use crate::task::signal;
+use crate::task::sync::WaitQueue;
+use crate::FRAMES;
+
+// one-way channels between processes, a frame of buffered bytes each
+pub const MAX_PIPES: usize = 16;
+pub const BUFFER_SIZE: usize = FRAME_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    TooManyPipes,
+    NoMemory,
+    // written with no reader left
+    Broken,
+    // a signal came in before anything was transferred
+    Interrupted,
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// one side of a pipe, held by a single open file shared by descriptors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct End {
+    pipe: usize,
+    pub write: bool,
+}
+
+struct Pipe {
+    buffer: usize,
+    // the bytes in the buffer start at `head` and wrap around
+    head: usize,
+    len: usize,
+    readers: usize,
+    writers: usize,
+    // readers waiting for bytes, writers waiting for room
+    readable: WaitQueue,
+    writable: WaitQueue,
+}
+
+static PIPES: LazyMut<[Option<Pipe>; MAX_PIPES]> = LazyMut::new();
+
+pub fn init() {
+    unsafe { PIPES.init([const { None }; MAX_PIPES]) };
+}
+
+fn get(end: End) -> &'static mut Pipe {
+    match PIPES.get_mut()[end.pipe].as_mut() {
+        Some(pipe) => pipe,
+        None => panic!("Pipe {} used after its last close", end.pipe),
+    }
+}
+
+impl Pipe {
+    fn data(&mut self) -> &'static mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.buffer as *mut u8, BUFFER_SIZE) }
+    }
+
+    fn pop(&mut self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.len);
+        let data = self.data();
+        for (i, byte) in buf[..len].iter_mut().enumerate() {
+            *byte = data[(self.head + i) % BUFFER_SIZE];
+        }
+        self.head = (self.head + len) % BUFFER_SIZE;
+        self.len -= len;
+        len
+    }
+
+    fn push(&mut self, buf: &[u8]) -> usize {
+        let len = buf.len().min(BUFFER_SIZE - self.len);
+        let tail = self.head + self.len;
+        let data = self.data();
+        for (i, byte) in buf[..len].iter().enumerate() {
+            data[(tail + i) % BUFFER_SIZE] = *byte;
+        }
+        self.len += len;
+        len
+    }
+}
+
+// a new pipe with one reader and one writer, returns both ends
+pub fn create() -> Result<(End, End)> {
+    cpu::without_interrupts(|| {
+        let pipes = PIPES.get_mut();
+        let pipe = pipes
+            .iter()
+            .position(|slot| slot.is_none())
+            .ok_or(Error::TooManyPipes)?;
+        let buffer = FRAMES.get_mut().alloc().ok_or(Error::NoMemory)?;
+        pipes[pipe] = Some(Pipe {
+            buffer,
+            head: 0,
+            len: 0,
+            readers: 1,
+            writers: 1,
+            readable: WaitQueue::new(),
+            writable: WaitQueue::new(),
+        });
+        Ok((End { pipe, write: false }, End { pipe, write: true }))
+    })
+}
+
+// the pipe goes away with its last end, the other side learns about a side
+// closing: readers see the end of file, writers a broken pipe
+pub fn close(end: End) {
+    cpu::without_interrupts(|| {
+        let pipe = get(end);
+        if end.write {
+            pipe.writers -= 1;
+            pipe.readable.wake_all();
+        } else {
+            pipe.readers -= 1;
+            pipe.writable.wake_all();
+        }
+        if pipe.readers == 0 && pipe.writers == 0 {
+            FRAMES.get_mut().free(pipe.buffer);
+            PIPES.get_mut()[end.pipe] = None;
+        }
+    })
+}
+
+// blocks until there are bytes to read, returns 0 once every writer is gone
+pub fn read(end: End, buf: &mut [u8]) -> Result<usize> {
+    if buf.is_empty() {
+        return Ok(0);
+    }
+    cpu::without_interrupts(|| {
+        let pipe = get(end);
+        pipe.readable
+            .wait_until(|| pipe.len > 0 || pipe.writers == 0 || signal::pending());
+        if pipe.len == 0 && pipe.writers > 0 {
+            return Err(Error::Interrupted);
+        }
+        let len = pipe.pop(buf);
+        pipe.writable.wake_all();
+        Ok(len)
+    })
+}
+
+// blocks until all of `buf` is in the pipe, writes smaller than the buffer
+// never get mixed with others. What went in is returned when the last
+// reader leaves or a signal comes in halfway
+pub fn write(end: End, buf: &[u8]) -> Result<usize> {
+    cpu::without_interrupts(|| {
+        let pipe = get(end);
+        let mut done = 0;
+        while done < buf.len() {
+            let atomic = buf.len() <= BUFFER_SIZE;
+            let room = |pipe: &Pipe| {
+                (atomic && BUFFER_SIZE - pipe.len >= buf.len())
+                    || (!atomic && pipe.len < BUFFER_SIZE)
+            };
+            pipe.writable
+                .wait_until(|| pipe.readers == 0 || room(pipe) || signal::pending());
+            if pipe.readers == 0 || !room(pipe) {
+                return match (done, pipe.readers) {
+                    (0, 0) => Err(Error::Broken),
+                    (0, _) => Err(Error::Interrupted),
+                    (done, _) => Ok(done),
+                };
+            }
+            done += pipe.push(&buf[done..]);
+            pipe.readable.wake_all();
+        }
+        Ok(done)
+    })
+}
@@ -0,0 +1,140 @@
+#![allow(dead_code)]
+
+use crate::arch::cpu;
+use crate::syscall::Registers;
+use crate::task::sync::WaitQueue;
+use crate::task::{self, current, elf, signal, Error, Id, NoPreempt, State, Task};
+use crate::task::{STACK_FRAMES, TASKS};
+use crate::{FRAMES, VFS};
+
+// a process is a task running a user program: its id is the pid, it has a
+// parent waiting for its exit status and a table of file descriptors
+
+// parents waiting for any of their children to exit
+pub static EXITED: WaitQueue = WaitQueue::new();
+
+// wait status of a process that exited by itself, as waitpid reports it
+pub const fn exited(code: u8) -> u32 {
+    (code as u32) << 8
+}
+
+// wait status of a process killed by `signal`
+pub const fn killed(signal: u8) -> u32 {
+    (signal & 0x7F) as u32
+}
+
+// wait status of a process killed by `signal` that left a core dump
+pub const fn dumped(signal: u8) -> u32 {
+    killed(signal) | 0x80
+}
+
+// orphans are adopted by this process, usually the first program started
+pub fn set_init(id: Id) {
+    TASKS.get_mut().init = Some(id);
+}
+
+pub fn parent() -> Option<Id> {
+    current().parent
+}
+
+// hands the children of `id` to init, or lets them be reaped on exit if
+// there is no init left to wait for them
+pub(super) fn orphan(id: Id) {
+    let tasks = TASKS.get_mut();
+    let init = tasks.init.filter(|init| {
+        *init != id
+            && tasks
+                .list
+                .iter()
+                .flatten()
+                .any(|task| task.id == *init && task.state != State::Zombie)
+    });
+    for task in tasks.list.iter_mut().flatten() {
+        if task.parent == Some(id) {
+            task.parent = init;
+        }
+    }
+}
+
+// duplicates the calling process, the child returns from the same system
+// call with 0 while the parent gets the pid of the child
+pub fn fork(regs: &Registers) -> Result<Id, Error> {
+    let _guard = NoPreempt::new();
+    let parent = current();
+    let Some(space) = &mut parent.space else {
+        return Err(Error::NotFound);
+    };
+    let space = space.fork().map_err(|_| Error::NoMemory)?;
+    let (id, name, priority) = (parent.id, parent.name, parent.priority);
+    let child = task::spawn_space(name.as_str(), space, Registers { eax: 0, ..*regs })?;
+    child.parent = Some(id);
+    child.priority = priority;
+    child.files = parent.files.fork();
+    child.signals = parent.signals.fork();
+    Ok(child.id)
+}
+
+// replaces the program of the calling process, `regs` are those it returns
+// to ring 3 with. The old program is kept until the new one is loaded, so
+// `argv` and `envp` may point in it and a failure leaves it running
+pub fn exec(regs: &mut Registers, path: &str, argv: &[&str], envp: &[&str]) -> elf::Result<()> {
+    let _guard = NoPreempt::new();
+    let image = elf::load(VFS.get_mut(), path, argv, envp)?;
+    let task = current();
+    if let Some(mut space) = task.space.replace(image.space) {
+        space.release();
+    }
+    if let Some(space) = &task.space {
+        space.activate();
+    }
+    let name = path.rsplit('/').next().unwrap_or(path);
+    task.name = task::Name::from(name);
+    task.signals.exec();
+    *regs = Registers::user(image.entry, image.stack);
+    Ok(())
+}
+
+// frees an exited child and returns its pid and wait status
+fn collect(slot: &mut Option<Task>) -> (Id, u32) {
+    let Some(mut task) = slot.take() else {
+        unreachable!("Collecting an empty slot");
+    };
+    if let Some(stack) = task.stack.take() {
+        FRAMES.get_mut().free_contiguous(stack, STACK_FRAMES);
+    }
+    (task.id, task.status)
+}
+
+// waits for a child to exit, `pid` or any if None. Without `block`, returns
+// None right away when no child has exited yet
+pub fn wait(pid: Option<Id>, block: bool) -> Result<Option<(Id, u32)>, Error> {
+    let me = task::current_id();
+    cpu::without_interrupts(|| loop {
+        let tasks = TASKS.get_mut();
+        let mut children = tasks
+            .list
+            .iter_mut()
+            .filter(|slot| {
+                slot.as_ref().is_some_and(|task| {
+                    task.parent == Some(me) && pid.is_none_or(|pid| task.id == pid)
+                })
+            })
+            .peekable();
+        if children.peek().is_none() {
+            return Err(Error::NoChild);
+        }
+        if let Some(slot) = children.find(|slot| {
+            slot.as_ref()
+                .is_some_and(|task| task.state == State::Zombie)
+        }) {
+            return Ok(Some(collect(slot)));
+        }
+        if !block {
+            return Ok(None);
+        }
+        if signal::pending() {
+            return Err(Error::Interrupted);
+        }
+        EXITED.sleep();
+    })
+}
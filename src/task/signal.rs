@@ -0,0 +1,327 @@
+#![allow(dead_code)]
+
+use core::fmt::Write;
+
+use crate::arch::{cpu, sysenter};
+use crate::syscall::Registers;
+use crate::task::{self, current, process, Error, Id, State, TASKS};
+
+// numbers as on Linux i386, a set of them is a mask with bit `signal`
+pub const SIGHUP: u8 = 1;
+pub const SIGINT: u8 = 2;
+pub const SIGQUIT: u8 = 3;
+pub const SIGILL: u8 = 4;
+pub const SIGTRAP: u8 = 5;
+pub const SIGABRT: u8 = 6;
+pub const SIGBUS: u8 = 7;
+pub const SIGFPE: u8 = 8;
+pub const SIGKILL: u8 = 9;
+pub const SIGUSR1: u8 = 10;
+pub const SIGSEGV: u8 = 11;
+pub const SIGUSR2: u8 = 12;
+pub const SIGPIPE: u8 = 13;
+pub const SIGALRM: u8 = 14;
+pub const SIGTERM: u8 = 15;
+pub const SIGCHLD: u8 = 17;
+pub const SIGCONT: u8 = 18;
+pub const SIGSTOP: u8 = 19;
+pub const SIGTSTP: u8 = 20;
+pub const COUNT: usize = 32;
+
+// neither blocked nor handled by the program
+const UNCATCHABLE: u32 = 1 << SIGKILL | 1 << SIGSTOP;
+const STOPS: u32 = 1 << SIGSTOP | 1 << SIGTSTP;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Default,
+    Ignore,
+    // user address of a `void handler(int)`
+    Handler(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Default {
+    Terminate,
+    // terminates with a dump of the registers in the kernel log
+    Core,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+fn default(signal: u8) -> Default {
+    match signal {
+        SIGQUIT | SIGILL | SIGTRAP | SIGABRT | SIGBUS | SIGFPE | SIGSEGV => Default::Core,
+        SIGCHLD => Default::Ignore,
+        SIGSTOP | SIGTSTP => Default::Stop,
+        SIGCONT => Default::Continue,
+        _ => Default::Terminate,
+    }
+}
+
+// signal state of a process
+#[derive(Debug, Clone, Copy)]
+pub struct Signals {
+    pending: u32,
+    blocked: u32,
+    actions: [Action; COUNT],
+}
+
+impl Signals {
+    pub const fn new() -> Self {
+        Self {
+            pending: 0,
+            blocked: 0,
+            actions: [Action::Default; COUNT],
+        }
+    }
+
+    // a forked child keeps the actions and mask, not what is pending
+    pub fn fork(&self) -> Self {
+        Self {
+            pending: 0,
+            ..*self
+        }
+    }
+
+    // the handlers are gone with the program, what was ignored stays so
+    pub fn exec(&mut self) {
+        for action in self.actions.iter_mut() {
+            if let Action::Handler(_) = action {
+                *action = Action::Default;
+            }
+        }
+    }
+
+    fn deliverable(&self) -> u32 {
+        self.pending & (!self.blocked | UNCATCHABLE)
+    }
+}
+
+// what a handler finds above its stack, sigreturn takes `regs` and `blocked`
+// back from there
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct Frame {
+    ret: u32,
+    signal: u32,
+    regs: Registers,
+    blocked: u32,
+}
+
+fn valid(signal: u8) -> bool {
+    signal > 0 && (signal as usize) < COUNT
+}
+
+// makes `signal` pending in the process `id`. A stopped process goes on with
+// SIGCONT and SIGKILL, a blocked one is woken so interruptible waits notice
+pub fn send(id: Id, signal: u8) -> Result<(), Error> {
+    if signal as usize >= COUNT {
+        return Err(Error::Invalid);
+    }
+    cpu::without_interrupts(|| {
+        let tasks = TASKS.get_mut();
+        let task = tasks
+            .list
+            .iter_mut()
+            .flatten()
+            .find(|task| task.id == id && task.state != State::Zombie)
+            .ok_or(Error::NotFound)?;
+        // 0 only checks the process is there
+        if signal == 0 {
+            return Ok(());
+        }
+        let signals = &mut task.signals;
+        match signal {
+            SIGCONT => signals.pending &= !STOPS,
+            SIGSTOP | SIGTSTP => signals.pending &= !(1 << SIGCONT),
+            _ => {}
+        }
+        // SIGCONT goes on even when ignored or handled
+        if (signal == SIGCONT || signal == SIGKILL) && task.state == State::Stopped {
+            task.state = State::Ready;
+        }
+        let action = signals.actions[signal as usize];
+        if action == Action::Ignore
+            || (action == Action::Default && default(signal) == Default::Ignore)
+        {
+            return Ok(());
+        }
+        signals.pending |= 1 << signal;
+        if task.state == State::Blocked && signals.deliverable() != 0 {
+            task::wake(id);
+        }
+        Ok(())
+    })
+}
+
+// `signal` for the current process
+pub fn raise(signal: u8) {
+    let _ = send(task::current_id(), signal);
+}
+
+// raises a signal caused by the current instruction: blocking or ignoring it
+// would only fault again, so the default action takes over in that case
+pub fn force(signal: u8) {
+    let signals = &mut current().signals;
+    let bit = 1 << signal;
+    if signals.blocked & bit != 0 || signals.actions[signal as usize] == Action::Ignore {
+        signals.blocked &= !bit;
+        signals.actions[signal as usize] = Action::Default;
+    }
+    signals.pending |= bit;
+}
+
+// true when the current process has a signal to take care of, for waits
+// that give up with Interrupted
+pub fn pending() -> bool {
+    current().signals.deliverable() != 0
+}
+
+pub fn action(signal: u8, action: Action) -> Result<Action, Error> {
+    if !valid(signal) || (UNCATCHABLE & 1 << signal != 0 && action != Action::Default) {
+        return Err(Error::Invalid);
+    }
+    let signals = &mut current().signals;
+    let old = core::mem::replace(&mut signals.actions[signal as usize], action);
+    // an ignored signal is dropped even when already pending
+    if action == Action::Ignore || (action == Action::Default && default(signal) == Default::Ignore)
+    {
+        signals.pending &= !(1 << signal);
+    }
+    Ok(old)
+}
+
+// changes the blocked mask with `update` and returns the old one
+pub fn mask(update: impl FnOnce(u32) -> u32) -> u32 {
+    let signals = &mut current().signals;
+    let old = signals.blocked;
+    signals.blocked = update(old) & !UNCATCHABLE & !1;
+    old
+}
+
+fn dump(signal: u8, regs: &Registers) {
+    crate::eprintln!(
+        "Task {} killed by signal {signal} at {:#010x}, core dumped:",
+        task::current_id(),
+        regs.eip
+    );
+    crate::eprintln!(
+        "  eax {:08x} ebx {:08x} ecx {:08x} edx {:08x}",
+        regs.eax,
+        regs.ebx,
+        regs.ecx,
+        regs.edx
+    );
+    crate::eprintln!(
+        "  esi {:08x} edi {:08x} ebp {:08x} esp {:08x}",
+        regs.esi,
+        regs.edi,
+        regs.ebp,
+        regs.esp
+    );
+    crate::eprintln!("  eip {:08x} eflags {:08x}", regs.eip, regs.eflags);
+}
+
+// the lowest deliverable signal, taken off the pending ones
+fn next() -> Option<u8> {
+    cpu::without_interrupts(|| {
+        let signals = &mut current().signals;
+        let deliverable = signals.deliverable();
+        if deliverable == 0 {
+            return None;
+        }
+        let signal = deliverable.trailing_zeros() as u8;
+        signals.pending &= !(1 << signal);
+        Some(signal)
+    })
+}
+
+fn stop() {
+    cpu::without_interrupts(|| {
+        // a SIGCONT may have come in since
+        if current().signals.pending & 1 << SIGCONT == 0 {
+            task::switch(State::Stopped);
+        }
+    })
+}
+
+// runs the user handler of `signal` from `regs`, those are saved on the
+// user stack for sigreturn. False when the stack can't hold them
+fn enter_handler(regs: &mut Registers, signal: u8, handler: u32) -> bool {
+    let Some(space) = task::space() else {
+        return false;
+    };
+    let signals = &mut current().signals;
+    let frame = Frame {
+        ret: sysenter::sigreturn(),
+        signal: signal as u32,
+        regs: *regs,
+        blocked: signals.blocked,
+    };
+    let size = core::mem::size_of::<Frame>();
+    // the argument is 16 bytes aligned as the System V ABI wants on calls
+    let Some(addr) = (regs.esp as usize)
+        .checked_sub(size)
+        .and_then(|top| (top & !15).checked_sub(4))
+    else {
+        return false;
+    };
+    let bytes = unsafe { core::slice::from_raw_parts(&frame as *const Frame as *const u8, size) };
+    {
+        let _guard = task::NoPreempt::new();
+        if space.prepare(addr, size, true).is_err() || space.write(addr, bytes).is_err() {
+            return false;
+        }
+    }
+    // no nesting of the same signal until the handler returns
+    signals.blocked |= 1 << signal;
+    regs.esp = addr as u32;
+    regs.eip = handler;
+    true
+}
+
+// acts on the pending signals before `regs` go back to ring 3: default
+// actions are taken right away, the first handled one changes `regs` so the
+// program runs its handler
+pub fn deliver(regs: &mut Registers) {
+    while let Some(signal) = next() {
+        let action = current().signals.actions[signal as usize];
+        match (action, default(signal)) {
+            (Action::Ignore, _) | (Action::Default, Default::Ignore | Default::Continue) => {}
+            (Action::Handler(handler), _) => {
+                if enter_handler(regs, signal, handler) {
+                    return;
+                }
+                // nowhere to run it, as a fault on the stack would do
+                dump(SIGSEGV, regs);
+                task::exit(process::dumped(SIGSEGV));
+            }
+            (Action::Default, Default::Stop) => stop(),
+            (Action::Default, Default::Terminate) => task::exit(process::killed(signal)),
+            (Action::Default, Default::Core) => {
+                dump(signal, regs);
+                task::exit(process::dumped(signal));
+            }
+        }
+    }
+}
+
+// back from a handler: the registers and mask saved by enter_handler are
+// restored, with what makes sense from ring 3 only. Returns eax since the
+// dispatcher writes it back
+pub fn sigreturn(regs: &mut Registers, frame: &[u8]) -> u32 {
+    let mut saved = [0u8; core::mem::size_of::<Frame>()];
+    saved.copy_from_slice(frame);
+    let frame = unsafe { (saved.as_ptr() as *const Frame).read_unaligned() };
+    *regs = frame.regs;
+    regs.sanitize();
+    mask(|_| frame.blocked);
+    regs.eax
+}
+
+// size of what sigreturn reads from the user stack, from its esp - 4
+pub const fn frame_size() -> usize {
+    core::mem::size_of::<Frame>()
+}
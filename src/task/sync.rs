@@ -0,0 +1,279 @@
+#![allow(dead_code)]
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+
+use crate::arch::cpu;
+use crate::task::{self, Id, MAX_TASKS};
+
+// everything here runs on a single CPU: a critical section is simply a
+// stretch with interrupts disabled, and a task blocks without leaving it
+
+// tasks blocked on some event, woken in FIFO order
+pub struct WaitQueue {
+    inner: UnsafeCell<Waiters>,
+}
+
+struct Waiters {
+    ids: [Id; MAX_TASKS],
+    len: usize,
+}
+
+unsafe impl Sync for WaitQueue {}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            inner: UnsafeCell::new(Waiters {
+                ids: [0; MAX_TASKS],
+                len: 0,
+            }),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn waiters(&self) -> &mut Waiters {
+        unsafe { &mut *self.inner.get() }
+    }
+
+    // blocks the current task until the next wake up, interrupts must be
+    // disabled since the condition was checked or the wake up could be missed
+    pub fn sleep(&self) {
+        let waiters = self.waiters();
+        let id = task::current_id();
+        // a task is in at most one queue, so there is always room
+        if waiters.len < MAX_TASKS {
+            waiters.ids[waiters.len] = id;
+            waiters.len += 1;
+        }
+        task::block();
+        // woken by something else than this queue, as a signal does
+        self.remove(id);
+    }
+
+    // takes `id` out of the queue, returns false if it wasn't there
+    pub fn remove(&self, id: Id) -> bool {
+        let waiters = self.waiters();
+        let Some(i) = waiters.ids[..waiters.len]
+            .iter()
+            .position(|other| *other == id)
+        else {
+            return false;
+        };
+        waiters.ids.copy_within(i + 1..waiters.len, i);
+        waiters.len -= 1;
+        true
+    }
+
+    // blocks until `cond` holds, it is evaluated with interrupts disabled
+    pub fn wait_until(&self, mut cond: impl FnMut() -> bool) {
+        cpu::without_interrupts(|| {
+            while !cond() {
+                self.sleep();
+            }
+        })
+    }
+
+    fn pop(&self) -> Option<Id> {
+        let waiters = self.waiters();
+        if waiters.len == 0 {
+            return None;
+        }
+        let id = waiters.ids[0];
+        waiters.ids.copy_within(1..waiters.len, 0);
+        waiters.len -= 1;
+        Some(id)
+    }
+
+    // wakes the oldest waiter still blocked, returns false if there was none
+    pub fn wake_one(&self) -> bool {
+        cpu::without_interrupts(|| {
+            while let Some(id) = self.pop() {
+                if task::wake(id) {
+                    return true;
+                }
+            }
+            false
+        })
+    }
+
+    pub fn wake_all(&self) -> usize {
+        let mut count = 0;
+        while self.wake_one() {
+            count += 1;
+        }
+        count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters().len == 0
+    }
+}
+
+// counting semaphore, `up` can be called from IRQ handlers
+pub struct Semaphore {
+    count: UnsafeCell<usize>,
+    queue: WaitQueue,
+}
+
+unsafe impl Sync for Semaphore {}
+
+impl Semaphore {
+    pub const fn new(count: usize) -> Self {
+        Self {
+            count: UnsafeCell::new(count),
+            queue: WaitQueue::new(),
+        }
+    }
+
+    pub fn down(&self) {
+        let count = self.count.get();
+        cpu::without_interrupts(|| {
+            self.queue.wait_until(|| unsafe { *count } > 0);
+            unsafe { *count -= 1 };
+        })
+    }
+
+    pub fn try_down(&self) -> bool {
+        let count = self.count.get();
+        cpu::without_interrupts(|| unsafe {
+            if *count == 0 {
+                return false;
+            }
+            *count -= 1;
+            true
+        })
+    }
+
+    pub fn up(&self) {
+        cpu::without_interrupts(|| {
+            unsafe { *self.count.get() += 1 };
+            self.queue.wake_one();
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        unsafe { *self.count.get() }
+    }
+}
+
+// sleeping mutex, the task waiting for it is blocked instead of spinning,
+// it can't be taken from IRQ handlers
+pub struct Mutex<T> {
+    owner: UnsafeCell<Option<Id>>,
+    queue: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for Mutex<T> {}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            owner: UnsafeCell::new(None),
+            queue: WaitQueue::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        let owner = self.owner.get();
+        let id = task::current_id();
+        cpu::without_interrupts(|| {
+            if unsafe { *owner } == Some(id) {
+                panic!("Task {id} locked a mutex it already holds");
+            }
+            self.queue.wait_until(|| unsafe { (*owner).is_none() });
+            unsafe { *owner = Some(id) };
+        });
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let owner = self.owner.get();
+        cpu::without_interrupts(|| unsafe {
+            if (*owner).is_some() {
+                return None;
+            }
+            *owner = Some(task::current_id());
+            Some(MutexGuard { mutex: self })
+        })
+    }
+
+    pub fn owner(&self) -> Option<Id> {
+        unsafe { *self.owner.get() }
+    }
+
+    fn unlock(&self) {
+        cpu::without_interrupts(|| {
+            unsafe { *self.owner.get() = None };
+            self.queue.wake_one();
+        })
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+// waits for a change of the state protected by a mutex
+pub struct Condvar {
+    queue: WaitQueue,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            queue: WaitQueue::new(),
+        }
+    }
+
+    // releases the mutex while blocked and takes it back before returning,
+    // nothing can be notified in between since interrupts stay disabled
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex;
+        cpu::without_interrupts(|| {
+            drop(guard);
+            self.queue.sleep();
+            mutex.lock()
+        })
+    }
+
+    pub fn wait_while<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut cond: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while cond(&mut guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    pub fn notify_one(&self) -> bool {
+        self.queue.wake_one()
+    }
+
+    pub fn notify_all(&self) -> usize {
+        self.queue.wake_all()
+    }
+}
@@ -4,3 +4,4 @@
 pub mod asserts;
 
 pub mod bits;
+pub mod fixed;
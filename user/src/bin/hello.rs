@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+use cairn::alloc::string::String;
+use cairn::alloc::vec::Vec;
+use cairn::{env, println, HEAP};
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut args: Vec<&str> = env::args().collect();
+    let name = if args.is_empty() {
+        "hello"
+    } else {
+        args.remove(0)
+    };
+    println!("Hello from {name}, pid {}!", cairn::process::getpid());
+    println!("HOME is {}", env::var("HOME").unwrap_or("unset"));
+
+    // the arguments sorted and joined on the heap
+    args.sort_unstable();
+    let mut joined = String::new();
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            joined.push_str(", ");
+        }
+        joined.push_str(arg);
+    }
+    println!("{} argument(s): {joined}", args.len());
+
+    let squares: Vec<u32> = (1..=10).map(|n| n * n).collect();
+    println!("squares: {squares:?}, sum {}", squares.iter().sum::<u32>());
+    println!("{} bytes of heap in use", HEAP.used());
+    0
+}
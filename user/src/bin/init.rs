@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+use core::ffi::CStr;
+
+use cairn::process::{self, Status};
+use cairn::{eprintln, println};
+
+// run one after the other, each with its argv
+const PROGRAMS: &[(&CStr, &[&CStr])] = &[(
+    c"/initrd/bin/hello",
+    &[c"hello", c"cairn", c"stones", c"pile"],
+)];
+
+const ENV: &[&CStr] = &[c"HOME=/", c"PATH=/initrd/bin"];
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("init: running as pid {}", process::getpid());
+    for (path, argv) in PROGRAMS {
+        let name = path.to_str().unwrap_or("?");
+        let pid = match process::spawn(path, argv, ENV) {
+            Ok(pid) => pid,
+            Err(err) => {
+                eprintln!("init: could not start {name}: {err:?}");
+                continue;
+            }
+        };
+        match process::wait(Some(pid)) {
+            Ok((_, Status::Exited(code))) => println!("init: {name} exited with {code}"),
+            Ok((_, Status::Killed { signal, core })) => {
+                let core = if core { ", core dumped" } else { "" };
+                println!("init: {name} killed by signal {signal}{core}")
+            }
+            Err(err) => eprintln!("init: could not wait for {name}: {err:?}"),
+        }
+    }
+    // orphans are left to the kernel
+    while process::wait(None).is_ok() {}
+    0
+}
@@ -0,0 +1,47 @@
+// what the compiler calls for copies and comparisons. The byte stores are
+// volatile so LLVM doesn't turn the loops back into calls to themselves
+use core::ptr::{read_volatile, write_volatile};
+
+#[no_mangle]
+pub unsafe extern "C" fn memset(ptr: *mut u8, val: i32, num: usize) -> *mut u8 {
+    for i in 0..num {
+        write_volatile(ptr.add(i), val as u8);
+    }
+    ptr
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memcpy(dst: *mut u8, src: *const u8, num: usize) -> *mut u8 {
+    for i in 0..num {
+        write_volatile(dst.add(i), read_volatile(src.add(i)));
+    }
+    dst
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memmove(dst: *mut u8, src: *const u8, num: usize) -> *mut u8 {
+    if (dst as usize) < (src as usize) {
+        return memcpy(dst, src, num);
+    }
+    for i in (0..num).rev() {
+        write_volatile(dst.add(i), read_volatile(src.add(i)));
+    }
+    dst
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memcmp(ptr1: *const u8, ptr2: *const u8, num: usize) -> i32 {
+    for i in 0..num {
+        let a = read_volatile(ptr1.add(i));
+        let b = read_volatile(ptr2.add(i));
+        if a != b {
+            return a as i32 - b as i32;
+        }
+    }
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn bcmp(ptr1: *const u8, ptr2: *const u8, num: usize) -> i32 {
+    memcmp(ptr1, ptr2, num)
+}
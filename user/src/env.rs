@@ -0,0 +1,99 @@
+use core::ffi::CStr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// what the kernel put on the initial stack, kept by start
+static ARGC: AtomicUsize = AtomicUsize::new(0);
+static ARGV: AtomicUsize = AtomicUsize::new(0);
+static ENVP: AtomicUsize = AtomicUsize::new(0);
+static AUXV: AtomicUsize = AtomicUsize::new(0);
+
+// auxiliary vector keys, as the kernel's ELF loader passes them
+pub const AT_PHDR: u32 = 3;
+pub const AT_PHENT: u32 = 4;
+pub const AT_PHNUM: u32 = 5;
+pub const AT_PAGESZ: u32 = 6;
+pub const AT_ENTRY: u32 = 9;
+pub const AT_SYSINFO: u32 = 32;
+
+// `stack` points at argc, followed by the NULL terminated argv and envp
+// then the key/value pairs of auxv
+pub(crate) unsafe fn init(stack: *const u32) {
+    let argc = *stack as usize;
+    let argv = stack.add(1);
+    let envp = argv.add(argc + 1);
+    let mut auxv = envp;
+    while *auxv != 0 {
+        auxv = auxv.add(1);
+    }
+    ARGC.store(argc, Ordering::Relaxed);
+    ARGV.store(argv as usize, Ordering::Relaxed);
+    ENVP.store(envp as usize, Ordering::Relaxed);
+    AUXV.store(auxv.add(1) as usize, Ordering::Relaxed);
+}
+
+// strings of a NULL terminated array, those that aren't UTF-8 are skipped
+pub struct Strings {
+    next: *const u32,
+}
+
+impl Iterator for Strings {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.next.is_null() {
+                return None;
+            }
+            let pointer = unsafe { *self.next };
+            if pointer == 0 {
+                return None;
+            }
+            self.next = unsafe { self.next.add(1) };
+            let s = unsafe { CStr::from_ptr(pointer as *const _) };
+            if let Ok(s) = s.to_str() {
+                return Some(s);
+            }
+        }
+    }
+}
+
+// the arguments, the program name first
+pub fn args() -> Strings {
+    Strings {
+        next: ARGV.load(Ordering::Relaxed) as *const u32,
+    }
+}
+
+pub fn argc() -> usize {
+    ARGC.load(Ordering::Relaxed)
+}
+
+// the environment as KEY=value strings
+pub fn vars() -> Strings {
+    Strings {
+        next: ENVP.load(Ordering::Relaxed) as *const u32,
+    }
+}
+
+pub fn var(key: &str) -> Option<&'static str> {
+    vars().find_map(|var| {
+        var.strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix('='))
+    })
+}
+
+// a value from the auxiliary vector
+pub fn aux(key: u32) -> Option<u32> {
+    let mut entry = AUXV.load(Ordering::Relaxed) as *const u32;
+    if entry.is_null() {
+        return None;
+    }
+    loop {
+        let (k, value) = unsafe { (*entry, *entry.add(1)) };
+        match k {
+            0 => return None,
+            k if k == key => return Some(value),
+            _ => entry = unsafe { entry.add(2) },
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::syscall;
+
+// hands out memory upwards from the initial break, moving the break as it
+// goes. Only the last block given out is ever taken back or grown in place,
+// programs that allocate and free in a loop should reuse their buffers
+pub struct Brk {
+    // the initial break, the next free byte and the break, all 0 until the
+    // first allocation
+    start: AtomicUsize,
+    next: AtomicUsize,
+    end: AtomicUsize,
+}
+
+impl Brk {
+    pub const fn new() -> Self {
+        Self {
+            start: AtomicUsize::new(0),
+            next: AtomicUsize::new(0),
+            end: AtomicUsize::new(0),
+        }
+    }
+
+    // bytes between the initial break and the next free one
+    pub fn used(&self) -> usize {
+        self.next.load(Ordering::Relaxed) - self.start.load(Ordering::Relaxed)
+    }
+
+    // makes sure the memory up to `end` is there
+    fn reserve(&self, end: usize) -> bool {
+        if end <= self.end.load(Ordering::Relaxed) {
+            return true;
+        }
+        let brk = syscall::brk(end);
+        self.end.store(brk, Ordering::Relaxed);
+        brk >= end
+    }
+}
+
+impl Default for Brk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// processes have a single thread, the atomics only keep the statics safe
+unsafe impl GlobalAlloc for Brk {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if self.next.load(Ordering::Relaxed) == 0 {
+            let brk = syscall::brk(0);
+            self.start.store(brk, Ordering::Relaxed);
+            self.next.store(brk, Ordering::Relaxed);
+            self.end.store(brk, Ordering::Relaxed);
+        }
+        let next = self.next.load(Ordering::Relaxed);
+        let Some(start) = next.checked_next_multiple_of(layout.align()) else {
+            return ptr::null_mut();
+        };
+        match start.checked_add(layout.size()) {
+            Some(end) if self.reserve(end) => {
+                self.next.store(end, Ordering::Relaxed);
+                start as *mut u8
+            }
+            _ => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let start = ptr as usize;
+        if self.next.load(Ordering::Relaxed) == start + layout.size() {
+            self.next.store(start, Ordering::Relaxed);
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let start = ptr as usize;
+        if self.next.load(Ordering::Relaxed) == start + layout.size() {
+            match start.checked_add(new_size) {
+                Some(end) if self.reserve(end) => {
+                    self.next.store(end, Ordering::Relaxed);
+                    return ptr;
+                }
+                _ => return ptr::null_mut(),
+            }
+        }
+        let new = self.alloc(Layout::from_size_align_unchecked(new_size, layout.align()));
+        if !new.is_null() {
+            ptr::copy_nonoverlapping(ptr, new, layout.size().min(new_size));
+        }
+        new
+    }
+}
@@ -0,0 +1,103 @@
+use core::fmt::{self, Write};
+
+use crate::syscall::{self, Error, Result};
+
+pub const STDIN: usize = 0;
+pub const STDOUT: usize = 1;
+pub const STDERR: usize = 2;
+
+// writes all of `buf`, a pipe or a console may take it in pieces
+pub fn write_all(fd: usize, mut buf: &[u8]) -> Result<()> {
+    while !buf.is_empty() {
+        match syscall::write(fd, buf)? {
+            0 => return Err(Error::Io),
+            len => buf = &buf[len..],
+        }
+    }
+    Ok(())
+}
+
+// reads a line into `buf` without its '\n', the console sends them a byte at
+// a time. Returns None at the end of the input
+pub fn read_line(fd: usize, buf: &mut [u8]) -> Result<Option<usize>> {
+    let mut len = 0;
+    while len < buf.len() {
+        let mut byte = 0;
+        if syscall::read(fd, core::slice::from_mut(&mut byte))? == 0 {
+            return Ok((len > 0).then_some(len));
+        }
+        match byte {
+            b'\n' | b'\r' => break,
+            _ => {
+                buf[len] = byte;
+                len += 1;
+            }
+        }
+    }
+    Ok(Some(len))
+}
+
+// gathers what print! formats so a line goes out in a single write, and
+// lines of processes sharing a console don't get mixed
+pub struct Writer {
+    fd: usize,
+    buf: [u8; 256],
+    len: usize,
+}
+
+impl Writer {
+    pub const fn new(fd: usize) -> Self {
+        Self {
+            fd,
+            buf: [0; 256],
+            len: 0,
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        let len = core::mem::take(&mut self.len);
+        write_all(self.fd, &self.buf[..len])
+    }
+}
+
+impl Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for chunk in s.as_bytes().chunks(self.buf.len()) {
+            if self.len + chunk.len() > self.buf.len() {
+                self.flush().map_err(|_| fmt::Error)?;
+            }
+            self.buf[self.len..self.len + chunk.len()].copy_from_slice(chunk);
+            self.len += chunk.len();
+        }
+        Ok(())
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(fd: usize, args: fmt::Arguments) {
+    let mut writer = Writer::new(fd);
+    let _ = writer.write_fmt(args);
+    let _ = writer.flush();
+}
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::io::_print($crate::io::STDOUT, format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => ($crate::io::_print($crate::io::STDERR, format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    () => ($crate::eprint!("\n"));
+    ($($arg:tt)*) => ($crate::eprint!("{}\n", format_args!($($arg)*)));
+}
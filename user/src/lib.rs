@@ -0,0 +1,21 @@
+#![no_std]
+
+// runtime of CairnOS programs: the entry point, the system calls, a heap on
+// the break and printing to the standard descriptors. A program is a
+// #![no_std] #![no_main] binary defining `#[no_mangle] fn main() -> i32`,
+// what it returns is its exit code
+pub extern crate alloc;
+
+mod builtins;
+pub mod env;
+pub mod heap;
+pub mod io;
+pub mod process;
+mod start;
+pub mod syscall;
+
+core::arch::global_asm!(include_str!("start.s"));
+core::arch::global_asm!(include_str!("syscall.s"));
+
+#[global_allocator]
+pub static HEAP: heap::Brk = heap::Brk::new();
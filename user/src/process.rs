@@ -0,0 +1,49 @@
+use core::ffi::CStr;
+
+use crate::syscall::{self, Error, Result};
+
+pub use crate::syscall::{exit, getpid, getppid};
+
+// how a child ended, from the status waitpid stores
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Exited(u8),
+    Killed { signal: u8, core: bool },
+}
+
+impl Status {
+    pub fn from_raw(status: u32) -> Self {
+        match status & 0x7F {
+            0 => Status::Exited((status >> 8) as u8),
+            signal => Status::Killed {
+                signal: signal as u8,
+                core: status & 0x80 != 0,
+            },
+        }
+    }
+
+    pub fn success(self) -> bool {
+        self == Status::Exited(0)
+    }
+}
+
+// runs `path` in a child process and returns its pid. The child exits with
+// 127 when the program can't be loaded, as shells do
+pub fn spawn(path: &CStr, argv: &[&CStr], envp: &[&CStr]) -> Result<usize> {
+    match syscall::fork()? {
+        0 => {
+            let err = syscall::exec(path, argv, envp);
+            crate::eprintln!("Could not run {}: {err:?}", path.to_str().unwrap_or("?"));
+            exit(127)
+        }
+        pid => Ok(pid),
+    }
+}
+
+// blocks until the child `pid`, or any child if None, exits
+pub fn wait(pid: Option<usize>) -> Result<(usize, Status)> {
+    match syscall::waitpid(pid, 0)? {
+        Some((pid, status)) => Ok((pid, Status::from_raw(status))),
+        None => Err(Error::NoChild),
+    }
+}
@@ -0,0 +1,25 @@
+use core::panic::PanicInfo;
+
+use crate::{env, process};
+
+extern "Rust" {
+    // the `#[no_mangle] fn main() -> i32` of the program
+    fn main() -> i32;
+}
+
+#[no_mangle]
+unsafe extern "C" fn cairn_start(stack: *const u32) -> ! {
+    env::init(stack);
+    process::exit(main() as u8)
+}
+
+// 101 is what Rust programs exit with after a panic elsewhere too
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let name = env::args().next().unwrap_or("?");
+    match info.location() {
+        Some(location) => crate::eprintln!("{name} panicked at {location}:\n{}", info.message()),
+        None => crate::eprintln!("{name} panicked:\n{}", info.message()),
+    }
+    process::exit(101)
+}
@@ -0,0 +1,13 @@
+# the kernel leaves argc, argv, envp and auxv at esp. The stack is aligned
+# the way the System V ABI wants it at a call before cairn_start(stack)
+.section .text
+.global _start
+.type _start, @function
+_start:
+    xor ebp, ebp
+    mov eax, esp
+    and esp, -16
+    sub esp, 12
+    push eax
+    call cairn_start
+    ud2
@@ -0,0 +1,354 @@
+use core::ffi::CStr;
+
+// numbers, flags and errors as the kernel's syscall module has them
+pub const EXIT: u32 = 0;
+pub const READ: u32 = 1;
+pub const WRITE: u32 = 2;
+pub const GETPID: u32 = 3;
+pub const SLEEP: u32 = 4;
+pub const BRK: u32 = 5;
+pub const MMAP: u32 = 6;
+pub const FORK: u32 = 7;
+pub const EXEC: u32 = 8;
+pub const WAITPID: u32 = 9;
+pub const GETPPID: u32 = 10;
+pub const OPEN: u32 = 11;
+pub const CLOSE: u32 = 12;
+pub const MUNMAP: u32 = 13;
+pub const MPROTECT: u32 = 14;
+pub const PIPE: u32 = 15;
+pub const DUP2: u32 = 16;
+pub const SIGRETURN: u32 = 17;
+pub const SIGNAL: u32 = 18;
+pub const SIGPROCMASK: u32 = 19;
+pub const KILL: u32 = 20;
+pub const PORT_CREATE: u32 = 21;
+pub const PORT_OPEN: u32 = 22;
+pub const SEND: u32 = 23;
+pub const RECEIVE: u32 = 24;
+pub const CALL: u32 = 25;
+pub const REPLY: u32 = 26;
+pub const SHM_MAP: u32 = 27;
+pub const SHM_UNLINK: u32 = 28;
+
+pub const WNOHANG: u32 = 1;
+
+pub const PROT_NONE: u32 = 0;
+pub const PROT_READ: u32 = 1 << 0;
+pub const PROT_WRITE: u32 = 1 << 1;
+pub const PROT_EXEC: u32 = 1 << 2;
+
+pub const MAP_SHARED: u32 = 0x01;
+pub const MAP_PRIVATE: u32 = 0x02;
+pub const MAP_FIXED: u32 = 0x10;
+pub const MAP_ANONYMOUS: u32 = 0x20;
+
+pub const SIG_DFL: u32 = 0;
+pub const SIG_IGN: u32 = 1;
+
+pub const SIG_BLOCK: u32 = 0;
+pub const SIG_UNBLOCK: u32 = 1;
+pub const SIG_SETMASK: u32 = 2;
+
+// most exec takes, not counting the NULL at the end
+pub const MAX_ARGS: usize = 32;
+pub const MAX_INLINE: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Error {
+    NoSys = 1,
+    Fault,
+    BadFd,
+    Invalid,
+    NoMemory,
+    NotFound,
+    NotDir,
+    IsDir,
+    Exists,
+    NotEmpty,
+    ReadOnly,
+    NoSpace,
+    Unsupported,
+    Busy,
+    Loop,
+    Io,
+    TooManyFiles,
+    NoChild,
+    Again,
+    NoExec,
+    TooBig,
+    BrokenPipe,
+    Interrupted,
+    Closed,
+}
+
+impl Error {
+    const ALL: [Error; 24] = [
+        Error::NoSys,
+        Error::Fault,
+        Error::BadFd,
+        Error::Invalid,
+        Error::NoMemory,
+        Error::NotFound,
+        Error::NotDir,
+        Error::IsDir,
+        Error::Exists,
+        Error::NotEmpty,
+        Error::ReadOnly,
+        Error::NoSpace,
+        Error::Unsupported,
+        Error::Busy,
+        Error::Loop,
+        Error::Io,
+        Error::TooManyFiles,
+        Error::NoChild,
+        Error::Again,
+        Error::NoExec,
+        Error::TooBig,
+        Error::BrokenPipe,
+        Error::Interrupted,
+        Error::Closed,
+    ];
+
+    // a value the kernel doesn't know yet reads as NoSys
+    fn from_code(code: u32) -> Self {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|err| *err as u32 == code)
+            .unwrap_or(Error::NoSys)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+// the IPC message of the kernel, see task::ipc::Message there
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Message {
+    pub tag: u32,
+    pub len: u32,
+    pub page: u32,
+    pub sender: u32,
+    pub reply: u32,
+    pub data: [u8; MAX_INLINE],
+}
+
+impl Message {
+    pub const fn empty() -> Self {
+        Self {
+            tag: 0,
+            len: 0,
+            page: 0,
+            sender: 0,
+            reply: 0,
+            data: [0; MAX_INLINE],
+        }
+    }
+
+    // a message carrying `tag` and as much of `data` as fits inline
+    pub fn new(tag: u32, data: &[u8]) -> Self {
+        let mut message = Self::empty();
+        let len = data.len().min(MAX_INLINE);
+        message.tag = tag;
+        message.len = len as u32;
+        message.data[..len].copy_from_slice(&data[..len]);
+        message
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data[..(self.len as usize).min(MAX_INLINE)]
+    }
+}
+
+extern "C" {
+    fn cairn_syscall(number: u32, a: u32, b: u32, c: u32, d: u32, e: u32) -> u32;
+}
+
+// errors come back as negated `Error` values, user addresses stay below
+// 2 GiB so nothing else gets that high
+fn syscall(number: u32, args: [u32; 5]) -> Result<usize> {
+    let ret = unsafe { cairn_syscall(number, args[0], args[1], args[2], args[3], args[4]) };
+    let code = ret.wrapping_neg();
+    if (1..4096).contains(&code) {
+        Err(Error::from_code(code))
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+pub fn exit(code: u8) -> ! {
+    let _ = syscall(EXIT, [code as u32, 0, 0, 0, 0]);
+    unreachable!()
+}
+
+pub fn read(fd: usize, buf: &mut [u8]) -> Result<usize> {
+    syscall(
+        READ,
+        [fd as u32, buf.as_mut_ptr() as u32, buf.len() as u32, 0, 0],
+    )
+}
+
+pub fn write(fd: usize, buf: &[u8]) -> Result<usize> {
+    syscall(
+        WRITE,
+        [fd as u32, buf.as_ptr() as u32, buf.len() as u32, 0, 0],
+    )
+}
+
+pub fn getpid() -> usize {
+    syscall(GETPID, [0; 5]).unwrap_or(0)
+}
+
+pub fn getppid() -> usize {
+    syscall(GETPPID, [0; 5]).unwrap_or(0)
+}
+
+pub fn sleep(ms: u32) {
+    let _ = syscall(SLEEP, [ms, 0, 0, 0, 0]);
+}
+
+// moves the break to `addr` and returns where it is now, 0 only asks
+pub fn brk(addr: usize) -> usize {
+    syscall(BRK, [addr as u32, 0, 0, 0, 0]).unwrap_or(0)
+}
+
+// `fd` is ignored with MAP_ANONYMOUS, `addr` 0 lets the kernel choose
+pub fn mmap(
+    addr: usize,
+    len: usize,
+    prot: u32,
+    flags: u32,
+    fd: usize,
+    offset: u32,
+) -> Result<usize> {
+    let args = [addr as u32, len as u32, prot, flags, fd as u32, offset];
+    syscall(MMAP, [args.as_ptr() as u32, 0, 0, 0, 0])
+}
+
+pub fn munmap(addr: usize, len: usize) -> Result<()> {
+    syscall(MUNMAP, [addr as u32, len as u32, 0, 0, 0]).map(drop)
+}
+
+pub fn mprotect(addr: usize, len: usize, prot: u32) -> Result<()> {
+    syscall(MPROTECT, [addr as u32, len as u32, prot, 0, 0]).map(drop)
+}
+
+// the pid of the child in the parent, 0 in the child
+pub fn fork() -> Result<usize> {
+    syscall(FORK, [0; 5])
+}
+
+// only returns when the program couldn't be loaded
+pub fn exec(path: &CStr, argv: &[&CStr], envp: &[&CStr]) -> Error {
+    if argv.len() > MAX_ARGS || envp.len() > MAX_ARGS {
+        return Error::TooBig;
+    }
+    let mut pointers = [[0u32; MAX_ARGS + 1]; 2];
+    for (list, strs) in pointers.iter_mut().zip([argv, envp]) {
+        for (pointer, s) in list.iter_mut().zip(strs) {
+            *pointer = s.as_ptr() as u32;
+        }
+    }
+    let args = [
+        path.as_ptr() as u32,
+        pointers[0].as_ptr() as u32,
+        pointers[1].as_ptr() as u32,
+        0,
+        0,
+    ];
+    match syscall(EXEC, args) {
+        Ok(_) => Error::NoExec,
+        Err(err) => err,
+    }
+}
+
+// pid or None for any child, returns the pid and wait status of the child
+// that exited, None with WNOHANG when none has yet
+pub fn waitpid(pid: Option<usize>, options: u32) -> Result<Option<(usize, u32)>> {
+    let mut status = 0u32;
+    let pid = pid.map_or(-1, |pid| pid as i32) as u32;
+    let addr = &mut status as *mut u32 as u32;
+    match syscall(WAITPID, [pid, addr, options, 0, 0])? {
+        0 => Ok(None),
+        id => Ok(Some((id, status))),
+    }
+}
+
+pub fn open(path: &CStr) -> Result<usize> {
+    syscall(OPEN, [path.as_ptr() as u32, 0, 0, 0, 0])
+}
+
+pub fn close(fd: usize) -> Result<()> {
+    syscall(CLOSE, [fd as u32, 0, 0, 0, 0]).map(drop)
+}
+
+// the read end, then the write end
+pub fn pipe() -> Result<(usize, usize)> {
+    let mut fds = [0u32; 2];
+    syscall(PIPE, [fds.as_mut_ptr() as u32, 0, 0, 0, 0])?;
+    Ok((fds[0] as usize, fds[1] as usize))
+}
+
+pub fn dup2(old: usize, new: usize) -> Result<usize> {
+    syscall(DUP2, [old as u32, new as u32, 0, 0, 0])
+}
+
+// `handler` is SIG_DFL, SIG_IGN or the address of an `extern "C" fn(i32)`,
+// returns the previous one
+pub fn signal(signal: u8, handler: u32) -> Result<u32> {
+    syscall(SIGNAL, [signal as u32, handler, 0, 0, 0]).map(|old| old as u32)
+}
+
+pub fn sigprocmask(how: u32, set: u32) -> Result<u32> {
+    syscall(SIGPROCMASK, [how, set, 0, 0, 0]).map(|old| old as u32)
+}
+
+pub fn kill(pid: usize, signal: u8) -> Result<()> {
+    syscall(KILL, [pid as u32, signal as u32, 0, 0, 0]).map(drop)
+}
+
+// a descriptor on a new port, reachable by `name` if any
+pub fn port_create(name: Option<&CStr>) -> Result<usize> {
+    let name = name.map_or(0, |name| name.as_ptr() as u32);
+    syscall(PORT_CREATE, [name, 0, 0, 0, 0])
+}
+
+pub fn port_open(name: &CStr) -> Result<usize> {
+    syscall(PORT_OPEN, [name.as_ptr() as u32, 0, 0, 0, 0])
+}
+
+pub fn send(fd: usize, message: &Message) -> Result<()> {
+    let message = message as *const Message as u32;
+    syscall(SEND, [fd as u32, message, 0, 0, 0]).map(drop)
+}
+
+pub fn receive(fd: usize) -> Result<Message> {
+    let mut message = Message::empty();
+    let addr = &mut message as *mut Message as u32;
+    syscall(RECEIVE, [fd as u32, addr, 0, 0, 0])?;
+    Ok(message)
+}
+
+// sends `message` and waits for the reply that replaces it
+pub fn call(fd: usize, message: &mut Message) -> Result<()> {
+    let addr = message as *mut Message as u32;
+    syscall(CALL, [fd as u32, addr, 0, 0, 0]).map(drop)
+}
+
+// `token` is the `reply` field of the received call
+pub fn reply(token: u32, message: &Message) -> Result<()> {
+    let message = message as *const Message as u32;
+    syscall(REPLY, [token, message, 0, 0, 0]).map(drop)
+}
+
+// maps the segment `name`, made with `len` bytes if there is none, 0 only
+// takes an existing one. Returns the address, munmap unmaps it
+pub fn shm_map(name: &CStr, len: usize, prot: u32) -> Result<usize> {
+    syscall(SHM_MAP, [name.as_ptr() as u32, len as u32, prot, 0, 0])
+}
+
+pub fn shm_unlink(name: &CStr) -> Result<()> {
+    syscall(SHM_UNLINK, [name.as_ptr() as u32, 0, 0, 0, 0]).map(drop)
+}
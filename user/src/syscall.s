@@ -0,0 +1,20 @@
+# cairn_syscall(number, a, b, c, d, e) moves its cdecl arguments to eax, ebx,
+# ecx, edx, esi and edi for int 0x80, saving the callee-saved ones it uses
+.section .text
+.global cairn_syscall
+.type cairn_syscall, @function
+cairn_syscall:
+    push ebx
+    push esi
+    push edi
+    mov eax, [esp + 16]
+    mov ebx, [esp + 20]
+    mov ecx, [esp + 24]
+    mov edx, [esp + 28]
+    mov esi, [esp + 32]
+    mov edi, [esp + 36]
+    int 0x80
+    pop edi
+    pop esi
+    pop ebx
+    ret